@@ -0,0 +1,469 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use serde::{Deserialize, Serialize};
+
+// Debian control files (Packages, status, Sources, Release) are sequences of paragraphs ("stanzas")
+// separated by blank lines; see https://www.debian.org/doc/debian-policy/ch-controlfields.html
+// and deb822(5). A field starts with "Name:" at the beginning of a line; lines starting with a
+// space or tab continue the previous field (folded fields like Depends, multiline fields like
+// Description or Conffiles).
+
+#[derive(Clone,Serialize,Deserialize)]
+pub struct Field {
+    pub name : String,
+    // first line, then one "\n"-prefixed entry per continuation line, leading whitespace kept
+    pub value : String,
+    pub line : usize
+}
+
+#[derive(Clone,Serialize,Deserialize)]
+pub struct Paragraph {
+    fields : Vec<Field>,
+    line : usize
+}
+
+impl Paragraph {
+    pub fn new(line: usize) -> Paragraph {
+        Paragraph { fields : vec![], line }
+    }
+
+    /// Line number (1-based) of the first field of this paragraph in its file.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    pub fn push(&mut self, name: &str, value: &str, line: usize) {
+        self.fields.push(Field { name : name.to_string(), value : value.to_string(), line });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Field names are case-insensitive.
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Raw value of a field, including any continuation lines.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.field(name).map(|f| &f.value[..])
+    }
+
+    /// Value of a folded field (e.g. Depends) with its continuation lines joined by single spaces.
+    pub fn get_folded(&self, name: &str) -> Option<String> {
+        self.get(name).map(fold)
+    }
+
+    /// Lines of a multiline field (e.g. Conffiles, SHA256); the first element is whatever follows
+    /// the colon (often empty), and continuation lines have their leading whitespace removed.
+    pub fn get_lines(&self, name: &str) -> Option<Vec<&str>> {
+        self.get(name).map(|v| {
+            v.split('\n').enumerate().map(|(i, l)| if i == 0 { l } else { l.trim_start() }).collect()
+        })
+    }
+}
+
+// a folded value with its continuation lines joined by single spaces
+fn fold(value: &str) -> String {
+    value.split('\n').map(|l| l.trim()).filter(|l| !l.is_empty()).collect::<Vec<&str>>().join(" ")
+}
+
+impl fmt::Display for Paragraph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for field in &self.fields {
+            let sep = if field.value.is_empty() || field.value.starts_with('\n') { "" } else { " " };
+            writeln!(f, "{}:{}{}", field.name, sep, field.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterates over the paragraphs of a deb822 file. A malformed line (including one that is not UTF-8) yields an
+/// InvalidData error carrying its line number; iteration then resumes at the next paragraph. An error reading the
+/// input, e.g. from a truncated compressed file, is returned once and ends the iteration.
+pub struct ParagraphReader<R: BufRead> {
+    reader : R,
+    line_no : usize,
+    failed : bool
+}
+
+impl<R: BufRead> ParagraphReader<R> {
+    pub fn new(reader: R) -> ParagraphReader<R> {
+        ParagraphReader::with_line_offset(reader, 0)
+    }
+
+    /// Like new, for input that starts after the first lines_before lines of a file, so that line numbers are those
+    /// of the file.
+    pub fn with_line_offset(reader: R, lines_before: usize) -> ParagraphReader<R> {
+        ParagraphReader { reader, line_no : lines_before, failed : false }
+    }
+
+    // the next line without its line ending, or None at the end of the input
+    fn next_line(&mut self) -> Option<io::Result<String>> {
+        if self.failed { return None }
+        let mut buf = vec![];
+        match self.reader.read_until(b'\n', &mut buf) {
+            Ok(0) => None,
+            Err(e) => { self.failed = true; Some(Err(e)) }
+            Ok(_) => {
+                self.line_no += 1;
+                if buf.ends_with(b"\n") { buf.pop(); }
+                Some(String::from_utf8(buf).map_err(|_| malformed(self.line_no, "not valid UTF-8")))
+            }
+        }
+    }
+
+    fn skip_to_blank_line(&mut self) -> io::Result<()> {
+        while let Some(line) = self.next_line() {
+            match line {
+                Ok(line) if line.trim().is_empty() => break,
+                Err(e) if self.failed => return Err(e),
+                _ => ()
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for ParagraphReader<R> {
+    type Item = io::Result<Paragraph>;
+
+    fn next(&mut self) -> Option<io::Result<Paragraph>> {
+        let mut para = Paragraph::new(0);
+        loop {
+            let line = match self.next_line() {
+                None => break,
+                Some(Err(e)) => {
+                    if !self.failed {
+                        if let Err(e) = self.skip_to_blank_line() { return Some(Err(e)) }
+                    }
+                    return Some(Err(e));
+                }
+                Some(Ok(l)) => l
+            };
+            let line = line.trim_end_matches('\r');
+
+            if line.trim().is_empty() {
+                if para.is_empty() { continue } else { break }
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with(' ') || line.starts_with('\t') {
+                match para.fields.last_mut() {
+                    Some(field) => { field.value.push('\n'); field.value.push_str(line); }
+                    None => {
+                        let err = malformed(self.line_no, "continuation line outside of a field");
+                        if let Err(e) = self.skip_to_blank_line() { return Some(Err(e)) }
+                        return Some(Err(err));
+                    }
+                }
+                continue;
+            }
+            match line.find(':') {
+                Some(colon) if colon > 0 => {
+                    if para.is_empty() { para.line = self.line_no; }
+                    let (name, rest) = line.split_at(colon);
+                    para.push(name, rest[1..].trim(), self.line_no);
+                }
+                _ => {
+                    let err = malformed(self.line_no, &format!("expected \"Field: value\", got {:?}", line));
+                    if let Err(e) = self.skip_to_blank_line() { return Some(Err(e)) }
+                    return Some(Err(err));
+                }
+            }
+        }
+        if para.is_empty() { None } else { Some(Ok(para)) }
+    }
+}
+
+/// A field of a ParagraphRef, borrowed from the input.
+pub struct FieldRef<'a> {
+    pub name : &'a str,
+    // what follows the colon on the field's own line, trimmed
+    pub first : &'a str,
+    // the continuation lines (with any comment lines among them) as they are in the input
+    pub more : Option<&'a str>,
+    pub line : usize
+}
+
+impl<'a> FieldRef<'a> {
+    /// The value as Paragraph::get gives it; borrowed from the input unless the field has continuation lines.
+    pub fn value(&self) -> Cow<'a, str> {
+        match self.more {
+            None => Cow::Borrowed(self.first),
+            Some(more) => {
+                let mut value = self.first.to_string();
+                for line in more.split('\n').map(|l| l.trim_end_matches('\r')).filter(|l| !l.starts_with('#')) {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+                Cow::Owned(value)
+            }
+        }
+    }
+}
+
+/// A paragraph borrowed from its input, from ParagraphScanner; the same fields, values and line numbers as the Paragraph
+/// ParagraphReader would give.
+pub struct ParagraphRef<'a> {
+    text : &'a str,
+    fields : Vec<FieldRef<'a>>,
+    line : usize
+}
+
+impl<'a> ParagraphRef<'a> {
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The paragraph as it is in the input, from its first field to the end of its last line.
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    pub fn fields(&self) -> &[FieldRef<'a>] {
+        &self.fields
+    }
+
+    /// Field names are case-insensitive.
+    pub fn field(&self, name: &str) -> Option<&FieldRef<'a>> {
+        self.fields.iter().find(|f| f.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Cow<'a, str>> {
+        self.field(name).map(|f| f.value())
+    }
+
+    /// Like Paragraph::get_folded; borrowed from the input unless the field has continuation lines.
+    pub fn get_folded(&self, name: &str) -> Option<Cow<'a, str>> {
+        self.field(name).map(|f| match f.more {
+            None => Cow::Borrowed(f.first),
+            Some(_) => Cow::Owned(fold(&f.value()))
+        })
+    }
+
+    pub fn to_paragraph(&self) -> Paragraph {
+        let fields = self.fields.iter()
+            .map(|f| Field { name : f.name.to_string(), value : f.value().into_owned(), line : f.line }).collect();
+        Paragraph { fields, line : self.line }
+    }
+}
+
+/// Iterates over the paragraphs of a deb822 file held in memory (e.g. memory-mapped) without copying anything: the
+/// paragraphs borrow from the input. Malformed lines give the same errors, and resume at the same place, as with
+/// ParagraphReader.
+pub struct ParagraphScanner<'a> {
+    input : &'a [u8],
+    pos : usize,
+    line_no : usize
+}
+
+impl<'a> ParagraphScanner<'a> {
+    pub fn new(input: &'a [u8]) -> ParagraphScanner<'a> {
+        ParagraphScanner { input, pos : 0, line_no : 0 }
+    }
+
+    // the offset of the next line and the line without its line ending, or None at the end of the input
+    fn next_line(&mut self) -> Option<(usize, io::Result<&'a str>)> {
+        if self.pos >= self.input.len() { return None }
+        let start = self.pos;
+        let end = self.input[start..].iter().position(|&c| c == b'\n').map_or(self.input.len(), |i| start + i);
+        self.pos = end + 1;
+        self.line_no += 1;
+        Some((start, std::str::from_utf8(&self.input[start..end]).map_err(|_| malformed(self.line_no, "not valid UTF-8"))))
+    }
+
+    fn skip_to_blank_line(&mut self) {
+        while let Some((_, line)) = self.next_line() {
+            if line.is_ok_and(|l| l.trim().is_empty()) { break }
+        }
+    }
+
+    // input[start..end], which is made of lines that were checked to be UTF-8
+    fn text(&self, start: usize, end: usize) -> &'a str {
+        std::str::from_utf8(&self.input[start..end]).expect("lines are checked to be UTF-8")
+    }
+}
+
+// name, first line of the value, line number and the range of the continuation lines of a field being scanned
+type ScannedField<'a> = (&'a str, &'a str, usize, Option<(usize, usize)>);
+
+impl<'a> Iterator for ParagraphScanner<'a> {
+    type Item = io::Result<ParagraphRef<'a>>;
+
+    fn next(&mut self) -> Option<io::Result<ParagraphRef<'a>>> {
+        let mut fields : Vec<ScannedField<'a>> = vec![];
+        let (mut start, mut end, mut first_line) = (0, 0, 0);
+        while let Some((offset, line)) = self.next_line() {
+            let line = match line {
+                Ok(l) => l.trim_end_matches('\r'),
+                Err(e) => { self.skip_to_blank_line(); return Some(Err(e)) }
+            };
+            if line.trim().is_empty() {
+                if fields.is_empty() { continue } else { break }
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with(' ') || line.starts_with('\t') {
+                match fields.last_mut() {
+                    Some((_, _, _, more)) => *more = Some((more.map_or(offset, |(s, _)| s), offset + line.len())),
+                    None => {
+                        let err = malformed(self.line_no, "continuation line outside of a field");
+                        self.skip_to_blank_line();
+                        return Some(Err(err));
+                    }
+                }
+                end = offset + line.len();
+                continue;
+            }
+            match line.find(':') {
+                Some(colon) if colon > 0 => {
+                    if fields.is_empty() { start = offset; first_line = self.line_no; }
+                    let (name, rest) = line.split_at(colon);
+                    fields.push((name, rest[1..].trim(), self.line_no, None));
+                    end = offset + line.len();
+                }
+                _ => {
+                    let err = malformed(self.line_no, &format!("expected \"Field: value\", got {:?}", line));
+                    self.skip_to_blank_line();
+                    return Some(Err(err));
+                }
+            }
+        }
+        if fields.is_empty() { return None }
+        let fields = fields.into_iter().map(|(name, first, line, more)| {
+            FieldRef { name, first, more : more.map(|(s, e)| self.text(s, e)), line }
+        }).collect();
+        Some(Ok(ParagraphRef { text : self.text(start, end), fields, line : first_line }))
+    }
+}
+
+/// A malformed line, carried by the InvalidData errors of ParagraphReader and ParagraphScanner so that readers can
+/// tell it from an error reading the input.
+#[derive(Debug)]
+pub struct MalformedLine {
+    pub line : usize,
+    pub message : String
+}
+
+impl MalformedLine {
+    /// The malformed line behind e, if that is what e is about.
+    pub fn of(e: &io::Error) -> Option<&MalformedLine> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<MalformedLine>())
+    }
+}
+
+impl fmt::Display for MalformedLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for MalformedLine {}
+
+fn malformed(line: usize, msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, MalformedLine { line, message : msg.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT : &str = "# a comment before the first paragraph\n\
+                          Package: a\nDepends: b,\n c\n# a comment inside a field\n  d\nDescription: short\n .\n long\n\
+                          \n\n\
+                          Package: b\r\nVersion: 1\r\n\r\n\
+                          Package: c\n";
+
+    // a paragraph as its line and its fields (name, value, line), or the line of its error
+    type Read = Result<(usize, Vec<(String, String, usize)>), usize>;
+
+    fn read(input: &str) -> Vec<Read> {
+        ParagraphReader::new(input.as_bytes()).map(|p| p.map(|p| (p.line(), p.fields().iter()
+            .map(|f| (f.name.clone(), f.value.clone(), f.line)).collect()))
+            .map_err(|e| MalformedLine::of(&e).unwrap().line)).collect()
+    }
+
+    fn scan(input: &str) -> Vec<Read> {
+        ParagraphScanner::new(input.as_bytes()).map(|p| p.map(|p| (p.line(), p.fields().iter()
+            .map(|f| (f.name.to_string(), f.value().into_owned(), f.line)).collect()))
+            .map_err(|e| MalformedLine::of(&e).unwrap().line)).collect()
+    }
+
+    fn field(name: &str, value: &str, line: usize) -> (String, String, usize) {
+        (name.to_string(), value.to_string(), line)
+    }
+
+    #[test]
+    fn continuation_lines_comments_and_crlf() {
+        let paragraphs = read(INPUT);
+        assert_eq!(paragraphs, [
+            Ok((2, vec![field("Package", "a", 2), field("Depends", "b,\n c\n  d", 3), field("Description", "short\n .\n long", 7)])),
+            Ok((12, vec![field("Package", "b", 12), field("Version", "1", 13)])),
+            Ok((15, vec![field("Package", "c", 15)]))
+        ]);
+        assert_eq!(scan(INPUT), paragraphs);
+    }
+
+    #[test]
+    fn folded_and_multiline_values() {
+        let paragraph = ParagraphReader::new(INPUT.as_bytes()).next().unwrap().unwrap();
+        assert_eq!(paragraph.get_folded("depends").unwrap(), "b, c d");
+        assert_eq!(paragraph.get_lines("Description").unwrap(), ["short", ".", "long"]);
+        assert!(paragraph.get("Version").is_none());
+        let scanned = ParagraphScanner::new(INPUT.as_bytes()).next().unwrap().unwrap();
+        assert_eq!(scanned.get_folded("Depends").unwrap(), "b, c d");
+        assert!(matches!(scanned.get_folded("Package").unwrap(), Cow::Borrowed("a")));
+        assert_eq!(scanned.text(), "Package: a\nDepends: b,\n c\n# a comment inside a field\n  d\nDescription: short\n .\n long");
+        assert_eq!(paragraph.to_string(), scanned.to_paragraph().to_string());
+    }
+
+    // a malformed line fails its paragraph, and reading resumes at the next one
+    #[test]
+    fn malformed_lines() {
+        let input = "Package: a\nno colon here\nVersion: 1\n\n \tcontinuation first\n\nPackage: b\n\n: no name\n\nPackage: c\n";
+        let expected = [Err(2), Err(5), Ok((7, vec![field("Package", "b", 7)])), Err(9)];
+        assert_eq!(read(input)[..4], expected);
+        assert_eq!(scan(input)[..4], expected);
+        let bytes = b"Package: c\nVersion: \xff\n\nPackage: d\n";
+        for paragraphs in [ParagraphReader::new(&bytes[..]).map(|p| p.map(|p| p.line())).collect::<Vec<_>>(),
+                           ParagraphScanner::new(&bytes[..]).map(|p| p.map(|p| p.line())).collect()] {
+            assert_eq!(paragraphs.len(), 2);
+            let e = paragraphs[0].as_ref().unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(MalformedLine::of(e).unwrap().to_string(), "line 2: not valid UTF-8");
+            assert_eq!(*paragraphs[1].as_ref().unwrap(), 4);
+        }
+    }
+
+    #[test]
+    fn line_offset() {
+        let paragraphs : Vec<usize> = ParagraphReader::with_line_offset("Package: a\n\nPackage: b\n".as_bytes(), 10)
+            .map(|p| p.unwrap().line()).collect();
+        assert_eq!(paragraphs, [11, 13]);
+    }
+
+    // an error reading the input ends the iteration, where a malformed line does not
+    #[test]
+    fn read_errors_end_the_iteration() {
+        struct Failing;
+        impl io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("truncated"))
+            }
+        }
+        let mut reader = ParagraphReader::new(io::BufReader::new(Failing));
+        let e = reader.next().unwrap().err().unwrap();
+        assert!(MalformedLine::of(&e).is_none());
+        assert!(reader.next().is_none());
+    }
+}
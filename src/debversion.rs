@@ -1,8 +1,11 @@
+use std::error::Error;
 use std::fmt;
-use std::string::ParseError;
 use std::cmp::Ordering::{Less, Equal, Greater};
 use std::str::FromStr;
 
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone,Serialize,Deserialize)]
 pub enum VersionRelation {
     StrictlyLess, // <<
     LessOrEqual, // <=
@@ -13,7 +16,7 @@ pub enum VersionRelation {
 
 impl fmt::Display for VersionRelation {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        return match self {
+        match self {
             VersionRelation::StrictlyLess =>    write!(f, "<<"),
             VersionRelation::LessOrEqual =>     write!(f, "<="),
             VersionRelation::Equal =>           write!(f, "="),
@@ -23,8 +26,20 @@ impl fmt::Display for VersionRelation {
     }
 }
 
+/// Why a version number or version relation operator could not be parsed.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct ParseVersionError(String);
+
+impl fmt::Display for ParseVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ParseVersionError {}
+
 impl FromStr for VersionRelation {
-    type Err = ParseError;
+    type Err = ParseVersionError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
@@ -33,13 +48,13 @@ impl FromStr for VersionRelation {
             "=" =>  Ok(VersionRelation::Equal),
             ">=" => Ok(VersionRelation::GreaterOrEqual),
             ">>" => Ok(VersionRelation::StrictlyGreater),
-            _ => panic!("bad version relation {}", s) // gah this should be a ParseError but I don't know how to do that, accepting PRs
+            _ => Err(ParseVersionError(format!("bad version relation {:?}", s)))
         }
     }
 }
 
 
-#[derive(PartialEq,Eq)]
+#[derive(PartialEq,Eq,Clone,Serialize,Deserialize)]
 pub struct DebianVersionNum {
     epoch : String,
     upstream : String,
@@ -61,9 +76,13 @@ impl fmt::Display for DebianVersionNum {
 }
 
 impl FromStr for DebianVersionNum {
-    type Err = ParseError;
+    type Err = ParseVersionError;
 
+    /// Parses [epoch:]upstream[-revision]. The epoch must be a number, the upstream version must not be empty, and
+    /// only alphanumerics and . + ~ (plus - and : in the upstream version) may appear; see
+    /// https://www.debian.org/doc/debian-policy/ch-controlfields.html#version
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = |why: &str| Err(ParseVersionError(format!("bad version {:?}: {}", s, why)));
         let (ep, rest0) = match s.find(':') {
             None => ("", s),
             Some(e) => { let (_e, _r) = s.split_at(e); (_e, &_r[1..]) }
@@ -72,6 +91,19 @@ impl FromStr for DebianVersionNum {
             None => (rest0, ""),
             Some(d) => { let (_u, _d) = rest0.split_at(d); (_u, &_d[1..]) }
         };
+        if !ep.is_empty() && !ep.bytes().all(|c| c.is_ascii_digit()) || s.starts_with(':') {
+            return bad("the epoch is not a number");
+        }
+        if up.is_empty() {
+            return bad("no upstream version");
+        }
+        if deb.is_empty() && rest0.ends_with('-') {
+            return bad("empty Debian revision");
+        }
+        let allowed = |c: char, extra: &str| c.is_ascii_alphanumeric() || ".+~".contains(c) || extra.contains(c);
+        if !up.chars().all(|c| allowed(c, "-:")) || !deb.chars().all(|c| allowed(c, "")) {
+            return bad("invalid character");
+        }
         Ok(DebianVersionNum {
             epoch : ep.to_string(),
             upstream: up.to_string(),
@@ -80,34 +112,28 @@ impl FromStr for DebianVersionNum {
     }
 }
 
-impl Ord for DebianVersionNum {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap()
+impl PartialOrd for DebianVersionNum {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-impl PartialOrd for DebianVersionNum {
+impl Ord for DebianVersionNum {
     // https://www.debian.org/doc/debian-policy/ch-controlfields.html#version
     // wow this is painful
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         fn extract_nonnum(s: &str) -> (&str,&str) {
             let first_digit = s.find(|c:char| c.is_ascii_digit());
             match first_digit {
                 None => (s, ""),
-                Some(fd) => {
-                    let (nonnum, rest) = s.split_at(fd);
-                    return (nonnum, rest);
-                }
+                Some(fd) => s.split_at(fd)
             }
         }
         fn extract_num(s: &str) -> (&str,&str) {
             let first_nondigit = s.find(|c:char| !c.is_ascii_digit());
             match first_nondigit {
                 None => (s, ""),
-                Some(fd) => {
-                    let (num, rest) = s.split_at(fd);
-                    return (num, rest);
-                }
+                Some(fd) => s.split_at(fd)
             }
         }
 
@@ -129,20 +155,22 @@ impl PartialOrd for DebianVersionNum {
 
         fn debian_nonnum_cmp(s: &str, o: &str) -> std::cmp::Ordering {
             let (d_s, d_o) = (to_debian_chars(s), to_debian_chars(o));
-            for it in d_s.iter().zip(d_o.iter()) {
-                let (c_s, c_o) = it;
+            // the end of a part sorts as 0: after a tilde, before anything else (aa~ < aa < aaa)
+            for i in 0..d_s.len().max(d_o.len()) {
+                let (c_s, c_o) = (d_s.get(i).unwrap_or(&0), d_o.get(i).unwrap_or(&0));
                 if c_s < c_o { return Less; }
                 if c_s > c_o { return Greater; }
             }
-            // aa < aaa
-            if s.len() < o.len() { return Less; }
-            // aa~ < aa
-            if s.len() > o.len() && s.ends_with('~') { return Less; }
-            // aaa > aa
-            if s.len() > o.len() { return Greater; }
             Equal
         }
 
+        // digit runs compare as numbers of any length: without their leading zeros, the longer run is the greater, and
+        // runs of the same length compare like their digits
+        fn debian_num_cmp(s: &str, o: &str) -> std::cmp::Ordering {
+            let (s, o) = (s.trim_start_matches('0'), o.trim_start_matches('0'));
+            s.len().cmp(&o.len()).then_with(|| s.cmp(o))
+        }
+
         fn debian_cmp(self_vers: &str, other_vers: &str) -> std::cmp::Ordering {
             let mut sv = self_vers;
             let mut ov = other_vers;
@@ -161,33 +189,29 @@ impl PartialOrd for DebianVersionNum {
                 let (self_num, self_rest1) = extract_num(self_rest);
                 let (other_num, other_rest1) = extract_num(other_rest);
 
-                let (sn_i, on_i) = (
-                    match self_num.parse::<i32>() { Err(_) => 0, Ok(e) => e },
-                    match other_num.parse::<i32>() { Err(_) => 0, Ok(e) => e });
-
-                if sn_i != on_i {
-                    return sn_i.partial_cmp(&on_i).unwrap();
+                match debian_num_cmp(self_num, other_num) {
+                    Less => return Less,
+                    Greater => return Greater,
+                    _ => ()
                 }
                 sv = self_rest1; ov = other_rest1;
             }
         }
 
-        let (epoch, other_epoch) = (
-            match self.epoch.parse::<i32>() { Err(_) => 0, Ok(e) => e },
-            match other.epoch.parse::<i32>() { Err(_) => 0, Ok(e) => e });
-        if epoch != other_epoch {
-            return Some(epoch.partial_cmp(&other_epoch).unwrap());
+        let epochs = debian_num_cmp(&self.epoch, &other.epoch);
+        if epochs != Equal {
+            return epochs;
         }
         let ups = debian_cmp(&self.upstream, &other.upstream);
         if ups != Equal {
-            return Some(ups);
+            return ups;
         }
-        return Some(debian_cmp(&self.debian, &other.debian));
+        debian_cmp(&self.debian, &other.debian)
     }
 }
 
 pub fn cmp_debversion_with_op(op:&VersionRelation, first: &DebianVersionNum, second: &DebianVersionNum) -> bool {
-    return match op {
+    match op {
         VersionRelation::StrictlyLess => first < second,
         VersionRelation::LessOrEqual => first <= second,
         VersionRelation::Equal => first == second,
@@ -195,3 +219,54 @@ pub fn cmp_debversion_with_op(op:&VersionRelation, first: &DebianVersionNum, sec
         VersionRelation::StrictlyGreater => first > second
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmp(a: &str, b: &str) -> std::cmp::Ordering {
+        a.parse::<DebianVersionNum>().unwrap().cmp(&b.parse::<DebianVersionNum>().unwrap())
+    }
+
+    #[test]
+    fn tilde_sorts_before_the_end_of_a_part() {
+        assert_eq!(cmp("1.0~rc1", "1.0"), Less);
+        assert_eq!(cmp("1.0~", "1.0"), Less);
+        assert_eq!(cmp("1.0~~", "1.0~"), Less);
+        assert_eq!(cmp("1.0~rc1", "1.0~rc1"), Equal);
+        // the end of the upstream version, then the start of the revision
+        assert_eq!(cmp("5.36.0-1", "5.36.0~"), Greater);
+        assert_eq!(cmp("5.36.0~rc1-1", "5.36.0-1"), Less);
+    }
+
+    #[test]
+    fn end_of_a_part_sorts_before_anything_else() {
+        assert_eq!(cmp("1.0", "1.0a"), Less);
+        assert_eq!(cmp("1.0", "1.0+b1"), Less);
+        assert_eq!(cmp("1.0a", "1.0"), Greater);
+        assert_eq!(cmp("1.0a", "1.0+"), Less);
+        assert_eq!(cmp("1:0.9", "2.0"), Greater);
+    }
+
+    #[test]
+    fn relations_compare_with_their_operator() {
+        let (old, new) = ("1.0~rc1".parse().unwrap(), "1.0".parse().unwrap());
+        assert!(cmp_debversion_with_op(&VersionRelation::StrictlyLess, &old, &new));
+        assert!(!cmp_debversion_with_op(&VersionRelation::GreaterOrEqual, &old, &new));
+        assert!(cmp_debversion_with_op(&VersionRelation::LessOrEqual, &new, &new));
+    }
+
+    #[test]
+    fn digit_runs_of_any_length() {
+        // a snapshot date with the time does not fit in 32 bits, nor does its difference from a smaller run
+        assert_eq!(cmp("0.0~git20240101123456", "0.0~git20240101123457"), Less);
+        assert_eq!(cmp("0.0~git20240101123456", "0.0~git9"), Greater);
+        assert_eq!(cmp("0.0~git20240101123456-1", "0.0~git20240101123456-1"), Equal);
+        assert_eq!(cmp("1.99999999999999999999999", "1.100000000000000000000000"), Less);
+        // leading zeros do not count
+        assert_eq!(cmp("1.007", "1.7"), Equal);
+        assert_eq!(cmp("1.0010", "1.9"), Greater);
+        assert_eq!(cmp("99999999999:1.0", "100000000000:0.1"), Less);
+        assert_eq!(cmp("0:1.0", "1.0"), Equal);
+    }
+}
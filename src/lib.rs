@@ -1 +1,5 @@
 pub mod debversion;
+pub mod deb822;
+pub mod packages;
+
+pub use packages::Packages;
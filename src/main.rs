@@ -2,23 +2,52 @@ use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
 use rpkg::debversion;
-use crate::packages::Packages;
+use rpkg::packages::{IndexStorage, LoadMode, RelationKind, SolverMode};
+use rpkg::Packages;
 
-mod packages;
+mod output;
 
-fn check_syntax(n: usize, cmd_fragments:&Vec<&str>, arg: &str) -> bool {
-    let cmd : &str = &cmd_fragments.get(0).unwrap();
+fn check_syntax(n: usize, cmd_fragments: &[&str], arg: &str) -> bool {
+    let cmd : &str = cmd_fragments[0];
     if cmd_fragments.len() != n {
         println!("syntax: {} {}", cmd, arg);
         return false
     }
-    return true
+    true
+}
+
+fn check_min_syntax(n: usize, cmd_fragments: &[&str], arg: &str) -> bool {
+    let cmd : &str = cmd_fragments[0];
+    if cmd_fragments.len() < n {
+        println!("syntax: {} {}", cmd, arg);
+        return false
+    }
+    true
+}
+
+// relation kinds given as trailing arguments, e.g. "recommends suggests" or "all"; falls back to default if none given
+fn parse_relation_kinds(args: &[&str], default: &[RelationKind]) -> Option<Vec<RelationKind>> {
+    if args.is_empty() {
+        return Some(default.to_vec());
+    }
+    let mut kinds = vec![];
+    for arg in args {
+        if *arg == "all" {
+            kinds.extend_from_slice(&RelationKind::ALL);
+            continue;
+        }
+        match arg.parse::<RelationKind>() {
+            Ok(kind) => kinds.push(kind),
+            Err(e) => { println!("{}", e); return None }
+        }
+    }
+    Some(kinds)
 }
 
 fn process_command(state: &mut Packages, cmdline: &str) -> bool {
     let cmd_fragments: Vec<&str> = cmdline.split(" ").collect();
     if cmdline.is_empty() { return false }
-    let cmd : &str = &cmd_fragments.get(0).unwrap();
+    let cmd : &str = cmd_fragments[0];
     match cmd {
         "quit" => { 
             return true 
@@ -26,56 +55,233 @@ fn process_command(state: &mut Packages, cmdline: &str) -> bool {
         "load-csv" | "lc" => {
             if !check_syntax(2, &cmd_fragments, "<csvfile-name>") { return false; }
             let arg = cmd_fragments.get(1).unwrap();
-            state.parse_csv(arg)
+            output::print_load_result(state.parse_csv(arg), "Packages available", state.available_count())
         }
         // parsers.rs
         "load-packages" | "lp" => {
-            if !check_syntax(2, &cmd_fragments, "<pkgfile-name>") { return false; }
+            if cmd_fragments.len() != 2 && cmd_fragments.len() != 3 {
+                println!("syntax: {} <pkgfile-name> [<release-file>]", cmd);
+                return false;
+            }
             let arg = cmd_fragments.get(1).unwrap();
-            state.parse_packages(arg)
+            output::print_load_result(state.parse_packages(arg, cmd_fragments.get(2).copied()), "Packages available", state.available_count())
         }
         "load-installed" | "li" => {
             if !check_syntax(2, &cmd_fragments, "<pkgfile-name>") { return false; }
             let arg = cmd_fragments.get(1).unwrap();
-            state.parse_installed(arg)
+            output::print_load_result(state.parse_installed(arg), "Packages installed", state.installed_count())
         }
         // convenience function, also depends on parsers.rs
         "load-defaults" | "ld" => {
-            state.parse_packages("data/mirror.csclub.uwaterloo.ca_debian_dists_sid_main_binary-amd64_Packages");
-            state.parse_installed("data/installed-packages")
+            output::print_load_result(state.parse_packages("data/mirror.csclub.uwaterloo.ca_debian_dists_sid_main_binary-amd64_Packages", None),
+                              "Packages available", state.available_count());
+            output::print_load_result(state.parse_installed("data/installed-packages"), "Packages installed", state.installed_count())
+        }
+        // errors.rs
+        "load-mode" => {
+            if cmd_fragments.len() > 2 {
+                println!("syntax: {} [lenient|strict]", cmd);
+                return false;
+            }
+            match cmd_fragments.get(1).map(|m| m.parse::<LoadMode>()) {
+                None => println!("load mode: {}", state.load_mode()),
+                Some(Ok(mode)) => state.set_load_mode(mode),
+                Some(Err(e)) => println!("{}", e)
+            }
+        }
+        // mapped.rs
+        "load-storage" => {
+            if cmd_fragments.len() > 2 {
+                println!("syntax: {} [owned|mapped]", cmd);
+                return false;
+            }
+            match cmd_fragments.get(1).map(|m| m.parse::<IndexStorage>()) {
+                None => println!("index storage: {}", state.index_storage()),
+                Some(Ok(storage)) => state.set_index_storage(storage),
+                Some(Err(e)) => println!("{}", e)
+            }
+        }
+        // validate.rs
+        "validate" => {
+            if !check_syntax(2, &cmd_fragments, "<pkgfile-name>") { return false; }
+            let arg = cmd_fragments.get(1).unwrap();
+            output::print_validation(state, arg)
+        }
+        // snapshot.rs
+        "save-snapshot" => {
+            if !check_syntax(2, &cmd_fragments, "<snapshot-file>") { return false; }
+            let arg = cmd_fragments.get(1).unwrap();
+            let result = state.save_snapshot(arg);
+            output::print_save_snapshot(state, arg, result)
+        }
+        "load-snapshot" => {
+            if !check_syntax(2, &cmd_fragments, "<snapshot-file>") { return false; }
+            let arg = cmd_fragments.get(1).unwrap();
+            let result = state.load_snapshot(arg);
+            output::print_load_snapshot(state, arg, result)
+        }
+        // release.rs
+        "load-suite" => {
+            if cmd_fragments.len() < 3 || cmd_fragments.len() > 5 {
+                println!("syntax: {} <mirror-dir> <suite> [<component> [<arch>]]", cmd);
+                return false;
+            }
+            let component = cmd_fragments.get(3).unwrap_or(&"main");
+            let arch = cmd_fragments.get(4).unwrap_or(&"amd64");
+            output::print_load_result(state.load_suite(cmd_fragments[1], cmd_fragments[2], component, arch), "Packages available", state.available_count())
+        }
+        // preferences.rs
+        "load-preferences" => {
+            if !check_syntax(2, &cmd_fragments, "<preferences-file>") { return false; }
+            let arg = cmd_fragments.get(1).unwrap();
+            output::print_load_result(state.parse_preferences(arg), "Pin preferences", state.preference_count())
         }
 
+        "audit" => {
+            output::print_audit(state)
+        }
+        // repository.rs
+        "integrity-conflicts" => {
+            output::print_integrity_conflicts(state)
+        }
         "info" => {
             if !check_syntax(2, &cmd_fragments, "<pkg>") { return false; }
             let pkg = cmd_fragments.get(1).unwrap();
-            state.print_info(pkg)
+            output::print_info(state, pkg)
+        }
+        "show" => {
+            if cmd_fragments.len() < 2 || cmd_fragments.len() > 3 {
+                println!("syntax: {} <pkg> [<field>]", cmd);
+                return false
+            }
+            let pkg = cmd_fragments.get(1).unwrap();
+            match cmd_fragments.get(2) {
+                None => output::print_show(state, pkg),
+                Some(field) => output::print_field(state, pkg, field)
+            }
+        }
+        "policy" => {
+            if !check_syntax(2, &cmd_fragments, "<pkg>") { return false; }
+            let pkg = cmd_fragments.get(1).unwrap();
+            output::print_policy(state, pkg)
+        }
+        "providers" => {
+            if !check_syntax(2, &cmd_fragments, "<virtual-pkg>") { return false; }
+            let pkg = cmd_fragments.get(1).unwrap();
+            output::print_providers(state, pkg)
         }
         "deps" => {
             // test: deps 0ad
-            if !check_syntax(2, &cmd_fragments, "<pkg>") { return false; }
+            if !check_min_syntax(2, &cmd_fragments, "<pkg> [<relation-kind>...|all]") { return false; }
             let pkg = cmd_fragments.get(1).unwrap();
-            state.print_deps(pkg)
+            if let Some(kinds) = parse_relation_kinds(&cmd_fragments[2..], &[RelationKind::Depends]) {
+                output::print_deps(state, pkg, &kinds)
+            }
         }
 
         // deps-available.rs
         "deps-available" => {
             // test: deps-available 3depict
-            if !check_syntax(2, &cmd_fragments, "<pkg>") { return false; }
+            if !check_min_syntax(2, &cmd_fragments, "<pkg> [<relation-kind>...|all]") { return false; }
             let pkg = cmd_fragments.get(1).unwrap();
-            state.deps_available(pkg)
+            if let Some(kinds) = parse_relation_kinds(&cmd_fragments[2..], &RelationKind::REQUIRED) {
+                output::print_deps_available(state, pkg, &kinds)
+            }
         }
 
         // solvers.rs, and deps-available.rs for how-to-install
         "transitive-dep-solution" => {
             // test: transitive-dep-solution 0ad
-            if !check_syntax(2, &cmd_fragments, "<pkg>") { return false; }
+            // test: transitive-dep-solution 0ad versioned
+            if !check_min_syntax(2, &cmd_fragments, "<pkg> [versioned]") { return false; }
             let pkg = cmd_fragments.get(1).unwrap();
-            state.print_transitive_dep_solution(pkg)
+            match cmd_fragments.get(2) {
+                None => output::print_transitive_dep_solution(state, pkg),
+                Some(&"versioned") if cmd_fragments.len() == 3 => output::print_transitive_versioned_solution(state, pkg),
+                Some(_) => { println!("syntax: {} <pkg> [versioned]", cmd); return false; }
+            }
         }
         "how-to-install" => {
+            // test: how-to-install 3depict recommends
+            if !check_min_syntax(2, &cmd_fragments, "<pkg> [<extra-relation-kind>...]") { return false; }
+            let pkg = cmd_fragments.get(1).unwrap();
+            if let Some(extra) = parse_relation_kinds(&cmd_fragments[2..], &[]) {
+                if let Some(kind) = extra.iter().find(|k| k.is_negative()) {
+                    println!("{} is not a relation that can be followed", kind);
+                    return false;
+                }
+                let mut kinds = RelationKind::REQUIRED.to_vec();
+                kinds.extend(extra.into_iter().filter(|k| !RelationKind::REQUIRED.contains(k)));
+                output::print_how_to_install(state, pkg, &kinds)
+            }
+        }
+        // installability.rs
+        "check-installable" => {
+            if !check_syntax(1, &cmd_fragments, "") { return false; }
+            output::print_installability(state, &state.check_installable())
+        }
+        // consistency.rs
+        "check-installed" => {
+            // test: check-installed json
+            match cmd_fragments.get(1) {
+                None => output::print_installed_check(state, &state.check_installed()),
+                Some(&"json") if cmd_fragments.len() == 2 => output::print_installed_check_json(state, &state.check_installed()),
+                Some(_) => { println!("syntax: {} [json]", cmd); return false; }
+            }
+        }
+        // upgrade.rs
+        "upgradable" => {
+            if !check_syntax(1, &cmd_fragments, "") { return false; }
+            output::print_upgradable(state)
+        }
+        "plan-upgrade" => {
+            // test: plan-upgrade dist
+            match cmd_fragments.get(1) {
+                None => output::print_upgrade_plan(state, false),
+                Some(&"dist") if cmd_fragments.len() == 2 => output::print_upgrade_plan(state, true),
+                Some(_) => { println!("syntax: {} [dist]", cmd); return false; }
+            }
+        }
+        // planner.rs
+        "solver" => {
+            if cmd_fragments.len() > 2 {
+                println!("syntax: {} [sat|greedy]", cmd);
+                return false;
+            }
+            match cmd_fragments.get(1).map(|m| m.parse::<SolverMode>()) {
+                None => println!("solver: {}", state.solver_mode()),
+                Some(Ok(mode)) => state.set_solver_mode(mode),
+                Some(Err(e)) => println!("{}", e)
+            }
+        }
+
+        // sources.rs
+        "load-sources" => {
+            if cmd_fragments.len() != 2 && cmd_fragments.len() != 3 {
+                println!("syntax: {} <sources-file> [<release-file>]", cmd);
+                return false;
+            }
+            let arg = cmd_fragments.get(1).unwrap();
+            output::print_load_result(state.parse_sources(arg, cmd_fragments.get(2).copied()), "Source packages available", state.source_count())
+        }
+        "build-dep" => {
+            if !check_min_syntax(2, &cmd_fragments, "<srcpkg> [<build-profile>...]") { return false; }
+            let src = cmd_fragments.get(1).unwrap();
+            let profiles : Vec<String> = cmd_fragments[2..].iter().map(|p| p.to_string()).collect();
+            output::print_build_dep(state, src, &profiles)
+        }
+        "source-of" => {
             if !check_syntax(2, &cmd_fragments, "<pkg>") { return false; }
             let pkg = cmd_fragments.get(1).unwrap();
-            state.print_how_to_install(pkg)
+            output::print_source_of(state, pkg)
+        }
+        "binaries-of" => {
+            if !check_syntax(2, &cmd_fragments, "<srcpkg>") { return false; }
+            let src = cmd_fragments.get(1).unwrap();
+            output::print_binaries_of(state, src)
+        }
+        "version-skew" => {
+            output::print_version_skew(state)
         }
 
         "set-server" => {
@@ -84,33 +290,56 @@ fn process_command(state: &mut Packages, cmdline: &str) -> bool {
             state.set_server(server)
         }
         "execute" => {
-            state.execute();
+            output::print_verifications(&state.execute());
         }
         "enq-verify" => {
-            let cmd : &str = &cmd_fragments.get(0).unwrap();
+            let cmd : &str = cmd_fragments[0];
             if cmd_fragments.len() < 2 || cmd_fragments.len() > 3 {
                 println!("syntax: {} <pkg> [<version>]", cmd);
                 return false
             }
             let pkg = cmd_fragments.get(1).unwrap();
-            if cmd_fragments.len() == 2 {
-                state.enq_verify(pkg);
-            } else {
-                let version = cmd_fragments.get(2).unwrap();
-                state.enq_verify_with_version(pkg, version);
+            let url = match cmd_fragments.get(2) {
+                None => state.enq_verify(pkg),
+                Some(version) => Some(state.enq_verify_with_version(pkg, version))
+            };
+            match url {
+                None => println!("Error: package {} not defined.", pkg),
+                Some(url) => println!("queueing request {}", url)
             }
         }
 
         "output-md5s" => {
             if !check_syntax(2, &cmd_fragments, "<output-file>") { return false; }
             let fname = cmd_fragments.get(1).unwrap();
-            state.output_md5s(fname);
+            if let Err(e) = state.output_md5s(fname) {
+                println!("error: {}: {}", fname, e);
+            }
+        }
+        // bench.rs
+        "bench-parse" => {
+            if cmd_fragments.len() != 2 && cmd_fragments.len() != 3 {
+                println!("syntax: {} <pkgfile-name> [<runs>]", cmd);
+                return false;
+            }
+            let runs = match cmd_fragments.get(2).map(|r| r.parse::<usize>()) {
+                None => 1,
+                Some(Ok(runs)) if runs > 0 => runs,
+                _ => { println!("runs must be a positive number"); return false }
+            };
+            match state.bench_parse(cmd_fragments[1], runs) {
+                Err(e) => println!("error: {}", e),
+                Ok(report) => output::print_bench(&report)
+            }
         }
         "test-version-compare" => {
             if !check_syntax(3, &cmd_fragments, "<version1> <version2>") { return false; }
-            let v1 = cmd_fragments.get(1).unwrap().parse::<debversion::DebianVersionNum>().unwrap();
-            let v2 = cmd_fragments.get(2).unwrap().parse::<debversion::DebianVersionNum>().unwrap();
-            println!("{} and {}: {:?}", v1, v2, v1.cmp(&v2));
+            let v1 = cmd_fragments.get(1).unwrap().parse::<debversion::DebianVersionNum>();
+            let v2 = cmd_fragments.get(2).unwrap().parse::<debversion::DebianVersionNum>();
+            match (v1, v2) {
+                (Ok(v1), Ok(v2)) => println!("{} and {}: {:?}", v1, v2, v1.cmp(&v2)),
+                (Err(e), _) | (_, Err(e)) => println!("{}", e)
+            }
             // 1:0.4.5+cvs20030824-9 vs 1:0.4.5+cvs20030824-10
             // a vs b
             // a vs a
@@ -123,7 +352,7 @@ fn process_command(state: &mut Packages, cmdline: &str) -> bool {
             println!("couldn't understand cmd {:?}", cmd)
         }
     }
-    return false;
+    false
 }
 
 fn main() {
@@ -131,7 +360,8 @@ fn main() {
 
     // bonus (0 points): implement command completion!
     let mut rl = Editor::<()>::new();
-    if rl.load_history("history.txt").is_err() {}
+    // there is no history before the first session
+    let _ = rl.load_history("history.txt");
     loop {
         let readline = rl.readline("$ ");
         match readline {
@@ -152,4 +382,6 @@ fn main() {
         }
     }
     rl.save_history("history.txt").unwrap();
+    // requests still queued are sent on the way out
+    output::print_verifications(&state.execute());
 }
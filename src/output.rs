@@ -0,0 +1,728 @@
+use std::borrow::Cow;
+
+use itertools::Itertools;
+
+use rpkg::debversion::DebianVersionNum;
+use rpkg::packages::{AvailableVersion, BenchDifference, BenchReport, BrokenPackage, BuildPlan, Change, ConflictResolution,
+                     Dependency, InstallPlan, InstallabilityReport, LoadError, LoadReport, MultiArch, PlanConflict, Provider, Reason,
+                     RelVersionedPackageNum, RelationKind, SnapshotError, SolverMode, SourceVersion, Verification};
+use rpkg::Packages;
+
+// What the REPL prints for each command, from what the rpkg API returns. Commands on a package that does not exist say
+// so and print nothing else.
+
+// how many skipped stanzas a load prints before summarizing the rest
+const SKIPPED_SHOWN : usize = 10;
+
+/// Reports on a loader: its warnings and what is loaded now (e.g. "Packages available: 63846"), then what it skipped in
+/// lenient mode; or why it stopped.
+pub fn print_load_result(result: Result<LoadReport, LoadError>, loaded: &str, count: usize) {
+    let report = match result {
+        Err(e) if e.is_fatal() => { println!("error: {}", e); return }
+        Err(e) => { println!("error: {}; stopped loading (strict mode)", e); return }
+        Ok(report) => report
+    };
+    for w in &report.warnings {
+        println!("warning: {}", w);
+    }
+    println!("{}: {}", loaded, count);
+    if report.skipped.is_empty() { return }
+    println!("{}: loaded {}, skipped {} malformed:", report.filename, report.loaded, report.skipped.len());
+    for e in report.skipped.iter().take(SKIPPED_SHOWN) {
+        println!("  {}", e);
+    }
+    if report.skipped.len() > SKIPPED_SHOWN {
+        println!("  ... and {} more", report.skipped.len() - SKIPPED_SHOWN);
+    }
+}
+
+// the number of package_name, after saying so if there is no such package
+fn existing_package(state: &Packages, package_name: &str) -> Option<u32> {
+    let package_num = state.package_num(package_name);
+    if package_num.is_none() {
+        println!("no such package {}", package_name);
+    }
+    package_num
+}
+
+// the name of package_num; the numbers printed all come from state, so the fallback is never seen
+fn name(state: &Packages, package_num: u32) -> &str {
+    state.get_package_name(package_num).unwrap_or("<unknown package>")
+}
+
+// the same for qualified names and relations
+fn qualified_name(state: &Packages, package_num: u32, arch: Option<&str>) -> String {
+    state.qualified_name(package_num, arch).unwrap_or_else(|| String::from("<unknown package>"))
+}
+
+fn deps2str(state: &Packages, deps: &[Dependency]) -> String {
+    state.deps2str(deps).unwrap_or_else(|| String::from("<unknown package>"))
+}
+
+fn dep2str(state: &Packages, dep: &Dependency) -> String {
+    state.dep2str(dep).unwrap_or_else(|| String::from("<unknown package>"))
+}
+
+fn rel2str(state: &Packages, rel: &RelVersionedPackageNum) -> String {
+    state.rel2str(rel).unwrap_or_else(|| String::from("<unknown package>"))
+}
+
+fn names(state: &Packages, packages: &[u32]) -> String {
+    packages.iter().map(|p| name(state, *p)).format(", ").to_string()
+}
+
+// Where the candidate version of package_num comes from.
+fn candidate_origins2str(state: &Packages, package_num: u32) -> String {
+    match state.get_candidate(package_num) {
+        None => String::from("not available"),
+        Some(c) => c.origins.iter().map(|o| state.origins()[*o].to_string()).collect::<Vec<String>>().join(", ")
+    }
+}
+
+// the planned packages that have a candidate, with where it comes from
+fn print_planned(state: &Packages, plan: &[u32]) {
+    for p in plan {
+        if let Some(v) = state.get_candidate_debver(*p) {
+            println!("- {} {} from {}", name(state, *p), v, candidate_origins2str(state, *p));
+        }
+    }
+}
+
+pub fn print_deps(state: &Packages, package_name: &str, kinds: &[RelationKind]) {
+    let package_num = match existing_package(state, package_name) {
+        None => return,
+        Some(p) => p
+    };
+    for kind in kinds {
+        let deps = state.get_relations(*kind, package_num);
+        if deps.is_empty() && kinds.len() > 1 { continue }
+        println!("{:?} {} {:?}", package_name, kind.verb(), deps2str(state, deps));
+    }
+}
+
+pub fn print_transitive_dep_solution(state: &Packages, package_name: &str) {
+    if existing_package(state, package_name).is_none() { return }
+    let dep_solution = state.transitive_dep_solution(package_name);
+    println!("{:?} transitive dependency solution: {:?}", package_name, names(state, &dep_solution));
+}
+
+pub fn print_transitive_versioned_solution(state: &Packages, package_name: &str) {
+    let closure = match state.transitive_versioned_solution(package_name) {
+        None => { println!("no such package {}", package_name); return }
+        Some(c) => c
+    };
+    println!("{:?} transitive dependency solution: {:?}", package_name, names(state, &closure.packages));
+    if closure.unsatisfiable.is_empty() { return }
+    println!("Unsatisfiable:");
+    for edge in &closure.unsatisfiable {
+        let declared_by = match state.get_candidate_debver(edge.package_num) {
+            None => format!("{:?}", name(state, edge.package_num)),
+            Some(v) => format!("{:?} ({})", name(state, edge.package_num), v)
+        };
+        println!("- {} {} {:?}: nothing available satisfies it", declared_by, edge.kind.verb(), dep2str(state, edge.relation));
+    }
+}
+
+/// Prints how to install package_name, planned by the solver of the session.
+pub fn print_how_to_install(state: &Packages, package_name: &str, kinds: &[RelationKind]) {
+    match state.solver_mode() {
+        SolverMode::Greedy => print_greedy_install(state, package_name, kinds),
+        SolverMode::Sat => print_solved_install(state, package_name, kinds)
+    }
+}
+
+fn print_greedy_install(state: &Packages, package_name: &str, kinds: &[RelationKind]) {
+    let InstallPlan { dependencies, packages, conflicts } = match state.plan_install(package_name, kinds) {
+        None => { println!("no such package {}", package_name); return }
+        Some(plan) => plan
+    };
+    println!("Package {}:", package_name);
+    println!("{:?} to install: {:?}", package_name, names(state, &dependencies));
+    print_planned(state, &packages);
+    print_plan_conflicts(state, &conflicts);
+}
+
+// "libc6 2.36-9 (installed)"
+fn choice2str(state: &Packages, package_num: u32, version: &DebianVersionNum, installed: bool) -> String {
+    format!("{} {}{}", name(state, package_num), version, if installed { " (installed)" } else { "" })
+}
+
+fn print_solved_install(state: &Packages, package_name: &str, kinds: &[RelationKind]) {
+    let solution = match state.solve_install(package_name, kinds) {
+        None => { println!("no such package {}", package_name); return }
+        Some(s) => s
+    };
+    println!("Package {}:", package_name);
+    let changes = match solution {
+        Ok(changes) => changes,
+        Err(reasons) => {
+            println!("{:?} cannot be installed:", package_name);
+            for reason in reasons {
+                println!("- {}", reason2str(state, &reason));
+            }
+            return
+        }
+    };
+    let installed : Vec<u32> = changes.iter().filter(|c| !matches!(c, Change::Remove { .. })).map(|c| c.package_num())
+        .filter(|p| name(state, *p) != package_name).collect();
+    println!("{:?} to install: {:?}", package_name, names(state, &installed));
+    for change in &changes {
+        match change {
+            Change::Install { package_num, version } => println!("- {} {} from {}", name(state, *package_num),
+                version.version, version.origins.iter().map(|o| state.origins()[*o].to_string()).format(", ")),
+            Change::Upgrade { package_num, from, to } => println!("- {} {} from {} (upgrading {})", name(state, *package_num),
+                to.version, to.origins.iter().map(|o| state.origins()[*o].to_string()).format(", "), from),
+            Change::Remove { package_num, version } => println!("- remove {} {}", name(state, *package_num), version)
+        }
+    }
+}
+
+/// Prints the versions that cannot be installed from the repository, each with the constraints that rule it out.
+pub fn print_installability(state: &Packages, report: &InstallabilityReport) {
+    println!("Checked {} versions: {} cannot be installed", report.checked, report.uninstallable.len());
+    for u in &report.uninstallable {
+        println!("{} {}:", name(state, u.package_num), u.version);
+        for reason in u.reasons.iter().filter(|r| !matches!(r, Reason::Install { .. })) {
+            println!("- {}", reason2str(state, reason));
+        }
+    }
+}
+
+/// Prints the installed packages whose Pre-Depends or Depends the installed set does not meet, like apt-get check.
+pub fn print_installed_check(state: &Packages, broken: &[BrokenPackage]) {
+    println!("Checked {} installed packages: {} have unmet relations", state.installed_count(), broken.len());
+    for b in broken {
+        println!("{} {}:", qualified_name(state, b.package_num, b.arch), b.version);
+        for u in &b.unmet {
+            let wrong : Vec<String> = u.wrong_versions.iter().map(|(p, v)| format!("{} {}", name(state, *p), v)).collect();
+            let installed = match wrong.as_slice() {
+                [] => "nothing installed satisfies it".to_string(),
+                [one] => format!("but {} is installed", one),
+                _ => format!("but {} are installed", wrong.join(" and "))
+            };
+            println!("- {} {:?}: {}", u.kind.verb(), dep2str(state, u.relation), installed);
+        }
+    }
+}
+
+/// The same report as one line of JSON, e.g. to compare the status files of many machines.
+pub fn print_installed_check_json(state: &Packages, broken: &[BrokenPackage]) {
+    println!("{}", installed_check_json(state, broken));
+}
+
+// {"installed":N,"broken":[{"package":..,"version":..,"unmet":[{"kind":"Depends","relation":..,"installed":[..]}]}]},
+// where installed lists the wrong versions of an unmet Depends, or what a Conflicts or Breaks matches
+fn installed_check_json(state: &Packages, broken: &[BrokenPackage]) -> serde_json::Value {
+    let broken : Vec<serde_json::Value> = broken.iter().map(|b| serde_json::json!({
+        "package" : qualified_name(state, b.package_num, b.arch),
+        "version" : b.version.to_string(),
+        "unmet" : b.unmet.iter().map(|u| serde_json::json!({
+            "kind" : u.kind.field_name(),
+            "relation" : dep2str(state, u.relation),
+            "installed" : u.wrong_versions.iter().map(|(p, v)| serde_json::json!({
+                "package" : name(state, *p),
+                "version" : v.to_string()
+            })).collect::<Vec<_>>()
+        })).collect::<Vec<_>>()
+    })).collect();
+    serde_json::json!({ "installed" : state.installed_count(), "broken" : broken })
+}
+
+/// Prints the installed packages that have a newer candidate.
+pub fn print_upgradable(state: &Packages) {
+    let upgradable = state.upgradable();
+    println!("{} packages can be upgraded", upgradable.len());
+    for u in &upgradable {
+        println!("- {} {} to {} from {}", name(state, u.package_num), u.installed, u.candidate.version, origins2str(state, u.candidate));
+    }
+}
+
+/// Prints what upgrading the system would upgrade, install, remove and hold back, each with why.
+pub fn print_upgrade_plan(state: &Packages, dist_upgrade: bool) {
+    let plan = match state.plan_upgrade(dist_upgrade) {
+        Ok(plan) => plan,
+        Err(reasons) => {
+            println!("the installed system cannot be kept, let alone upgraded:");
+            for reason in reasons {
+                println!("- {}", reason2str(state, &reason));
+            }
+            return
+        }
+    };
+    println!("{} upgraded, {} newly installed, {} to remove and {} held back",
+             plan.upgraded.len(), plan.new.len(), plan.removed.len(), plan.held_back.len());
+    let because = |reason: &Option<Reason>| match reason {
+        None => "the candidate".to_string(),
+        Some(r) => reason2str(state, r)
+    };
+    let sections = [("Upgraded", &plan.upgraded), ("New", &plan.new), ("Removed", &plan.removed)];
+    for (title, changes) in sections.iter().filter(|(_, changes)| !changes.is_empty()) {
+        println!("{}:", title);
+        for c in changes.iter() {
+            let change = match &c.change {
+                Change::Install { package_num, version } =>
+                    format!("{} {} from {}", name(state, *package_num), version.version, origins2str(state, version)),
+                Change::Upgrade { package_num, from, to } =>
+                    format!("{} {} to {} from {}", name(state, *package_num), from, to.version, origins2str(state, to)),
+                Change::Remove { package_num, version } => format!("{} {}", name(state, *package_num), version)
+            };
+            println!("- {}: {}", change, because(&c.reason));
+        }
+    }
+    if !plan.held_back.is_empty() {
+        println!("Held back:");
+        for h in &plan.held_back {
+            println!("- {} {} (candidate {}): {}", name(state, h.package_num), h.installed, h.candidate.version,
+                     h.reason.as_ref().map_or("the candidate cannot be installed".to_string(), |r| reason2str(state, r)));
+        }
+    }
+}
+
+// "stable/Packages, backports/Packages"
+fn origins2str(state: &Packages, version: &AvailableVersion) -> String {
+    version.origins.iter().map(|o| state.origins()[*o].to_string()).join(", ")
+}
+
+fn reason2str(state: &Packages, reason: &Reason) -> String {
+    match reason {
+        Reason::Install { package_num, versions } if versions.is_empty() =>
+            format!("{} is to be installed, but has no version that can be", name(state, *package_num)),
+        Reason::Install { package_num, versions } =>
+            format!("{} is to be installed, as {}", name(state, *package_num), versions.iter().format(" or ")),
+        Reason::OneVersion { package_num, first, second } =>
+            format!("{} cannot have both {} and {}", name(state, *package_num), first, second),
+        Reason::Depends { package_num, version, installed, kind, relation } =>
+            format!("{} {} {:?}", choice2str(state, *package_num, version, *installed), kind.verb(), dep2str(state, relation)),
+        Reason::Conflicts { package_num, version, installed, kind, relation, target, target_version, target_installed } =>
+            format!("{} {} {:?}, which {} matches", choice2str(state, *package_num, version, *installed), kind.verb(),
+                    rel2str(state, relation), choice2str(state, *target, target_version, *target_installed))
+    }
+}
+
+/// Prints whether the relations of the given kinds of package_name are satisfied, and by which package and version.
+pub fn print_deps_available(state: &Packages, package_name: &str, kinds: &[RelationKind]) {
+    let package_num = match existing_package(state, package_name) {
+        None => return,
+        Some(p) => p
+    };
+    println!("Package {}:", package_name);
+    let dependencies: Vec<_> = state.get_relations_of_kinds(kinds, package_num).collect();
+    if dependencies.is_empty() {
+        println!("There are no associated dependencies.");
+    }
+    for dependency in dependencies {
+        println!("- dependency {:?}", dep2str(state, dependency));
+        match state.dep_is_satisfied(dependency, state.candidate_arch(package_num)) {
+            None => println!("-> not satisfied"),
+            Some(s) if s.is_provider() => println!("+ {} satisfied by installed {} {}, which provides it",
+                name(state, s.alternative.package_num()), name(state, s.package_num), s.version),
+            Some(s) => println!("+ {} satisfied by installed version {}", name(state, s.package_num), s.version)
+        }
+    }
+}
+
+// "dbus-user-session 1.12.20-3 (installed)"
+fn provider2str(state: &Packages, provider: &Provider) -> String {
+    let name = name(state, provider.package_num);
+    let provided = match &provider.version {
+        None => String::new(),
+        Some(v) => format!(" (= {})", v)
+    };
+    let installed = if state.installed_debver(provider.package_num).is_some() { " (installed)" } else { "" };
+    match state.get_candidate_debver(provider.package_num).or_else(|| state.installed_debver(provider.package_num)) {
+        None => format!("{}{}{}", name, provided, installed),
+        Some(v) => format!("{} {}{}{}", name, v, provided, installed)
+    }
+}
+
+/// Prints every known provider of virtual_name, available or installed.
+pub fn print_providers(state: &Packages, virtual_name: &str) {
+    let virtual_num = match existing_package(state, virtual_name) {
+        None => return,
+        Some(p) => p
+    };
+    let available = state.get_available_providers(virtual_num);
+    let mut providers: Vec<&Provider> = available.iter().collect();
+    for p in state.get_installed_providers(virtual_num) {
+        if !providers.iter().any(|q| q.package_num == p.package_num) {
+            providers.push(p);
+        }
+    }
+    if providers.is_empty() {
+        println!("{:?} is not provided by any package", virtual_name);
+        return;
+    }
+    println!("{:?} is provided by:", virtual_name);
+    for p in providers {
+        println!("- {}", provider2str(state, p));
+    }
+}
+
+pub fn print_info(state: &Packages, package_name: &str) {
+    let package_num = match existing_package(state, package_name) {
+        None => return,
+        Some(p) => p
+    };
+    println!("Package: {}", package_name);
+    if state.is_virtual(package_num) {
+        let providers = state.get_available_providers(package_num).into_iter().map(|p| p.package_num)
+            .chain(state.get_installed_providers(package_num).iter().map(|p| p.package_num))
+            .map(|p| name(state, p)).unique().join(", ");
+        println!("Virtual-Package-Provided-By: {}", providers);
+    }
+    let a = state.get_candidate(package_num);
+    let i = state.installed_debver(package_num);
+    if let Some(candidate) = a {
+        println!("Version: {}", candidate.version);
+        if let Some(arch) = &candidate.architecture {
+            println!("Architecture: {}", arch);
+        }
+        if candidate.multi_arch != MultiArch::No {
+            println!("Multi-Arch: {}", candidate.multi_arch);
+        }
+        if let Some(md5sum) = &candidate.md5sum {
+            println!("MD5Sum: {}", md5sum);
+        }
+        println!("Depends: {}", deps2str(state, state.get_relations(RelationKind::Depends, package_num)));
+        for kind in RelationKind::ALL.iter().filter(|k| **k != RelationKind::Depends) {
+            let deps = state.get_relations(*kind, package_num);
+            if !deps.is_empty() {
+                println!("{}: {}", kind, deps2str(state, deps));
+            }
+        }
+    }
+    if let Some(i) = i {
+        println!("Installed-Version: {}", i);
+    }
+    let installed_arches = state.get_installed_instances(package_num).iter().filter(|i| i.status.is_installed())
+        .filter_map(|i| i.arch.as_deref()).join(", ");
+    if !installed_arches.is_empty() {
+        println!("Installed-Architectures: {}", installed_arches);
+    }
+    match state.get_installed_status(package_name) {
+        Some(status) if status.problem().is_some() => println!("Installed-Status: {}", status),
+        _ => ()
+    }
+    if let (Some(a), Some(i)) = (a, i) {
+        println!("Newer-Available: {:?}", a.version > *i);
+    }
+    print_versions(state, package_num);
+}
+
+// every known version of package_num, newest first, marking the candidate and the installed version
+fn print_versions(state: &Packages, package_num: u32) {
+    let candidate = state.get_candidate_debver(package_num);
+    let installed = state.installed_debver(package_num);
+    let mut versions: Vec<(&DebianVersionNum, Option<&str>, &[usize])> = state.get_available_versions(package_num).iter()
+        .map(|v| (&v.version, v.architecture.as_deref(), &v.origins[..])).collect();
+    if let Some(i) = installed {
+        if !versions.iter().any(|(v, _, _)| *v == i) {
+            versions.push((i, None, &[]));
+        }
+    }
+    if versions.is_empty() { return }
+    versions.sort_by(|a, b| b.0.cmp(a.0));
+    println!("Versions:");
+    for (v, arch, origins) in versions {
+        let mut marks = vec![];
+        let native = state.is_native_arch(arch);
+        if let (false, Some(a)) = (native, arch) { marks.push(a); }
+        if Some(v) == candidate && native { marks.push("candidate"); }
+        if Some(v) == installed && native { marks.push("installed"); }
+        if state.get_integrity_conflicts(package_num).any(|c| &c.version == v) { marks.push("MD5sum conflict"); }
+        if marks.is_empty() {
+            println!(" {}", v);
+        } else {
+            println!(" {} ({})", v, marks.join(", "));
+        }
+        for o in origins {
+            println!("    {}", state.origins()[*o]);
+        }
+    }
+}
+
+/// Prints the full stanzas of every available version of package_name, then its installed stanza, like apt-cache show.
+pub fn print_show(state: &Packages, package_name: &str) {
+    let package_num = match existing_package(state, package_name) {
+        None => return,
+        Some(p) => p
+    };
+    let available = state.get_available_versions(package_num).iter().map(|v| v.paragraph.paragraph());
+    for para in available.chain(state.get_installed_paragraphs(package_num).iter().map(Cow::Borrowed)) {
+        println!("{}", para);
+    }
+}
+
+pub fn print_field(state: &Packages, package_name: &str, field: &str) {
+    if existing_package(state, package_name).is_none() { return }
+    match state.get_field(package_name, field) {
+        None => println!("{} has no field {}", package_name, field),
+        Some(value) => println!("{}: {}", field, value)
+    }
+}
+
+/// Prints the installed and candidate versions of package_name and the priority of every version, like apt-cache policy.
+pub fn print_policy(state: &Packages, package_name: &str) {
+    let package_num = match existing_package(state, package_name) {
+        None => return,
+        Some(p) => p
+    };
+    let installed = state.installed_debver(package_num);
+    let candidate = state.get_candidate_debver(package_num);
+    let none = String::from("(none)");
+    println!("{}:", package_name);
+    println!("  Installed: {}", installed.map_or(none.clone(), |v| v.to_string()));
+    println!("  Candidate: {}", candidate.map_or(none, |v| v.to_string()));
+    println!("  Version table:");
+    let mut versions : Vec<&DebianVersionNum> = state.get_available_versions(package_num).iter().map(|v| &v.version).collect();
+    if let Some(i) = installed {
+        if !versions.contains(&i) {
+            versions.push(i);
+        }
+    }
+    versions.sort_by(|a, b| b.cmp(a));
+    for v in versions {
+        let mark = if Some(v) == installed { "***" } else { "   " };
+        println!(" {} {} {}", mark, v, state.version_priority(package_num, v));
+        for source in state.version_sources(package_num, v) {
+            let priority = state.origin_priority(package_name, v, source);
+            match source {
+                None => println!("        {} installed (status file)", priority),
+                Some(o) => println!("        {} {}", priority, state.origins()[o])
+            }
+        }
+    }
+}
+
+pub fn print_integrity_conflicts(state: &Packages) {
+    if state.integrity_conflicts().is_empty() {
+        println!("No integrity conflicts.");
+    }
+    for c in state.integrity_conflicts() {
+        println!("{} {}:", name(state, c.package_num), c.version);
+        println!("  {} from {}", c.first.1, state.origins()[c.first.0]);
+        println!("  {} from {}", c.second.1, state.origins()[c.second.0]);
+    }
+}
+
+// e.g. "libfoo1" (installed 1.2-1)
+fn describe_after_plan(state: &Packages, package_num: u32, planned: bool) -> String {
+    let (what, version) = if planned {
+        ("to install", state.get_candidate_debver(package_num))
+    } else {
+        ("installed", state.installed_debver(package_num))
+    };
+    match version {
+        None => format!("{:?} ({})", name(state, package_num), what),
+        Some(v) => format!("{:?} ({} {})", name(state, package_num), what, v)
+    }
+}
+
+pub fn print_plan_conflicts(state: &Packages, conflicts: &[PlanConflict]) {
+    if conflicts.is_empty() { return }
+    println!("Conflicts:");
+    for c in conflicts {
+        let resolution = match c.resolution {
+            ConflictResolution::Remove(q) if c.replaces =>
+                format!("remove {} (replaced by {})", name(state, q), name(state, c.declared_by)),
+            ConflictResolution::Remove(q) => format!("remove {}", name(state, q)),
+            ConflictResolution::Upgrade(q) =>
+                format!("upgrade {} to {}", name(state, q), state.get_candidate_debver(q).unwrap()),
+            ConflictResolution::Unresolvable => String::from("both are in the plan; no installable combination chosen"),
+        };
+        println!("- {} {} {:?}, found {}: {}", describe_after_plan(state, c.declared_by, c.declared_by_planned),
+                 c.kind.verb(), c.relation, describe_after_plan(state, c.target, c.target_planned), resolution);
+    }
+}
+
+/// Lists the packages of the status file that are in an abnormal state, grouped by problem, like dpkg --audit.
+pub fn print_audit(state: &Packages) {
+    let problems = state.audit();
+    if problems.is_empty() {
+        println!("No packages in abnormal states.");
+    }
+    for (problem, instances) in problems {
+        println!("The following packages are {}:", problem);
+        for (package_num, instance) in instances {
+            println!(" {} {} ({})", qualified_name(state, package_num, instance.arch.as_deref()),
+                     instance.version.as_deref().unwrap_or(""), instance.status);
+        }
+    }
+}
+
+/// Prints the source package of every installed and available version of binary package_name.
+pub fn print_source_of(state: &Packages, package_name: &str) {
+    let package_num = match existing_package(state, package_name) {
+        None => return,
+        Some(p) => p
+    };
+    println!("{:?} is built from:", package_name);
+    for b in state.sources_of(package_num) {
+        let what = if b.installed { "installed" } else { "available" };
+        println!("- {} ({} {} {})", b.source, what, b.qualified_name, b.version);
+    }
+}
+
+/// Prints the binary packages built from source_name, with their installed and candidate versions.
+pub fn print_binaries_of(state: &Packages, source_name: &str) {
+    let mut binaries = match state.get_source_binaries(source_name) {
+        None => { println!("no such source package {}", source_name); return }
+        Some(b) => b.to_vec()
+    };
+    binaries.sort_by_key(|b| name(state, *b));
+    println!("{:?} builds:", source_name);
+    for b in binaries {
+        let mut states : Vec<String> = state.get_installed_instances(b).iter().filter(|i| i.status.is_installed())
+            .map(|i| match i.arch.as_deref() {
+                Some(a) if !state.is_native_arch(Some(a)) => format!("installed {} ({})", i.version.as_deref().unwrap_or(""), a),
+                _ => format!("installed {}", i.version.as_deref().unwrap_or(""))
+            }).collect();
+        if let Some(v) = state.get_candidate_debver(b) {
+            states.push(format!("candidate {}", v));
+        }
+        if states.is_empty() {
+            states.push(String::from("not available"));
+        }
+        println!("- {}: {}", name(state, b), states.join(", "));
+    }
+}
+
+pub fn print_version_skew(state: &Packages) {
+    let skew = state.version_skew();
+    for s in &skew {
+        println!("Source {:?} has installed binaries from different versions:", s.source);
+        for (version, binaries) in &s.versions {
+            println!("- {}: {}", version, binaries.join(", "));
+        }
+    }
+    println!("Source packages with version skew: {}", skew.len());
+}
+
+/// Prints the packages to install to build source_name on the native architecture with the given build profiles
+/// active, like apt-get build-dep, and the Build-Conflicts that are in the way.
+pub fn print_build_dep(state: &Packages, source_name: &str, profiles: &[String]) {
+    let source : &SourceVersion = match state.get_source_candidate(source_name) {
+        None => { println!("no such source package {}", source_name); return }
+        Some(s) => s
+    };
+    let arch = match state.native_arch() {
+        None => { println!("the native architecture is unknown; load a status or Packages file first"); return }
+        Some(a) => a
+    };
+    println!("Source {} {} (builds {}):", source_name, source.version, source.binaries.join(", "));
+    let BuildPlan { packages, unsatisfiable, build_conflicts, conflicts } = state.plan_build_dep(source, arch, profiles);
+    println!("{:?} build dependencies to install: {:?}", source_name, names(state, &packages));
+    print_planned(state, &packages);
+    for dep in &unsatisfiable {
+        println!("unsatisfiable: {}", dep2str(state, dep));
+    }
+    if !build_conflicts.is_empty() {
+        println!("Build-Conflicts:");
+    }
+    for c in &build_conflicts {
+        let what = if c.target_planned { "to install" } else { "installed" };
+        let resolution = match c.resolution {
+            ConflictResolution::Remove(q) => format!("remove {}", name(state, q)),
+            ConflictResolution::Upgrade(q) => format!("upgrade {} to {}", name(state, q), state.get_candidate_debver(q).unwrap()),
+            ConflictResolution::Unresolvable => String::from("it is in the plan; no buildable combination chosen")
+        };
+        println!("- {:?} build-conflicts with {:?}, found {:?} ({} {}): {}", source_name, c.relation,
+                 name(state, c.target), what, c.version, resolution);
+    }
+    print_plan_conflicts(state, &conflicts);
+}
+
+/// Prints every problem validate_index finds in filename, then how many there were.
+pub fn print_validation(state: &Packages, filename: &str) {
+    match state.validate_index(filename) {
+        Err(e) => println!("error: {}", e),
+        Ok(problems) if problems.is_empty() => println!("{}: no problems found", filename),
+        Ok(problems) => {
+            for p in &problems {
+                println!("{}", p);
+            }
+            println!("{}: {} problems", filename, problems.len());
+        }
+    }
+}
+
+/// Says what a saved snapshot holds.
+pub fn print_save_snapshot(state: &Packages, filename: &str, result: Result<(), SnapshotError>) {
+    match result {
+        Err(e) => println!("error: {}: {}", filename, e),
+        Ok(()) => println!("{}: saved {} packages from {} files", filename, state.package_count(), state.loaded_files().len())
+    }
+}
+
+/// Says what a loaded snapshot held.
+pub fn print_load_snapshot(state: &Packages, filename: &str, result: Result<(), SnapshotError>) {
+    match result {
+        Err(e) => println!("error: {}: {}", filename, e),
+        Ok(()) => {
+            println!("{}: loaded {} packages from {} files", filename, state.package_count(), state.loaded_files().len());
+            println!("Packages available: {}", state.available_count());
+            println!("Packages installed: {}", state.installed_count());
+        }
+    }
+}
+
+pub fn print_verifications(verifications: &[Verification]) {
+    for v in verifications {
+        match v {
+            Verification::Checked { package, matches } => println!("verifying {}, matches: {:?}", package, matches),
+            Verification::Failed { package, version, response_code } =>
+                println!("got error {} on request for package {} version {}", response_code, package, version)
+        }
+    }
+}
+
+/// Prints the best time of each parser, then whether they all built the same thing.
+pub fn print_bench(report: &BenchReport) {
+    for (parser, best) in &report.timings {
+        println!("{}: {:.3} s", parser, best.as_secs_f64());
+    }
+    println!("threads: {}", report.threads);
+    let first = report.timings[0].0;
+    for d in &report.differences {
+        match d {
+            BenchDifference::Length { parser, first_lines, lines } =>
+                println!("results differ: {} lines for {} against {} for {}", first_lines, first, lines, parser),
+            BenchDifference::Line { parser, first_line, line } =>
+                println!("results differ:\n  {}: {}\n  {}: {}", first, first_line, parser, line)
+        }
+    }
+    if report.differences.is_empty() {
+        println!("results are identical");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rpkg::Packages;
+
+    use super::installed_check_json;
+
+    #[test]
+    fn installed_check_json_shape() {
+        let path = std::env::temp_dir().join(format!("rpkg-output-test-{}", std::process::id()));
+        std::fs::write(&path, "Package: app\nStatus: install ok installed\nVersion: 1.0\nDepends: libx (>= 2), missing\n\n\
+                               Package: libx\nStatus: install ok installed\nVersion: 1.5\nConflicts: other\n\n\
+                               Package: other\nStatus: install ok installed\nVersion: 2\n").unwrap();
+        let mut state = Packages::new();
+        let report = state.parse_installed(path.to_str().unwrap());
+        let _ = std::fs::remove_file(&path);
+        assert!(report.unwrap().skipped.is_empty());
+        assert_eq!(installed_check_json(&state, &state.check_installed()), serde_json::json!({
+            "installed" : 3,
+            "broken" : [
+                { "package" : "app", "version" : "1.0", "unmet" : [
+                    { "kind" : "Depends", "relation" : "libx (>= 2)", "installed" : [{ "package" : "libx", "version" : "1.5" }] },
+                    { "kind" : "Depends", "relation" : "missing", "installed" : [] }
+                ] },
+                { "package" : "libx", "version" : "1.5", "unmet" : [
+                    { "kind" : "Conflicts", "relation" : "other", "installed" : [{ "package" : "other", "version" : "2" }] }
+                ] }
+            ]
+        }));
+    }
+}
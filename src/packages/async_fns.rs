@@ -10,26 +10,29 @@ use std::sync::atomic::{AtomicI32, Ordering};
 
 use crate::Packages;
 
-struct Collector(Box<String>);
+struct Collector(String);
 impl Handler for Collector {
     fn write(&mut self, data: &[u8]) -> Result<usize, WriteError> {
-        (*self.0).push_str(str::from_utf8(&data.to_vec()).unwrap());
+        self.0.push_str(&String::from_utf8_lossy(data));
         Ok(data.len())
     }
 }
 
 const DEFAULT_SERVER : &str = "ece459.patricklam.ca:4590";
-impl Drop for Packages {
-    fn drop(&mut self) {
-        self.execute()
-    }
-}
 
 static EASYKEY_COUNTER: AtomicI32 = AtomicI32::new(0);
 
+/// The outcome of one request sent by execute.
+pub enum Verification {
+    /// the server knows the version; matches tells whether its MD5sum is the one loaded for it
+    Checked { package : String, matches : bool },
+    /// the server answered with an error, e.g. 404 for a version it does not know
+    Failed { package : String, version : String, response_code : u32 }
+}
+
 pub struct AsyncState {
     server : String,
-    easy_key_map: HashMap<i32, (String, String, i32)>,
+    easy_key_map: HashMap<i32, (String, String, u32)>,
     urls: Vec<(String, i32)>
 }
 
@@ -49,19 +52,14 @@ impl Packages {
     }
 
     /// Retrieves the version number of pkg and calls enq_verify_with_version with that version number.
-    pub fn enq_verify(&mut self, pkg:&str) {
-        let version = self.get_available_debver(pkg);
-        match version {
-            None => { println!("Error: package {} not defined.", pkg); return },
-            Some(v) => { 
-                let vs = &v.to_string();
-                self.enq_verify_with_version(pkg, vs); 
-            }
-        };
+    /// Returns the URL of the request, or None if pkg has no candidate version.
+    pub fn enq_verify(&mut self, pkg:&str) -> Option<String> {
+        let version = self.get_available_debver(pkg)?.to_string();
+        Some(self.enq_verify_with_version(pkg, &version))
     }
 
-    /// Enqueues a request for the provided version/package information. Stores any needed state to async_state so that execute() can handle the results and print out needed output.
-    pub fn enq_verify_with_version(&mut self, pkg:&str, version:&str) {
+    /// Enqueues a request for the provided version/package information, returning its URL. Stores any needed state to async_state so that execute() can handle the results.
+    pub fn enq_verify_with_version(&mut self, pkg:&str, version:&str) -> String {
         // URL encode the version
         let encoded_version = encode(version);
 
@@ -74,18 +72,17 @@ impl Packages {
         // Increment the Easy2Handle counter
         let easy_key = EASYKEY_COUNTER.fetch_add(1, Ordering::SeqCst);
 
-        // Print the queued request
-        println!("queueing request {}", url);
-
         let pkg_number = self.get_package_num_inserting(pkg);
 
         // Store the Easy2Handle key and store urls
         self.async_state.easy_key_map.insert(easy_key, (pkg.to_string(), version.to_string(), pkg_number));
-        self.async_state.urls.push((url, easy_key));
+        self.async_state.urls.push((url.clone(), easy_key));
+        url
     }
 
-    /// Asks curl to perform all enqueued requests. For requests that succeed with response code 200, compares received MD5sum with local MD5sum (perhaps stored earlier). For requests that fail with 400+, prints error message.
-    pub fn execute(&mut self) {
+    /// Asks curl to perform all enqueued requests. For requests that succeed with response code 200, compares received MD5sum with local MD5sum (perhaps stored earlier); those without a local MD5sum are left out. Requests that fail with 400+ are returned as failures.
+    pub fn execute(&mut self) -> Vec<Verification> {
+        let mut verifications = vec![];
         let mut easys: Vec<(Easy2Handle<Collector>, i32)> = Vec::new();
         let mut multi: Multi = Multi::new();
         let urls = self.async_state.urls.clone();
@@ -103,36 +100,33 @@ impl Packages {
 
         // Iterate through all Easy2Handlesr
         for easy in easys.drain(..) {
-            let mut handler_after: Easy2<Collector> = multi.remove2(easy.0).unwrap();
+            let handler_after: Easy2<Collector> = multi.remove2(easy.0).unwrap();
             let response_code = handler_after.response_code().unwrap();
             let easy_key = easy.1;
             let (pkg_name, version, pkg_number) = self.async_state.easy_key_map.get(&easy_key).unwrap();
         
             if response_code == 200 {
-                // Check the received MD5sum with the local MD5sum
-                match self.md5sums.get(&pkg_number) {
-                    Some(md5_local) => {
-                        let md5_api = handler_after.get_ref().0.as_ref().clone();
-                        let match_md5 = &md5_api == md5_local;
-                        println!("verifying {}, matches: {:?}", pkg_name, match_md5);
-                    } None => {}
+                // Check the received MD5sum with the local MD5sum of the requested version (or of the candidate, if that version isn't known)
+                let available = self.get_available_version(*pkg_number, version).or_else(|| self.get_candidate(*pkg_number));
+                if let Some(md5_local) = available.and_then(|v| v.md5sum.as_ref()) {
+                    let md5_api = &handler_after.get_ref().0;
+                    let match_md5 = md5_api == md5_local.as_str();
+                    verifications.push(Verification::Checked { package : pkg_name.clone(), matches : match_md5 });
                 }
 
             } else {
-                // Print error message for response codes 400+
-                println!(
-                    "got error {} on request for package {} version {}",
-                    response_code, pkg_name, version
-                );
+                // Report response codes 400+
+                verifications.push(Verification::Failed { package : pkg_name.clone(), version : version.clone(), response_code });
             }
         }
         // Set urls, multi, easys, map back to empty
         self.async_state.easy_key_map = HashMap::new();
         self.async_state.urls = Vec::new();
+        verifications
     }
 
     fn init(&mut self, multi:&Multi, url: String) -> Result<Easy2Handle<Collector>, Error> {
-        let mut easy = Easy2::new(Collector(Box::new(String::new())));
+        let mut easy = Easy2::new(Collector(String::new()));
         easy.url(&url)?;
         easy.verbose(false)?;
         Ok(multi.add2(easy).unwrap())
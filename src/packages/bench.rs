@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+
+use crate::Packages;
+use crate::packages::RelationKind;
+use crate::packages::errors::{LoadError, LoadReport};
+
+// bench-parse times the sequential, parallel and memory-mapped Packages parsers on the same file, each into an empty
+// Packages, and checks that they built the same thing.
+
+/// What bench_parse measured: the best time of each parser, in order, and how the results of the others differ from
+/// that of the first.
+pub struct BenchReport {
+    pub timings : Vec<(&'static str, Duration)>,
+    pub threads : usize,
+    pub differences : Vec<BenchDifference>
+}
+
+/// The first difference between what the first parser and another one built, as lines of their digests.
+pub enum BenchDifference {
+    Length { parser : &'static str, first_lines : usize, lines : usize },
+    Line { parser : &'static str, first_line : String, line : String }
+}
+
+impl Packages {
+    // everything parse_packages records, package by package in order of number, with package numbers replaced by names
+    // so that two Packages can be compared line by line and differences are readable
+    pub(super) fn parse_digest(&self) -> Vec<String> {
+        let mut digest : Vec<String> = self.interner.names().map(String::from).collect();
+        digest.push(format!("native {:?}", self.native_arch));
+        for (num, name) in self.interner.names().enumerate() {
+            let num = num as u32;
+            for v in self.get_available_versions(num) {
+                digest.push(format!("{} {} {:?} {:?} {} from {:?}", name, v.version, v.architecture, v.md5sum, v.multi_arch, v.origins));
+                for kind in RelationKind::ALL {
+                    digest.push(format!("  {}: {}", kind, v.get_relations(kind).iter().map(|dep| dep.iter().map(|r| self.relation_text(r)).format(" | ")).format(", ")));
+                }
+                let provides = v.provides.iter().map(|p| match &p.version {
+                    None => self.shown_name(p.package_num).to_string(),
+                    Some(version) => format!("{} (= {})", self.shown_name(p.package_num), version)
+                });
+                digest.push(format!("  provides {}", provides.format(", ")));
+                digest.extend(v.paragraph.paragraph().fields().iter().map(|f| format!("  {} {}: {}", f.line, f.name, f.value)));
+            }
+            if let Some(providers) = self.available_providers.get(num).filter(|p| !p.is_empty()) {
+                digest.push(format!("{} provided by {}", name, providers.iter().map(|p| self.shown_name(*p)).format(", ")));
+            }
+        }
+        for c in &self.integrity_conflicts {
+            digest.push(format!("conflict {} {} {:?} {:?}", self.shown_name(c.package_num), c.version, c.first, c.second));
+        }
+        for (source, binaries) in self.source_binaries.iter().sorted_by_key(|(source, _)| *source) {
+            digest.push(format!("source {}: {}", source, binaries.iter().map(|b| self.shown_name(*b)).format(", ")));
+        }
+        digest
+    }
+
+    /// Parses filename runs times with each of parse_packages_sequential, parse_packages_parallel and
+    /// parse_packages_mapped, each time into an empty Packages, and reports the best time of each and how the results
+    /// of the others differ from that of the first, if they do.
+    pub fn bench_parse(&self, filename: &str, runs: usize) -> Result<BenchReport, LoadError> {
+        type Parser = fn(&mut Packages, &str, Option<&str>) -> Result<LoadReport, LoadError>;
+        let parsers : [(&'static str, Parser); 3] = [
+            ("sequential", Packages::parse_packages_sequential),
+            ("parallel", Packages::parse_packages_parallel),
+            ("mapped", Packages::parse_packages_mapped)
+        ];
+        let mut timings = vec![];
+        let mut digests : Vec<Vec<String>> = vec![];
+        for (label, parse) in parsers {
+            let mut best = Duration::MAX;
+            let mut digest = vec![];
+            for _ in 0..runs {
+                let mut packages = Packages::new();
+                packages.set_load_mode(self.load_mode);
+                let start = Instant::now();
+                parse(&mut packages, filename, None)?;
+                best = best.min(start.elapsed());
+                digest = packages.parse_digest();
+            }
+            timings.push((label, best));
+            digests.push(digest);
+        }
+        let first = &digests[0];
+        let mut differences = vec![];
+        for (i, digest) in digests.iter().enumerate().skip(1) {
+            let parser = timings[i].0;
+            match first.iter().zip(digest).position(|(a, b)| a != b) {
+                None if first.len() == digest.len() => (),
+                None => differences.push(BenchDifference::Length { parser, first_lines : first.len(), lines : digest.len() }),
+                Some(l) => differences.push(BenchDifference::Line { parser, first_line : first[l].clone(), line : digest[l].clone() })
+            }
+        }
+        Ok(BenchReport { timings, threads : rayon::current_num_threads(), differences })
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+
+use crate::debversion::DebianVersionNum;
+
+use crate::Packages;
+use crate::packages::{RelVersionedPackageNum, RelationKind};
+
+/// What has to happen to the system (or the plan) for a conflict to go away.
+pub enum ConflictResolution {
+    /// remove this installed package
+    Remove(u32),
+    /// upgrade this installed package to its available version, which is no longer in conflict
+    Upgrade(u32),
+    /// both packages are part of the plan, so the plan itself needs to pick different alternatives
+    Unresolvable
+}
+
+/// A Conflicts or Breaks relation that is violated once a plan is installed on top of the installed packages.
+pub struct PlanConflict {
+    pub declared_by : u32,
+    pub declared_by_planned : bool,
+    pub kind : RelationKind,
+    pub relation : String,
+    pub target : u32,
+    pub target_planned : bool,
+    // the declaring package also Replaces the target, so removing the target is the expected outcome
+    pub replaces : bool,
+    pub resolution : ConflictResolution
+}
+
+/// What installing a package takes, as planned by plan_install.
+pub struct InstallPlan {
+    /// the packages to install for the relations of the package, in no particular order
+    pub dependencies : Vec<u32>,
+    /// the package itself, then its dependencies
+    pub packages : Vec<u32>,
+    pub conflicts : Vec<PlanConflict>
+}
+
+impl Packages {
+    /// Version package_num would have after installing plan: the available version if it's in the plan,
+    /// otherwise the installed version (if any).
+    pub(super) fn version_after_plan(&self, package_num: u32, plan: &HashSet<u32>) -> Option<&DebianVersionNum> {
+        if plan.contains(&package_num) {
+            self.get_candidate_debver(package_num)
+        } else {
+            self.installed_debver(package_num)
+        }
+    }
+
+    // whether package_num (planned or installed) Replaces target, or the name the conflict is on (as a package that
+    // conflicts with, replaces and provides a virtual package does)
+    fn declares_replaces(&self, package_num: u32, planned: bool, target: u32, relation: &RelVersionedPackageNum) -> bool {
+        let replaces = if planned {
+            self.get_relations(RelationKind::Replaces, package_num)
+        } else {
+            self.get_installed_relations(RelationKind::Replaces, package_num)
+        };
+        replaces.iter().flatten().any(|r| r.package_num == target || r.package_num == relation.package_num)
+    }
+
+    // whether the candidate of package_num matches relation, by its version or by what it provides
+    fn candidate_matches(&self, package_num: u32, relation: &RelVersionedPackageNum) -> bool {
+        let named = package_num == relation.package_num && self.get_candidate_debver(package_num)
+            .is_some_and(|v| self.rel_version_matches(relation, v) == Some(true));
+        named || self.get_available_providers(relation.package_num).iter()
+            .any(|p| p.package_num == package_num && self.provider_satisfies(p, relation))
+    }
+
+    // the packages of plan other than declared_by that relation matches once they are installed: the package it names,
+    // and the planned packages providing it
+    fn planned_matches(&self, declared_by: u32, relation: &RelVersionedPackageNum, plan: &HashSet<u32>) -> Vec<u32> {
+        let mut matches : Vec<u32> = std::iter::once(relation.package_num)
+            .chain(self.get_available_providers(relation.package_num).iter().map(|p| p.package_num))
+            .filter(|p| *p != declared_by && plan.contains(p) && self.candidate_matches(*p, relation)).collect();
+        matches.sort_unstable();
+        matches.dedup();
+        matches
+    }
+
+    /// Returns true if the available version of package_num declares a Conflicts/Breaks relation of the given
+    /// kind that the planned target matches.
+    fn available_version_conflicts_with(&self, package_num: u32, kind: RelationKind, target: u32) -> bool {
+        self.get_relations(kind, package_num).iter().flatten().any(|r| self.candidate_matches(target, r))
+    }
+
+    /// Checks the Conflicts and Breaks relations of the packages in plan (with their available versions)
+    /// against the installed packages and each other, and those of the installed packages against the plan. A relation
+    /// on a virtual package is checked against its planned and installed providers.
+    pub fn find_plan_conflicts(&self, plan: &[u32]) -> Vec<PlanConflict> {
+        let plan_set: HashSet<u32> = plan.iter().copied().collect();
+        let mut conflicts = vec![];
+
+        // relations declared by the packages we are about to install
+        for &p in plan {
+            for kind in RelationKind::NEGATIVE.iter() {
+                for rel in self.get_relations(*kind, p).iter().flatten() {
+                    let planned = self.planned_matches(p, rel, &plan_set).into_iter().map(|target| (target, true));
+                    let installed = self.installed_matches(p, rel).into_iter()
+                        .filter(|(target, _)| !plan_set.contains(target)).map(|(target, _)| (target, false));
+                    for (target, target_planned) in planned.chain(installed).collect::<Vec<_>>() {
+                        let resolution = if target_planned {
+                            ConflictResolution::Unresolvable
+                        } else if self.get_candidate_debver(target).is_some() && !self.candidate_matches(target, rel) {
+                            ConflictResolution::Upgrade(target)
+                        } else {
+                            ConflictResolution::Remove(target)
+                        };
+                        conflicts.push(PlanConflict {
+                            declared_by : p, declared_by_planned : true, kind : *kind,
+                            relation : self.relation_text(rel), target, target_planned,
+                            replaces : self.declares_replaces(p, true, target, rel), resolution
+                        });
+                    }
+                }
+            }
+        }
+
+        // relations declared by installed packages that stay installed
+        for (q, _) in self.installed_versions().filter(|(q, _)| !plan_set.contains(q)) {
+            for kind in RelationKind::NEGATIVE.iter() {
+                for rel in self.get_installed_relations(*kind, q).iter().flatten() {
+                    for target in self.planned_matches(q, rel, &plan_set) {
+                        let resolution = if self.get_candidate_debver(q).is_some() && !self.available_version_conflicts_with(q, *kind, target) {
+                            ConflictResolution::Upgrade(q)
+                        } else {
+                            ConflictResolution::Remove(q)
+                        };
+                        conflicts.push(PlanConflict {
+                            declared_by : q, declared_by_planned : false, kind : *kind,
+                            relation : self.relation_text(rel), target, target_planned : true,
+                            replaces : false, resolution
+                        });
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Plans the installation of package_name: the packages compute_how_to_install picks for its relations of the
+    /// given kinds, and the conflicts installing them all would run into. None if there is no such package.
+    pub fn plan_install(&self, package_name: &str, kinds: &[RelationKind]) -> Option<InstallPlan> {
+        let package_num = self.package_num(package_name)?;
+        let dependencies = self.compute_how_to_install(package_name, kinds);
+        let mut packages = vec![package_num];
+        packages.extend(dependencies.iter().filter(|p| **p != package_num));
+        let conflicts = self.find_plan_conflicts(&packages);
+        Some(InstallPlan { dependencies, packages, conflicts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packages::testutil::{load, num};
+
+    // each conflict as declarer, relation, target and resolution
+    fn describe(packages: &Packages, conflicts: &[PlanConflict]) -> Vec<String> {
+        let name = |p: u32| packages.get_package_name(p).unwrap();
+        conflicts.iter().map(|c| {
+            let resolution = match c.resolution {
+                ConflictResolution::Remove(q) => format!("remove {}", name(q)),
+                ConflictResolution::Upgrade(q) => format!("upgrade {}", name(q)),
+                ConflictResolution::Unresolvable => String::from("unresolvable")
+            };
+            format!("{} {} {} on {}: {}{}", name(c.declared_by), c.kind.verb(), c.relation, name(c.target), resolution,
+                    if c.replaces { " (replaces)" } else { "" })
+        }).collect()
+    }
+
+    fn plan_conflicts(available: &str, installed: &str, plan: &[&str]) -> Vec<String> {
+        let packages = load(available, installed);
+        let plan : Vec<u32> = plan.iter().map(|p| num(&packages, p)).collect();
+        describe(&packages, &packages.find_plan_conflicts(&plan))
+    }
+
+    // a conflict on a virtual package holds against its providers, installed or planned, but not against the declarer
+    #[test]
+    fn virtual_conflicts_match_providers() {
+        let available = "Package: postfix\nVersion: 3.7\nProvides: mail-transport-agent\nConflicts: mail-transport-agent\n\n\
+                         Package: msmtp-mta\nVersion: 1.8\nProvides: mail-transport-agent\n";
+        let installed = "Package: exim4\nStatus: install ok installed\nVersion: 4.96\nProvides: mail-transport-agent\n\n\
+                         Package: old-mta\nStatus: install ok installed\nVersion: 1\nConflicts: mail-transport-agent\n";
+        assert_eq!(plan_conflicts(available, installed, &["postfix"]), [
+            "postfix conflicts with mail-transport-agent on exim4: remove exim4",
+            "old-mta conflicts with mail-transport-agent on postfix: remove old-mta"
+        ]);
+        assert_eq!(plan_conflicts(available, installed, &["postfix", "msmtp-mta"])[..2], [
+            "postfix conflicts with mail-transport-agent on msmtp-mta: unresolvable",
+            "postfix conflicts with mail-transport-agent on exim4: remove exim4"
+        ]);
+    }
+
+    // the candidates of the installed packages no longer match the versioned relations, on either side
+    #[test]
+    fn versioned_conflicts_resolve_by_upgrades() {
+        let available = "Package: app\nVersion: 2\nBreaks: libold (<< 2)\n\nPackage: libold\nVersion: 2\n\n\
+                         Package: tool\nVersion: 2\n\nPackage: lib\nVersion: 3\n";
+        let installed = "Package: libold\nStatus: install ok installed\nVersion: 1\n\n\
+                         Package: tool\nStatus: install ok installed\nVersion: 1\nConflicts: lib (>= 3)\n";
+        assert_eq!(plan_conflicts(available, installed, &["app", "lib"]), [
+            "app breaks libold (<< 2) on libold: upgrade libold",
+            "tool conflicts with lib (>= 3) on lib: upgrade tool"
+        ]);
+        // not matched at the installed version
+        assert!(plan_conflicts(available, installed.replace("Version: 1\n\n", "Version: 2\n\n").as_str(), &["app"]).is_empty());
+    }
+
+    #[test]
+    fn replaces_flag() {
+        let available = "Package: new\nVersion: 1\nConflicts: old, mta\nReplaces: old, mta\nProvides: mta\n\n\
+                         Package: other\nVersion: 1\nConflicts: old\n";
+        let installed = "Package: old\nStatus: install ok installed\nVersion: 1\nProvides: mta\n";
+        assert_eq!(plan_conflicts(available, installed, &["new", "other"]), [
+            "new conflicts with old on old: remove old (replaces)",
+            "new conflicts with mta on old: remove old (replaces)",
+            "other conflicts with old on old: remove old"
+        ]);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::debversion::DebianVersionNum;
+
+use crate::Packages;
+use crate::packages::{Dependency, RelVersionedPackageNum, RelationKind};
+
+// check_installed is apt-get check for the installed set alone: whatever the indices hold, every Pre-Depends and
+// Depends of an installed package must be satisfied by installed packages (or their Provides), as dep_is_satisfied
+// decides for deps-available, and no Conflicts or Breaks of one may match another installed package (or what it
+// provides). Each installed architecture of a package is checked with its own relations, from its own architecture.
+
+/// A relation of an installed package that the installed set does not meet: a Pre-Depends or Depends that no installed
+/// package satisfies, with the alternatives that are installed, but at a version the relation does not accept; or a
+/// Conflicts or Breaks, with the installed packages it matches.
+pub struct UnmetRelation<'a> {
+    pub kind : RelationKind,
+    pub relation : &'a Dependency,
+    pub wrong_versions : Vec<(u32, &'a DebianVersionNum)>
+}
+
+/// An installed package (one architecture of it) with unmet relations, Pre-Depends first, then Depends, Conflicts and
+/// Breaks.
+pub struct BrokenPackage<'a> {
+    pub package_num : u32,
+    pub arch : Option<&'a str>,
+    pub version : &'a DebianVersionNum,
+    pub unmet : Vec<UnmetRelation<'a>>
+}
+
+impl Packages {
+    /// Checks the relations of every installed package against the installed set; returns the packages with unmet
+    /// ones, by name and architecture.
+    pub fn check_installed(&self) -> Vec<BrokenPackage<'_>> {
+        let instances = self.installed_instances.iter()
+            .flat_map(|(package_num, instances)| instances.iter().filter(|i| i.status.is_installed()).map(move |i| (package_num, i)));
+        let mut broken : Vec<BrokenPackage> = instances.filter_map(|(package_num, instance)| {
+            let (arch, version) = (instance.arch.as_deref(), instance.debver.as_ref()?);
+            let relations = |kinds: &'static [RelationKind]| kinds.iter()
+                .flat_map(move |kind| instance.get_relations(*kind).iter().map(move |relation| (*kind, relation)));
+            let unmet_depends = relations(&RelationKind::REQUIRED)
+                .filter_map(|(kind, relation)| self.dep_satisfied_by_wrong_version(relation, arch)
+                    .map(|wrong_versions| UnmetRelation { kind, relation, wrong_versions }));
+            let conflicts = relations(&RelationKind::NEGATIVE)
+                .map(|(kind, relation)| UnmetRelation { kind, relation, wrong_versions : relation.iter()
+                    .flat_map(|r| self.installed_matches(package_num, r)).collect() })
+                .filter(|u| !u.wrong_versions.is_empty());
+            let unmet : Vec<UnmetRelation> = unmet_depends.chain(conflicts).collect();
+            if unmet.is_empty() { None } else { Some(BrokenPackage { package_num, arch, version, unmet }) }
+        }).collect();
+        broken.sort_by_key(|b| (self.interner.get(b.package_num), b.arch));
+        broken
+    }
+
+    // the installed packages other than declared_by that relation, a Conflicts or Breaks of declared_by, matches: the
+    // package it names at a version it matches, and the installed packages providing it (following provider_satisfies)
+    pub(super) fn installed_matches(&self, declared_by: u32, relation: &RelVersionedPackageNum) -> Vec<(u32, &DebianVersionNum)> {
+        let named = self.installed_debver(relation.package_num)
+            .filter(|v| self.rel_version_matches(relation, v) == Some(true))
+            .map(|v| (relation.package_num, v));
+        let providers = self.get_installed_providers(relation.package_num).iter()
+            .filter(|p| self.provider_satisfies(p, relation))
+            .filter_map(|p| self.installed_debver(p.package_num).map(|v| (p.package_num, v)));
+        let mut matches : Vec<(u32, &DebianVersionNum)> = named.into_iter().chain(providers).filter(|(p, _)| *p != declared_by).collect();
+        matches.dedup_by_key(|(p, _)| *p);
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packages::RelationKind;
+    use crate::packages::testutil::{load, num};
+
+    const STATUS : &str = "Package: app\nStatus: install ok installed\nVersion: 1.0\n\
+                           Pre-Depends: libc6 (>= 2.36)\nDepends: libx (>= 2.0) | libx-compat, missing, mail-transport-agent\n\n\
+                           Package: libx\nStatus: install ok installed\nVersion: 1.5\n\n\
+                           Package: libc6\nStatus: install ok installed\nVersion: 2.36-9\n\n\
+                           Package: postfix\nStatus: install ok installed\nVersion: 3.7\n\
+                           Provides: mail-transport-agent\nConflicts: mail-transport-agent, exim4\n\n\
+                           Package: exim4\nStatus: install ok installed\nVersion: 4.96\nProvides: mail-transport-agent\n\
+                           Conflicts: mail-transport-agent\nBreaks: libx (<< 2)\n\n\
+                           Package: gone\nStatus: deinstall ok config-files\nVersion: 1.0\nDepends: nothing\n";
+
+    // an unmet relation as (kind, relation, installed packages)
+    type Unmet = (RelationKind, String, Vec<String>);
+
+    // each broken package with its unmet relations
+    fn report(status: &str) -> Vec<(String, Vec<Unmet>)> {
+        let packages = load("", status);
+        packages.check_installed().iter().map(|b| (packages.get_package_name(b.package_num).unwrap().to_string(),
+            b.unmet.iter().map(|u| (u.kind, packages.dep2str(u.relation).unwrap(),
+                u.wrong_versions.iter().map(|(p, v)| format!("{} {}", packages.get_package_name(*p).unwrap(), v)).collect())).collect()
+        )).collect()
+    }
+
+    #[test]
+    fn unmet_depends() {
+        let report = report(STATUS);
+        assert_eq!(report[0], (String::from("app"), vec![
+            (RelationKind::Depends, String::from("libx (>= 2.0) | libx-compat"), vec![String::from("libx 1.5")]),
+            (RelationKind::Depends, String::from("missing"), vec![])
+        ]));
+        // not installed, so not checked
+        assert!(report.iter().all(|(name, _)| name != "gone"));
+    }
+
+    // postfix and exim4 both provide and conflict with mail-transport-agent, which only rules out the other one
+    #[test]
+    fn conflicting_pair() {
+        let report = report(STATUS);
+        assert_eq!(report.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["app", "exim4", "postfix"]);
+        assert_eq!(report[1].1, [
+            (RelationKind::Conflicts, String::from("mail-transport-agent"), vec![String::from("postfix 3.7")]),
+            (RelationKind::Breaks, String::from("libx (<< 2)"), vec![String::from("libx 1.5")])
+        ]);
+        assert_eq!(report[2].1, [
+            (RelationKind::Conflicts, String::from("mail-transport-agent"), vec![String::from("exim4 4.96")]),
+            (RelationKind::Conflicts, String::from("exim4"), vec![String::from("exim4 4.96")])
+        ]);
+    }
+
+    #[test]
+    fn consistent_system() {
+        let packages = load("", "Package: a\nStatus: install ok installed\nVersion: 1\nDepends: b (>= 1)\nConflicts: c\n\n\
+                                 Package: b\nStatus: install ok installed\nVersion: 1\nBreaks: a (<< 1)\n");
+        assert!(packages.check_installed().is_empty());
+        assert!(packages.installed_debver(num(&packages, "c")).is_none());
+    }
+}
@@ -1,121 +1,82 @@
-use rpkg::debversion::{self, DebianVersionNum};
+use crate::debversion::DebianVersionNum;
 use crate::Packages;
-use crate::packages::Dependency;
+use crate::packages::{Dependency, RelVersionedPackageNum};
 
-impl Packages {
-    /// Gets the dependencies of package_name, and prints out whether they are satisfied (and by which library/version) or not.
-    pub fn deps_available(&mut self, package_name: &str) {
-        if !self.package_exists(package_name) {
-            println!("no such package {}", package_name);
-            return;
-        }
-        println!("Package {}:", package_name);
+/// How the installed packages satisfy a dependency: which alternative, by which installed package (the package of the
+/// alternative itself, or an installed provider of it) and at what version.
+pub struct Satisfier<'a> {
+    pub alternative : &'a RelVersionedPackageNum,
+    pub package_num : u32,
+    pub version : &'a DebianVersionNum
+}
 
-        let package_num = self.get_package_num_inserting(&package_name);
-        match self.dependencies.get(&package_num) {
-            Some(dependencies) => {
-                for dependency in dependencies {
-                    println!("- dependency {:?}", self.dep2str(dependency));
-                    match self.dep_is_satisfied(dependency) {
-                        Some(string) => {
-                            println!("{}", string);
-                        }
-                        None => {
-                            println!("-> not satisfied");
-                        }
-                    }
-                }
-            }
-            None => println!("There are no associated dependencies.")
-        }
+impl Satisfier<'_> {
+    /// Is the dependency satisfied by a provider of the package it names rather than by that package itself?
+    pub fn is_provider(&self) -> bool {
+        self.package_num != self.alternative.package_num
     }
+}
 
-    /// Returns Some(package) which satisfies dependency dd, or None if not satisfied.
-    pub fn dep_is_satisfied(&self, dd:&Dependency) -> Option<String> {
-        for package in dd {
-            match &package.rel_version {
-                Some((op, version_string)) => {
-                    let v = version_string.parse::<debversion::DebianVersionNum>().unwrap();
-                    match self.installed_debvers.get(&package.package_num) {
-                        Some(iv) => {
-                            if debversion::cmp_debversion_with_op(&op, &iv, &v) {
-                                let package_name = self.get_package_name(package.package_num);
-                                return Some(format!("+ {} satisfied by installed version {}", package_name, iv));
-                            } else {
-                                return None
-                            }
-                        }
-                        None => ()
-                    }
-                }
-                None => ()
-            }
-            match self.installed_debvers.get(&package.package_num) {
-                Some(iv) => {
-                    let package_name = self.get_package_name(package.package_num);
-                    return Some(format!("+ {} satisfied by installed version {}", package_name, iv));
-                }
-                None => ()
-            }
-
-        }
-        return None;
+impl Packages {
+    /// Returns how the first satisfied alternative of dependency dd, declared by a package of architecture from_arch
+    /// (None for the native architecture), is satisfied, or None if not satisfied. An alternative is satisfied by the
+    /// installed package itself or, for a virtual package, by an installed provider.
+    pub fn dep_is_satisfied<'a>(&'a self, dd: &'a Dependency, from_arch: Option<&'a str>) -> Option<Satisfier<'a>> {
+        dd.iter().find_map(|alternative| self.installed_satisfier(alternative, from_arch)
+            .map(|(package_num, version)| Satisfier { alternative, package_num, version }))
     }
 
-    /// Returns Some(package<i32>) which satisfies dependency dd, or None if not satisfied.
-    pub fn dep_is_satisfied_2(&self, dd:&Dependency) -> Option<i32> {
-        for package in dd {
-            match &package.rel_version {
-                Some((op, version_string)) => {
-                    let v = version_string.parse::<debversion::DebianVersionNum>().unwrap();
-                    match self.installed_debvers.get(&package.package_num) {
-                        Some(iv) => {
-                            if debversion::cmp_debversion_with_op(&op, &iv, &v) {
-                                return Some(package.package_num);
-                            } else {
-                                return None
-                            }
-                        }
-                        None => ()
-                    }
-                }
-                None => ()
-            }
-            match self.installed_debvers.get(&package.package_num) {
-                Some(iv) => {
-                    return Some(package.package_num);
-                }
-                None => ()
-            }
-
-        }
-        return None;
+    /// Returns Some(package<u32>) which satisfies dependency dd (declared by a package of architecture from_arch), or
+    /// None if not satisfied.
+    pub fn dep_is_satisfied_2(&self, dd:&Dependency, from_arch: Option<&str>) -> Option<u32> {
+        dd.iter().find_map(|package| self.installed_satisfier(package, from_arch)).map(|(satisfier, _)| satisfier)
     }
 
-    /// Returns a Vec of packages which would satisfy dependency dd but for the version.
-    /// Used by the how-to-install command, which calls compute_how_to_install().
-    pub fn dep_satisfied_by_wrong_version(&self, dd:&Dependency) -> Vec<(i32,&DebianVersionNum)> {
-        assert! (self.dep_is_satisfied(dd).is_none());
+    /// Returns a Vec of packages which would satisfy dependency dd (declared by a package of architecture from_arch)
+    /// but for the version, or None if dd is satisfied. Used by the how-to-install command, which calls
+    /// compute_how_to_install().
+    pub fn dep_satisfied_by_wrong_version<'a>(&'a self, dd: &'a Dependency, from_arch: Option<&'a str>) -> Option<Vec<(u32, &'a DebianVersionNum)>> {
+        if self.dep_is_satisfied(dd, from_arch).is_some() {
+            return None;
+        }
         let mut result = vec![];
-        // another loop on dd
+        // another loop on dd, over the installed architectures that would do
 
         for package in dd {
-            match &package.rel_version {
-                Some((op, version_string)) => {
-                    let v = version_string.parse::<debversion::DebianVersionNum>().unwrap();
-                    match self.installed_debvers.get(&package.package_num) {
-                        Some(iv) => {
-                            if !debversion::cmp_debversion_with_op(&op, &iv, &v) {
-                                result.push((package.package_num, iv));
-                            }
-                        }
-                        None => ()
-                    }
-                }
-                None => ()
+            let wrong = self.installed_arch_satisfiers(package, from_arch, package.package_num).filter_map(|i| i.debver.as_ref())
+                .find(|iv| self.rel_version_matches(package, iv) == Some(false));
+            if let Some(iv) = wrong {
+                result.push((package.package_num, iv));
             }
         }
-        return result;
+        Some(result)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::debversion::VersionRelation;
+    use crate::packages::{RelVersionedPackageNum, RelationKind, Text};
+    use crate::packages::testutil::{load, num};
+
+    const STATUS : &str = "Package: a\nStatus: install ok installed\nVersion: 1\nDepends: b (>= 2), c | b (>= 1)\n\n\
+                           Package: b\nStatus: install ok installed\nVersion: 1.5\n";
+
+    #[test]
+    fn wrong_versions_only_for_unsatisfied_dependencies() {
+        let packages = load("", STATUS);
+        let depends = packages.get_installed_relations(RelationKind::Depends, num(&packages, "a"));
+        let wrong = packages.dep_satisfied_by_wrong_version(&depends[0], None).unwrap();
+        assert_eq!(wrong.iter().map(|(p, v)| (*p, v.to_string())).collect::<Vec<_>>(), [(num(&packages, "b"), String::from("1.5"))]);
+        assert!(packages.dep_satisfied_by_wrong_version(&depends[1], None).is_none());
+    }
+
+    #[test]
+    fn unparsable_relation_version_matches_nothing() {
+        let packages = load("", STATUS);
+        let b = num(&packages, "b");
+        let relation = RelVersionedPackageNum { package_num : b, rel_version : Some((VersionRelation::Equal, Text::from("1 .5"))), arch : None };
+        assert_eq!(packages.rel_version_matches(&relation, packages.installed_debver(b).unwrap()), None);
+        assert!(packages.dep_is_satisfied(&vec![relation], None).is_none());
+    }
+}
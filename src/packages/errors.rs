@@ -0,0 +1,192 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use crate::deb822::{MalformedLine, Paragraph, ParagraphRef};
+
+/// How loaders treat a malformed stanza (or CSV record): skip it and go on with the rest of the file, or stop at the
+/// first one. Either way, an error about the whole file (it cannot be read, or does not match its Release file) stops
+/// loading it. Stopping does not undo anything: the stanzas before the malformed one stay loaded, along with the names
+/// they mention and the file itself (so a snapshot saved afterwards has them too).
+#[derive(Clone,Copy,PartialEq,Eq,Debug,Default)]
+pub enum LoadMode {
+    #[default]
+    Lenient,
+    Strict
+}
+
+impl FromStr for LoadMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lenient" => Ok(LoadMode::Lenient),
+            "strict" => Ok(LoadMode::Strict),
+            _ => Err(format!("unknown load mode {:?}: expected lenient or strict", s))
+        }
+    }
+}
+
+impl fmt::Display for LoadMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadMode::Lenient => write!(f, "lenient"),
+            LoadMode::Strict => write!(f, "strict")
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadErrorKind {
+    // the file cannot be opened or read
+    Io(io::Error),
+    // the Release file cannot be used, or the file does not match its entry there
+    Release(String),
+    // a stanza or record is malformed
+    Malformed(String)
+}
+
+/// An error while loading a file, with where it happened: "Packages: line 12 (package foo): bad version".
+#[derive(Debug)]
+pub struct LoadError {
+    pub filename : String,
+    // first line of the stanza or record; None for errors about the whole file
+    pub line : Option<usize>,
+    // the package the stanza describes, if known
+    pub package : Option<String>,
+    pub kind : LoadErrorKind
+}
+
+impl LoadError {
+    pub fn io(filename: &str, e: io::Error) -> LoadError {
+        LoadError { filename : filename.to_string(), line : None, package : None, kind : LoadErrorKind::Io(e) }
+    }
+
+    pub fn release(filename: &str, message: String) -> LoadError {
+        LoadError { filename : filename.to_string(), line : None, package : None, kind : LoadErrorKind::Release(message) }
+    }
+
+    pub fn malformed(filename: &str, line: usize, package: Option<&str>, message: String) -> LoadError {
+        LoadError {
+            filename : filename.to_string(), line : Some(line), package : package.map(String::from),
+            kind : LoadErrorKind::Malformed(message)
+        }
+    }
+
+    /// An error from reading the paragraphs of filename: a malformed line, or else a failure to read the file.
+    pub fn from_read(filename: &str, e: io::Error) -> LoadError {
+        match MalformedLine::of(&e) {
+            Some(m) => LoadError::malformed(filename, m.line, None, m.message.clone()),
+            None => LoadError::io(filename, e)
+        }
+    }
+
+    /// Errors that are not about a single stanza stop loading the file even in lenient mode.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self.kind, LoadErrorKind::Malformed(_))
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.filename)?;
+        if let Some(line) = self.line {
+            write!(f, ": line {}", line)?;
+        }
+        if let Some(package) = &self.package {
+            write!(f, " (package {})", package)?;
+        }
+        match &self.kind {
+            LoadErrorKind::Io(e) => write!(f, ": {}", e),
+            LoadErrorKind::Release(message) | LoadErrorKind::Malformed(message) => write!(f, ": {}", message)
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            LoadErrorKind::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+/// What a loader did with a file: how many stanzas (or records) it loaded, which ones it skipped and why, and what it
+/// loaded anyway but is worth knowing about (a bad Multi-Arch field, an index its Release file does not list, ...).
+#[derive(Debug)]
+pub struct LoadReport {
+    pub filename : String,
+    pub loaded : usize,
+    pub skipped : Vec<LoadError>,
+    pub warnings : Vec<String>
+}
+
+impl LoadReport {
+    pub fn new(filename: &str) -> LoadReport {
+        LoadReport { filename : filename.to_string(), loaded : 0, skipped : vec![], warnings : vec![] }
+    }
+
+    /// Records a stanza that could not be loaded: in lenient mode it is skipped, in strict mode (or if the error is
+    /// about the whole file) the error is returned to stop loading.
+    pub fn skip(&mut self, mode: LoadMode, error: LoadError) -> Result<(), LoadError> {
+        if mode == LoadMode::Strict || error.is_fatal() {
+            return Err(error);
+        }
+        self.skipped.push(error);
+        Ok(())
+    }
+
+    /// Passes each paragraph (or stanza holding one) to load_stanza, with the warnings to add to, counting the ones it
+    /// loads; those that are malformed, or that it rejects with a message, are skipped (or stop loading, in strict mode).
+    pub fn load_paragraphs<T: Stanza>(&mut self, mode: LoadMode, paragraphs: impl IntoIterator<Item = io::Result<T>>,
+                                                 mut load_stanza: impl FnMut(T, &mut Vec<String>) -> Result<(), String>) -> Result<(), LoadError> {
+        for para in paragraphs {
+            let para = match para {
+                Ok(p) => p,
+                Err(e) => {
+                    let error = LoadError::from_read(&self.filename, e);
+                    self.skip(mode, error)?;
+                    continue;
+                }
+            };
+            let (line, package) = (para.line(), para.package());
+            match load_stanza(para, &mut self.warnings) {
+                Ok(()) => self.loaded += 1,
+                Err(message) => {
+                    let error = LoadError::malformed(&self.filename, line, package.as_deref(), message);
+                    self.skip(mode, error)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What load_paragraphs needs to know about a stanza to report it.
+pub trait Stanza {
+    fn line(&self) -> usize;
+    fn package(&self) -> Option<String>;
+}
+
+impl Stanza for Paragraph {
+    fn line(&self) -> usize {
+        Paragraph::line(self)
+    }
+
+    fn package(&self) -> Option<String> {
+        self.get("Package").map(String::from)
+    }
+}
+
+impl Stanza for ParagraphRef<'_> {
+    fn line(&self) -> usize {
+        ParagraphRef::line(self)
+    }
+
+    fn package(&self) -> Option<String> {
+        self.get("Package").map(Cow::into_owned)
+    }
+}
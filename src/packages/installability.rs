@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use rayon::prelude::*;
+
+use crate::debversion::DebianVersionNum;
+
+use crate::Packages;
+use crate::packages::RelationKind;
+use crate::packages::planner::{Encoder, Reason};
+
+// check_installable decides, like edos-debcheck and dose-debcheck, whether each available version (of the native
+// architecture) belongs to some set of available versions where every Pre-Depends and Depends is satisfied and no
+// Conflicts or Breaks holds, regardless of what is installed and of pins. The clauses of the whole repository are
+// built once, with the planner's encoder, and each version is solved within its closure: the versions it could need
+// through dependencies. Every version of a solution is installable too, so most versions are settled by the solution
+// of another one without a search of their own; versions are checked dependents first, so that the solution for a
+// version settles what it depends on. Versions are checked in parallel.
+
+/// A version that no installable set contains, with the constraints that rule it out, from its own dependencies on.
+pub struct Uninstallable<'a> {
+    pub package_num : u32,
+    pub version : &'a DebianVersionNum,
+    pub reasons : Vec<Reason<'a>>
+}
+
+/// What check_installable found: how many versions it checked, how many it had to search for (the others were part of
+/// a solution found for another version), and the versions that cannot be installed, by name and version.
+pub struct InstallabilityReport<'a> {
+    pub checked : usize,
+    pub searches : usize,
+    pub uninstallable : Vec<Uninstallable<'a>>
+}
+
+impl Packages {
+    /// Checks whether each available version of the native architecture can be installed with the available versions
+    /// alone; see InstallabilityReport.
+    pub fn check_installable(&self) -> InstallabilityReport<'_> {
+        let mut repository = Encoder::new(self, &RelationKind::REQUIRED, false);
+        let mut queue = VecDeque::new();
+        for package_num in 0..self.package_count() as u32 {
+            repository.add_package(package_num, &mut queue);
+        }
+        repository.add_relations();
+        let installable : Vec<AtomicBool> = repository.choices.iter().map(|_| AtomicBool::new(false)).collect();
+        let searches = AtomicUsize::new(0);
+        let mut uninstallable : Vec<Uninstallable> = repository.dependents_first().into_par_iter().filter_map(|var| {
+            if installable[var].load(Ordering::Relaxed) { return None }
+            searches.fetch_add(1, Ordering::Relaxed);
+            let (problem, vars) = repository.restrict(var);
+            match problem.search() {
+                Ok(model) => {
+                    for (_, global) in vars.iter().enumerate().filter(|(local, _)| model[*local]) {
+                        installable[*global].store(true, Ordering::Relaxed);
+                    }
+                    None
+                }
+                Err(core) => {
+                    let choice = repository.choices[var];
+                    let reasons = core.into_iter().map(|c| problem.reasons[c].clone()).collect();
+                    Some(Uninstallable { package_num : choice.package_num, version : choice.version, reasons })
+                }
+            }
+        }).collect();
+        uninstallable.sort_by(|a, b| (self.interner.get(a.package_num), a.version).cmp(&(self.interner.get(b.package_num), b.version)));
+        InstallabilityReport { checked : repository.choices.len(), searches : searches.into_inner(), uninstallable }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packages::testutil::{load, num};
+
+    // names are numbered as they are first seen, relations first: b, a, c, missing, d
+    const INDEX : &str = "Package: a\nVersion: 1\nDepends: b\n\nPackage: b\nVersion: 1\nDepends: a, c (>= 1)\n\n\
+                          Package: c\nVersion: 1\n\nPackage: d\nVersion: 1\nDepends: b, missing\n";
+
+    // on one thread, versions are checked dependents first: d, which cannot be installed, then one of a and b, whose
+    // solution settles the other one and c
+    #[test]
+    fn one_solution_settles_several_versions() {
+        let packages = load(INDEX, "");
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let report = pool.install(|| packages.check_installable());
+        assert_eq!((report.checked, report.searches), (4, 2));
+        assert_eq!(report.uninstallable.iter().map(|u| u.package_num).collect::<Vec<_>>(), [num(&packages, "d")]);
+        assert!(!report.uninstallable[0].reasons.is_empty());
+    }
+
+    // numbered a, b, c; checked in that order, each would need a search
+    #[test]
+    fn dependents_are_checked_first() {
+        let packages = load("Package: a\nVersion: 1\n\nPackage: b\nVersion: 1\nDepends: a\n\nPackage: c\nVersion: 1\nDepends: b\n", "");
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let report = pool.install(|| packages.check_installable());
+        assert_eq!((report.checked, report.searches), (3, 1));
+        assert!(report.uninstallable.is_empty());
+    }
+
+    #[test]
+    fn parallel_checks_agree() {
+        let packages = load(INDEX, "");
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let report = pool.install(|| packages.check_installable());
+        assert!(report.searches >= 2 && report.searches <= report.checked);
+        assert_eq!(report.uninstallable.iter().map(|u| u.package_num).collect::<Vec<_>>(), [num(&packages, "d")]);
+    }
+}
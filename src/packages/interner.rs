@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The package names a Packages knows, numbered densely in the order they were first seen: the first name is 0, the
+/// next 1, and so on. Each Packages numbers its own names, so the same name can have different numbers in two of them.
+#[derive(Default)]
+pub struct Interner {
+    names : Vec<String>,
+    nums : HashMap<String, u32>
+}
+
+impl Interner {
+    pub fn num(&self, name: &str) -> Option<u32> {
+        self.nums.get(name).copied()
+    }
+
+    /// The name of num, or None if num was not handed out by this interner.
+    pub fn get(&self, num: u32) -> Option<&str> {
+        self.names.get(num as usize).map(|n| &n[..])
+    }
+
+    /// The number of name, numbering it if it is new.
+    pub fn intern(&mut self, name: &str) -> u32 {
+        if let Some(num) = self.nums.get(name) {
+            return *num;
+        }
+        let num = u32::try_from(self.names.len()).expect("too many package names");
+        self.names.push(name.to_string());
+        self.nums.insert(name.to_string(), num);
+        num
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// The names in the order they were numbered.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(|n| &n[..])
+    }
+}
+
+// snapshots only hold the names; the numbers follow from their order
+impl Serialize for Interner {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.names.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Interner {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Interner, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        let nums = names.iter().enumerate().map(|(num, name)| (name.clone(), num as u32)).collect();
+        Ok(Interner { names, nums })
+    }
+}
+
+/// Something for each package, indexed by package number. Packages that nothing was recorded for (including those
+/// numbered after the last one that was) have the default value.
+#[derive(Serialize,Deserialize)]
+pub struct PackageTable<T> {
+    entries : Vec<T>
+}
+
+impl<T> Default for PackageTable<T> {
+    fn default() -> Self {
+        PackageTable { entries : vec![] }
+    }
+}
+
+impl<T: Default> PackageTable<T> {
+    pub fn get(&self, package_num: u32) -> Option<&T> {
+        self.entries.get(package_num as usize)
+    }
+
+    /// The entry of package_num, which can be changed; the table grows as needed.
+    pub fn entry(&mut self, package_num: u32) -> &mut T {
+        let i = package_num as usize;
+        if i >= self.entries.len() {
+            self.entries.resize_with(i + 1, T::default);
+        }
+        &mut self.entries[i]
+    }
+
+    /// Every entry with its package number, in order of package number.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> {
+        self.entries.iter().enumerate().map(|(i, e)| (i as u32, e))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter()
+    }
+}
+
+impl<T> PackageTable<Option<T>> {
+    /// The value recorded for package_num, if any.
+    pub fn value(&self, package_num: u32) -> Option<&T> {
+        self.entries.get(package_num as usize).and_then(Option::as_ref)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dense_numbers() {
+        let mut interner = Interner::default();
+        assert_eq!((interner.intern("libc6"), interner.intern("dpkg"), interner.intern("libc6"), interner.intern("tar")), (0, 1, 0, 2));
+        assert_eq!(interner.len(), 3);
+        assert_eq!(interner.names().collect::<Vec<_>>(), ["libc6", "dpkg", "tar"]);
+        assert_eq!((interner.num("dpkg"), interner.get(2)), (Some(1), Some("tar")));
+        // the numbers follow from the order of the names in a snapshot
+        let copy : Interner = bincode::deserialize(&bincode::serialize(&interner).unwrap()).unwrap();
+        assert_eq!((copy.num("tar"), copy.get(0)), (Some(2), Some("libc6")));
+    }
+
+    #[test]
+    fn unknown_names_and_numbers() {
+        let mut interner = Interner::default();
+        assert_eq!((interner.num("libc6"), interner.get(0)), (None, None));
+        interner.intern("libc6");
+        assert_eq!((interner.num("libc"), interner.num("LIBC6"), interner.get(1), interner.get(u32::MAX)), (None, None, None, None));
+    }
+}
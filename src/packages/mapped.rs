@@ -0,0 +1,397 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::ops::Deref;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Packages;
+use crate::packages::{Dependency, RelVersionedPackageNum, RelationKind};
+use crate::packages::multiarch::ArchQualifier;
+use crate::packages::providers::Provided;
+use crate::packages::repository::AvailableVersion;
+use crate::packages::release::checked_release;
+use crate::packages::sources::SourceRef;
+use crate::packages::errors::{LoadError, LoadReport};
+use crate::packages::parsers::{GZIP_MAGIC, XZ_MAGIC};
+
+use crate::debversion::{DebianVersionNum, VersionRelation};
+use crate::deb822::{FieldRef, Paragraph, ParagraphRef, ParagraphScanner};
+
+/// How load-packages keeps what it loads from an uncompressed Packages index: copied into the heap, or as ranges of
+/// the memory-mapped file (stanzas, MD5sums and relation versions), which keeps the file mapped for as long as they
+/// are in use. A setting of the session, like LoadMode.
+#[derive(Clone,Copy,PartialEq,Eq,Debug,Default)]
+pub enum IndexStorage {
+    #[default]
+    Owned,
+    Mapped
+}
+
+impl FromStr for IndexStorage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "owned" => Ok(IndexStorage::Owned),
+            "mapped" => Ok(IndexStorage::Mapped),
+            _ => Err(format!("unknown index storage {:?}: expected owned or mapped", s))
+        }
+    }
+}
+
+impl fmt::Display for IndexStorage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IndexStorage::Owned => write!(f, "owned"),
+            IndexStorage::Mapped => write!(f, "mapped")
+        }
+    }
+}
+
+/// A range of a memory-mapped index, which keeps the mapping alive. Mapped files are smaller than 4 GiB.
+#[derive(Clone)]
+pub struct MappedText {
+    map : Arc<Mmap>,
+    start : u32,
+    len : u32
+}
+
+impl MappedText {
+    // s as a range of map, if it lies within it
+    fn within(map: &Arc<Mmap>, s: &str) -> Option<MappedText> {
+        let start = (s.as_ptr() as usize).checked_sub(map.as_ptr() as usize)?;
+        if start + s.len() > map.len() { return None }
+        Some(MappedText { map : Arc::clone(map), start : start as u32, len : s.len() as u32 })
+    }
+
+    // The text was checked to be UTF-8 when it was loaded, so it can only stop being so if the file was changed in
+    // place since, which parse_packages_mapped rules out; it is then cut at the first byte that is not, rather than
+    // panicking, and reads back as whatever the file now holds.
+    fn as_str(&self) -> &str {
+        let start = self.start as usize;
+        let bytes = &self.map[start..start + self.len as usize];
+        std::str::from_utf8(bytes).unwrap_or_else(|e| std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default())
+    }
+}
+
+/// A string loaded from an index: owned, or a range of a memory-mapped index (see IndexStorage). Snapshots save it as
+/// a string and load it back owned.
+#[derive(Clone)]
+pub enum Text {
+    Owned(String),
+    Mapped(MappedText)
+}
+
+impl Text {
+    /// s, as a range of map if it lies within it (so that nothing is copied), or else copied.
+    pub fn new(map: &Arc<Mmap>, s: &str) -> Text {
+        match MappedText::within(map, s) {
+            Some(m) => Text::Mapped(m),
+            None => Text::Owned(s.to_string())
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Text::Owned(s) => s,
+            Text::Mapped(m) => m.as_str()
+        }
+    }
+}
+
+impl Deref for Text {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<String> for Text {
+    fn from(s: String) -> Text {
+        Text::Owned(s)
+    }
+}
+
+impl From<&str> for Text {
+    fn from(s: &str) -> Text {
+        Text::Owned(s.to_string())
+    }
+}
+
+impl PartialEq for Text {
+    fn eq(&self, other: &Text) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Text {}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl fmt::Debug for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Text {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Text {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Text, D::Error> {
+        String::deserialize(deserializer).map(Text::Owned)
+    }
+}
+
+/// The stanza an available version was loaded from: a Paragraph, or its text in a memory-mapped index with the number
+/// of its first line and where each field lies in the text, as found when it was loaded. Snapshots save it as a
+/// Paragraph.
+pub enum StanzaText {
+    Owned(Paragraph),
+    Mapped { text : MappedText, line : u32, fields : Vec<MappedField> }
+}
+
+/// Where a field of a mapped stanza lies, as (offset, length) ranges of the stanza text: its name, what follows the
+/// colon, and its continuation lines, if any.
+#[derive(Clone)]
+pub struct MappedField {
+    name : (u32, u32),
+    first : (u32, u32),
+    more : Option<(u32, u32)>,
+    line : u32
+}
+
+impl MappedField {
+    // field, borrowed from text
+    fn new(text: &str, field: &FieldRef) -> MappedField {
+        let range = |s: &str| ((s.as_ptr() as usize - text.as_ptr() as usize) as u32, s.len() as u32);
+        MappedField { name : range(field.name), first : range(field.first), more : field.more.map(range), line : field.line as u32 }
+    }
+
+    fn field<'a>(&self, text: &'a str) -> FieldRef<'a> {
+        let at = |(start, len): (u32, u32)| text.get(start as usize..(start + len) as usize).unwrap_or_default();
+        FieldRef { name : at(self.name), first : at(self.first), more : self.more.map(at), line : self.line as usize }
+    }
+}
+
+impl StanzaText {
+    pub fn paragraph(&self) -> Cow<'_, Paragraph> {
+        match self {
+            StanzaText::Owned(para) => Cow::Borrowed(para),
+            StanzaText::Mapped { text, line, fields } => {
+                let mut para = Paragraph::new(*line as usize);
+                for f in fields.iter().map(|f| f.field(text.as_str())) {
+                    para.push(f.name, &f.value(), f.line);
+                }
+                Cow::Owned(para)
+            }
+        }
+    }
+
+    /// Like Paragraph::get.
+    pub fn get(&self, name: &str) -> Option<String> {
+        match self {
+            StanzaText::Owned(para) => para.get(name).map(String::from),
+            StanzaText::Mapped { text, fields, .. } => fields.iter().map(|f| f.field(text.as_str()))
+                .find(|f| f.name.eq_ignore_ascii_case(name)).map(|f| f.value().into_owned())
+        }
+    }
+}
+
+impl Serialize for StanzaText {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.paragraph().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for StanzaText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<StanzaText, D::Error> {
+        Paragraph::deserialize(deserializer).map(StanzaText::Owned)
+    }
+}
+
+impl Packages {
+    /// Like parse_packages, but memory-maps filename and keeps its stanzas, MD5sums and relation versions as ranges of
+    /// the mapping instead of copying them, scanning stanzas and relations by hand instead of with regular expressions.
+    /// The result is otherwise the same. Compressed indices (and indices of 4 GiB or more) cannot be used in place;
+    /// they are loaded by parse_packages_parallel. filename must not be changed in place for as long as anything loaded
+    /// from it is in use.
+    pub fn parse_packages_mapped(&mut self, filename: &str, release_filename: Option<&str>) -> Result<LoadReport, LoadError> {
+        let file = File::open(filename).map_err(|e| LoadError::io(filename, e))?;
+        // SAFETY: Mmap::map is unsafe because the mapping is shared with the file: the &[u8] it hands out is only
+        // sound if nothing changes the file while it is mapped. Nothing here writes to it (the mapping is read-only),
+        // so this relies on the documented requirement that indices are not changed in place while loaded this way.
+        // apt honours it by writing new indices to other files and renaming them over the old ones, which leaves the
+        // mapped file (now unlinked) as it was. If it is broken anyway, reads of a truncated file fault and other
+        // changes read back as whatever the file holds; MappedText::as_str does not assume the text is still UTF-8.
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| LoadError::io(filename, e))?;
+        let compressed = map.starts_with(GZIP_MAGIC) || map.starts_with(XZ_MAGIC)
+            || matches!(Path::new(filename).extension().and_then(|e| e.to_str()), Some("gz" | "xz"));
+        if compressed || map.len() > u32::MAX as usize {
+            let mut report = self.parse_packages_parallel(filename, release_filename)?;
+            report.warnings.insert(0, format!("{}: cannot be used in place; loaded a copy", filename));
+            return Ok(report);
+        }
+        let map = Arc::new(map);
+
+        let mut report = LoadReport::new(filename);
+        let release = checked_release(filename, &map, release_filename, &mut report.warnings)?;
+        self.record_loaded_file(filename, &mut report.warnings);
+        if let Some(r) = release_filename { self.record_loaded_file(r, &mut report.warnings) }
+        let origin = self.add_origin(filename, release);
+        let mode = self.load_mode;
+        report.load_paragraphs(mode, ParagraphScanner::new(&map), |para, warnings| self.load_mapped_stanza(&map, filename, origin, para, warnings))?;
+        Ok(report)
+    }
+
+    // like load_available_stanza, for a stanza borrowed from map: the same checks in the same order, and the same
+    // names interned in the same order, so that package numbers come out the same
+    fn load_mapped_stanza(&mut self, map: &Arc<Mmap>, filename: &str, origin: usize, para: ParagraphRef, warnings: &mut Vec<String>) -> Result<(), String> {
+        let name = para.get("Package").ok_or("no Package field")?;
+        let version = para.get("Version").ok_or("no Version field")?.parse::<DebianVersionNum>().map_err(|e| e.to_string())?;
+        let values : Vec<(RelationKind, Cow<str>)> = RelationKind::ALL.iter()
+            .filter_map(|kind| para.get_folded(kind.field_name()).map(|value| (*kind, value))).collect();
+        let mut relations = vec![];
+        for (kind, value) in &values {
+            relations.push((*kind, scan_dependencies(value).map_err(|e| format!("{}: {}", kind.field_name(), e))?));
+        }
+        let provides = para.get_folded("Provides");
+        let provides = provides.as_deref().map(scan_dependencies).transpose()?.unwrap_or_default();
+
+        let relations = relations.into_iter().map(|(kind, deps)| (kind, self.intern_scanned(map, deps))).collect();
+        let provides = Provided::from_dependencies(self.intern_scanned(map, provides));
+        let package_num = self.get_package_num_inserting(&name);
+        let source = SourceRef::new(&name, para.get("Source").as_deref(), para.get("Version").as_deref());
+        let md5sum = para.get("MD5sum").map(|m| Text::new(map, &m));
+        let architecture = para.get("Architecture").map(Cow::into_owned);
+        let multi_arch = self.parse_multi_arch(filename, para.line(), para.get("Multi-Arch").as_deref(), warnings);
+        let text = MappedText::within(map, para.text()).expect("scanned stanzas lie within the mapping");
+        let fields = para.fields().iter().map(|f| MappedField::new(para.text(), f)).collect();
+        self.record_available_version(package_num, source, AvailableVersion {
+            version, md5sum, architecture, multi_arch, relations, provides,
+            paragraph : StanzaText::Mapped { text, line : para.line() as u32, fields }, origins : vec![origin]
+        }, warnings);
+        Ok(())
+    }
+
+    fn intern_scanned(&mut self, map: &Arc<Mmap>, deps: Vec<Vec<ScannedRelation>>) -> Vec<Dependency> {
+        deps.into_iter().map(|dep| dep.into_iter().map(|relation| RelVersionedPackageNum {
+            package_num : self.get_package_num_inserting(relation.package_name),
+            rel_version : relation.rel_version.map(|(op, version)| (op, Text::new(map, version))),
+            arch : relation.arch
+        }).collect()).collect()
+    }
+}
+
+// one alternative of a relation, borrowed from the field it was scanned from
+struct ScannedRelation<'a> {
+    package_name : &'a str,
+    rel_version : Option<(VersionRelation, &'a str)>,
+    arch : Option<ArchQualifier>
+}
+
+// like parse_dependencies_text
+fn scan_dependencies(value: &str) -> Result<Vec<Vec<ScannedRelation<'_>>>, String> {
+    value.split(',').map(|s| s.trim()).filter(|s| !s.is_empty())
+        .map(|element| element.split('|').map(|s| s.trim()).map(scan_relation).collect())
+        .collect()
+}
+
+// \w in a regular expression
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Matches alternative the way PKGNAME_AND_VERSION_REGEX does (package name characters, then an optional ":arch" and
+// an optional "(op version)", and nothing else), with the same errors as parse_relation_text.
+fn scan_relation(alternative: &str) -> Result<ScannedRelation<'_>, String> {
+    let bad = || format!("bad relation {:?}", alternative);
+    let is_name = |c: char| is_word(c) || matches!(c, '.' | '+' | '-');
+    let (package_name, mut rest) = alternative.split_at(alternative.find(|c| !is_name(c)).unwrap_or(alternative.len()));
+    if package_name.is_empty() {
+        return Err(bad());
+    }
+    let mut arch = None;
+    if let Some(after_colon) = rest.strip_prefix(':') {
+        let len = after_colon.find(|c: char| !(is_word(c) || c == '-')).unwrap_or(after_colon.len());
+        if len == 0 {
+            return Err(bad());
+        }
+        arch = Some(after_colon[..len].parse::<ArchQualifier>().map_err(|e| format!("{} in {:?}", e, alternative))?);
+        rest = &after_colon[len..];
+    }
+    let mut rel_version = None;
+    if !rest.is_empty() {
+        let in_parens = rest.trim_start().strip_prefix('(').ok_or_else(bad)?.trim_start();
+        let op_len = in_parens.chars().take(2).take_while(|c| matches!(c, '<' | '=' | '>')).count();
+        let (op, after_op) = in_parens.split_at(op_len);
+        let close = after_op.find(')').ok_or_else(bad)?;
+        if op_len == 0 || close + 1 != after_op.len() {
+            return Err(bad());
+        }
+        let op = op.parse::<VersionRelation>().map_err(|e| format!("{} in {:?}", e, alternative))?;
+        let version = after_op[..close].trim();
+        version.parse::<DebianVersionNum>().map_err(|e| format!("{} in {:?}", e, alternative))?;
+        rel_version = Some((op, version));
+    }
+    Ok(ScannedRelation { package_name, rel_version, arch })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packages::testutil::{num, TempFile};
+
+    // what loading contents with storage reports and numbers
+    fn load_with(storage: IndexStorage, contents: &str) -> (usize, Vec<String>, Vec<String>) {
+        let index = TempFile::new(contents);
+        let mut packages = Packages::new();
+        packages.set_index_storage(storage);
+        let report = packages.parse_packages(index.path(), None).unwrap();
+        let skipped = report.skipped.iter().map(|e| e.to_string().replace(index.path(), "Packages")).collect();
+        (report.loaded, skipped, packages.get_package_names().into_iter().map(String::from).collect())
+    }
+
+    #[test]
+    fn scanned_relations_match_the_regex() {
+        let relations = ["python3:any (>= 3.6~)", "libc6(<<2.36)", "foo ( = 1:2.0-1 )", "(>= 1) foo", "foo bar baz",
+                         "foo (>= 1", "foo (>= 1) bar", "foo:", "foo ()", "foo (=> 2)", "foo (>= 1.0 beta)", "a | b:i386"];
+        let contents : String = relations.iter().enumerate()
+            .map(|(i, r)| format!("Package: p{}\nVersion: 1\nDepends: {}\n\n", i, r)).collect();
+        let owned = load_with(IndexStorage::Owned, &contents);
+        assert_eq!(owned.0, 4);
+        assert_eq!(owned, load_with(IndexStorage::Mapped, &contents));
+    }
+
+    #[test]
+    fn mapped_stanzas_read_back_like_owned_ones() {
+        let contents = "Package: a\r\nVersion: 1\r\nDepends: b,\r\n c,\r\n# comment\r\n  d\r\nDescription: short\r\n long\r\n .\r\n\r\n\
+                        Package: e\nVersion: 2\nEmpty:\nMD5sum: 0123\n";
+        let index = TempFile::new(contents);
+        let (mut owned, mut mapped) = (Packages::new(), Packages::new());
+        assert_eq!(owned.parse_packages_parallel(index.path(), None).unwrap().loaded, 2);
+        assert_eq!(mapped.parse_packages_mapped(index.path(), None).unwrap().loaded, 2);
+        assert_eq!(owned.parse_digest(), mapped.parse_digest());
+        for name in ["a", "e"] {
+            let (o, m) = (&owned.get_available_versions(num(&owned, name))[0], &mapped.get_available_versions(num(&mapped, name))[0]);
+            assert!(matches!(m.paragraph, StanzaText::Mapped { .. }));
+            for field in ["package", "Depends", "Description", "Empty", "MD5sum", "Missing"] {
+                assert_eq!(o.paragraph.get(field), m.paragraph.get(field));
+            }
+            assert_eq!(o.paragraph.paragraph().to_string(), m.paragraph.paragraph().to_string());
+        }
+    }
+}
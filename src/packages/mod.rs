@@ -1,219 +1,467 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::io;
 use std::path::Path;
-use std::sync::atomic::{AtomicI32, Ordering};
+use std::str::FromStr;
+use std::sync::OnceLock;
 
-use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 
-use rpkg::debversion;
-use rpkg::debversion::{DebianVersionNum,VersionRelation};
+use crate::debversion;
+use crate::debversion::{DebianVersionNum,VersionRelation};
+use crate::deb822::Paragraph;
 
 mod deps_available;
 mod solvers;
 mod parsers;
 mod async_fns;
+mod conflicts;
+mod providers;
+mod status;
+mod repository;
+mod release;
+mod preferences;
+mod multiarch;
+mod sources;
+mod errors;
+mod validate;
+mod snapshot;
+mod bench;
+mod mapped;
+mod interner;
+mod sat;
+mod planner;
+mod installability;
+mod consistency;
+mod upgrade;
+#[cfg(test)]
+mod testutil;
 
 use crate::packages::async_fns::AsyncState;
+use crate::packages::preferences::Preference;
+use crate::packages::interner::{Interner, PackageTable};
+pub use crate::packages::async_fns::Verification;
+pub use crate::packages::bench::{BenchDifference, BenchReport};
+pub use crate::packages::consistency::{BrokenPackage, UnmetRelation};
+pub use crate::packages::conflicts::{ConflictResolution, InstallPlan, PlanConflict};
+pub use crate::packages::deps_available::Satisfier;
+pub use crate::packages::errors::{LoadError, LoadErrorKind, LoadMode, LoadReport, Stanza};
+pub use crate::packages::installability::{InstallabilityReport, Uninstallable};
+pub use crate::packages::mapped::{IndexStorage, MappedField, MappedText, StanzaText, Text};
+pub use crate::packages::multiarch::{ArchQualifier, InstalledInstance, MultiArch};
+pub use crate::packages::planner::{Change, Reason, SolverMode};
+pub use crate::packages::providers::{Provided, Provider};
+pub use crate::packages::release::{ReleaseFile, ReleaseInfo};
+pub use crate::packages::repository::{AvailableVersion, IntegrityConflict, Origin};
+pub use crate::packages::snapshot::{FileStamp, SnapshotError};
+pub use crate::packages::solvers::{UnsatisfiableEdge, VersionedClosure};
+pub use crate::packages::sources::{BinarySource, BuildConflict, BuildDependency, BuildPlan, BuildRelation, BuildRelationKind,
+                                   SourceRef, SourceVersion, VersionSkew};
+pub use crate::packages::upgrade::{HeldBack, PlannedChange, Upgradable, UpgradePlan};
+pub use crate::packages::status::{EFlag, PackageStatus, StatusState, Want};
 
-static PACKAGE_COUNTER: AtomicI32 = AtomicI32::new(0);
-
+#[derive(Serialize,Deserialize)]
 pub struct Packages {
-    dependencies : HashMap<i32,Vec<Dependency>>,
-    md5sums : HashMap<i32,String>,
-    available_debvers : HashMap<i32,DebianVersionNum>,
-    installed_debvers : HashMap<i32,DebianVersionNum>,
-    package_name_to_num : HashMap<String, i32>,
-    package_num_to_name : HashMap<i32, String>,
+    // every available version of each package, with its md5sum, relations, Provides and stanza
+    available : PackageTable<Vec<AvailableVersion>>,
+    // every index file loaded so far, referred to by position from AvailableVersion::origins
+    origins : Vec<Origin>,
+    // versions that were loaded with different MD5sums from different origins
+    integrity_conflicts : Vec<IntegrityConflict>,
+    // pin preferences in file order, which decide the candidate version
+    preferences : Vec<Preference>,
+    // the candidate of each package, by position in its available versions; computed when first asked for, and cleared
+    // when versions, origins or preferences change (see clear_candidates)
+    #[serde(skip)]
+    candidates : OnceLock<PackageTable<Option<usize>>>,
+    // from virtual package number to the packages that provide it (in some available version, or when installed)
+    available_providers : PackageTable<Vec<u32>>,
+    installed_providers : PackageTable<Vec<Provider>>,
+    // every status stanza, one per architecture, with its dpkg status, version and relations; only instances whose
+    // status makes them satisfy dependencies count as installed
+    installed_instances : PackageTable<Vec<InstalledInstance>>,
+    // complete stanzas as read from the status file (one per architecture), all fields in file order
+    installed_paragraphs : PackageTable<Vec<Paragraph>>,
+    native_arch : Option<String>,
+    // every version of each source package, from Sources indices
+    sources : HashMap<String,Vec<SourceVersion>>,
+    // from source package name to the binary packages built from it, per Source fields and Binary fields of Sources
+    source_binaries : HashMap<String,Vec<u32>>,
+    // every file loaded so far, with its size and modification time then, so that snapshots can tell when it changed
+    loaded_files : Vec<FileStamp>,
+    // what loaders do with malformed stanzas; a setting of the session, not saved in snapshots
+    #[serde(skip)]
+    load_mode : LoadMode,
+    // how parse_packages keeps what it loads; also a setting of the session
+    #[serde(skip)]
+    index_storage : IndexStorage,
+    // how how-to-install plans; also a setting of the session
+    #[serde(skip)]
+    solver_mode : SolverMode,
+    // package names and their numbers, which index the tables above
+    interner : Interner,
+    #[serde(skip, default = "AsyncState::new")]
     async_state : AsyncState,
 }
 
 // Dependency([X, Y, Z]) means X|Y|Z
+#[derive(Clone,Serialize,Deserialize)]
 pub struct RelVersionedPackageNum {
-    package_num : i32,
-    rel_version : Option<(VersionRelation, String)>
+    package_num : u32,
+    rel_version : Option<(VersionRelation, Text)>,
+    arch : Option<ArchQualifier>
 }
 pub type Dependency = Vec<RelVersionedPackageNum>;
 
+impl RelVersionedPackageNum {
+    /// The package the relation is on (which may be virtual).
+    pub fn package_num(&self) -> u32 {
+        self.package_num
+    }
+
+    /// The version constraint, as in "(>= 2.33)"; None for an unversioned relation.
+    pub fn rel_version(&self) -> Option<(&VersionRelation, &str)> {
+        self.rel_version.as_ref().map(|(op, version)| (op, version.as_str()))
+    }
+
+    /// The architecture qualifier, as in "python3:any".
+    pub fn arch(&self) -> Option<&ArchQualifier> {
+        self.arch.as_ref()
+    }
+}
+
+/// The relationship fields a binary package can declare; all of them share the Dependency structure.
+/// Conflicts, Breaks and Replaces are negative: their entries have no alternatives.
+#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug,Serialize,Deserialize)]
+pub enum RelationKind {
+    PreDepends,
+    Depends,
+    Recommends,
+    Suggests,
+    Enhances,
+    Conflicts,
+    Breaks,
+    Replaces
+}
+
+impl RelationKind {
+    pub const ALL : [RelationKind; 8] = [RelationKind::PreDepends, RelationKind::Depends, RelationKind::Recommends,
+                                         RelationKind::Suggests, RelationKind::Enhances,
+                                         RelationKind::Conflicts, RelationKind::Breaks, RelationKind::Replaces];
+
+    /// Kinds that must be satisfied before a package can be installed (what dpkg enforces).
+    pub const REQUIRED : [RelationKind; 2] = [RelationKind::PreDepends, RelationKind::Depends];
+
+    /// Kinds that forbid packages from being installed alongside the declaring package.
+    pub const NEGATIVE : [RelationKind; 2] = [RelationKind::Conflicts, RelationKind::Breaks];
+
+    pub fn is_negative(&self) -> bool {
+        matches!(self, RelationKind::Conflicts | RelationKind::Breaks | RelationKind::Replaces)
+    }
+
+    /// Name of the control field holding relations of this kind.
+    pub fn field_name(&self) -> &'static str {
+        match self {
+            RelationKind::PreDepends => "Pre-Depends",
+            RelationKind::Depends => "Depends",
+            RelationKind::Recommends => "Recommends",
+            RelationKind::Suggests => "Suggests",
+            RelationKind::Enhances => "Enhances",
+            RelationKind::Conflicts => "Conflicts",
+            RelationKind::Breaks => "Breaks",
+            RelationKind::Replaces => "Replaces"
+        }
+    }
+
+    /// Used in output, as in "apt" depends on "...".
+    pub fn verb(&self) -> &'static str {
+        match self {
+            RelationKind::PreDepends => "pre-depends on",
+            RelationKind::Depends => "depends on",
+            RelationKind::Recommends => "recommends",
+            RelationKind::Suggests => "suggests",
+            RelationKind::Enhances => "enhances",
+            RelationKind::Conflicts => "conflicts with",
+            RelationKind::Breaks => "breaks",
+            RelationKind::Replaces => "replaces"
+        }
+    }
+}
+
+impl fmt::Display for RelationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.field_name())
+    }
+}
+
+impl FromStr for RelationKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RelationKind::ALL.iter().find(|k| k.field_name().eq_ignore_ascii_case(s)).copied()
+            .ok_or_else(|| format!("unknown relation kind {}", s))
+    }
+}
+
+impl Default for Packages {
+    fn default() -> Packages {
+        Packages::new()
+    }
+}
+
 impl Packages {
     pub fn new() -> Packages {
         Packages { 
-            dependencies : HashMap::new(), 
-            md5sums : HashMap::new(),
-            available_debvers : HashMap::new(),
-            installed_debvers : HashMap::new(),
-            package_name_to_num : HashMap::new(), 
-            package_num_to_name : HashMap::new(),
+            available : PackageTable::default(),
+            origins : vec![],
+            integrity_conflicts : vec![],
+            preferences : vec![],
+            candidates : OnceLock::new(),
+            available_providers : PackageTable::default(),
+            installed_providers : PackageTable::default(),
+            installed_instances : PackageTable::default(),
+            installed_paragraphs : PackageTable::default(),
+            native_arch : None,
+            sources : HashMap::new(),
+            source_binaries : HashMap::new(),
+            loaded_files : vec![],
+            load_mode : LoadMode::Lenient,
+            index_storage : IndexStorage::Owned,
+            solver_mode : SolverMode::Sat,
+            interner : Interner::default(),
             async_state : AsyncState::new(),
         }
     }
 
     // next few functions manipulate the list of packages and the name/number interface
+    pub fn load_mode(&self) -> LoadMode {
+        self.load_mode
+    }
+
+    /// Sets what the loaders do with malformed stanzas from now on; see LoadMode.
+    pub fn set_load_mode(&mut self, mode: LoadMode) {
+        self.load_mode = mode;
+    }
+
+    pub fn index_storage(&self) -> IndexStorage {
+        self.index_storage
+    }
+
+    /// Sets how parse_packages keeps what it loads from now on; see IndexStorage.
+    pub fn set_index_storage(&mut self, storage: IndexStorage) {
+        self.index_storage = storage;
+    }
+
+    /// Every known package name, in the order they were numbered.
     pub fn get_package_names(&self) -> Vec<&str> {
-        self.package_name_to_num.keys().map(|x| &x[..]).collect()
+        self.interner.names().collect()
     }
 
-    fn get_package_name(&self, package_num: i32) -> &str {
-        return self.package_num_to_name.get(&package_num).unwrap();
+    /// The name of package_num, or None if no package has that number (e.g. it was given out by another Packages).
+    pub fn get_package_name(&self, package_num: u32) -> Option<&str> {
+        self.interner.get(package_num)
+    }
+
+    // the name of package_num, taken from one of this Packages' own tables, for messages; such numbers always have a
+    // name, so the fallback would only show for a damaged snapshot
+    pub(super) fn shown_name(&self, package_num: u32) -> &str {
+        self.interner.get(package_num).unwrap_or("<unknown package>")
+    }
+
+    /// The number of package_name, if it is known. Package numbers are dense, from 0 to package_count() - 1, and only
+    /// mean something to the Packages that gave them out.
+    pub fn package_num(&self, package_name: &str) -> Option<u32> {
+        self.interner.num(package_name)
+    }
+
+    /// Number of known package names, whether available, installed, virtual or only mentioned in a relation.
+    pub fn package_count(&self) -> usize {
+        self.interner.len()
     }
 
     // panics if package_name doesn't already exist
-    fn get_package_num(&self, package_name: &str) -> &i32 {
-        return self.package_name_to_num.get(package_name).unwrap();
-    }
-
-    // inserts package_name into package_name_to_num if it doesn't already exist
-    fn get_package_num_inserting(&mut self, package_name: &str) -> i32 {
-        if !self.package_name_to_num.contains_key(package_name) {
-            let pnum = PACKAGE_COUNTER.load(Ordering::SeqCst);
-            self.package_name_to_num.insert(String::from(package_name), pnum);
-            self.package_num_to_name.insert(pnum, String::from(package_name));
-            self.dependencies.insert(pnum, vec![]);
-            PACKAGE_COUNTER.fetch_add(1, Ordering::SeqCst);
-            return pnum;
-        } else {
-            return *self.package_name_to_num.get(package_name).unwrap();
-        }
+    // numbers package_name if it doesn't already have a number
+    fn get_package_num_inserting(&mut self, package_name: &str) -> u32 {
+        self.interner.intern(package_name)
     }
 
     pub fn package_exists(&self, package_name: &str) -> bool {
-        return self.package_name_to_num.contains_key(package_name);
+        self.interner.num(package_name).is_some()
+    }
+
+    /// The installed version of package_num, if it is installed: that of its primary instance (see primary_instance).
+    pub fn installed_debver(&self, package_num: u32) -> Option<&DebianVersionNum> {
+        self.primary_instance(package_num).and_then(|i| i.debver.as_ref())
+    }
+
+    /// Every installed package with its installed version, in order of package number.
+    pub fn installed_versions(&self) -> impl Iterator<Item = (u32, &DebianVersionNum)> {
+        self.installed_instances.iter().filter_map(move |(package_num, _)| self.installed_debver(package_num).map(|v| (package_num, v)))
+    }
+
+    /// Number of installed packages.
+    pub fn installed_count(&self) -> usize {
+        self.installed_versions().count()
     }
 
     // accessor methods for various maps
+    /// Returns the candidate version of package_name.
     pub fn get_available_debver(&self, package_name: &str) -> Option<&DebianVersionNum> {
-        let package_num = self.package_name_to_num.get(package_name);
-        return match package_num {
-            None => None,
-            Some(x) => match self.available_debvers.get(x) {
-                None => None,
-                Some(y) => Some(y)
-            }
-        }
+        let package_num = self.interner.num(package_name)?;
+        self.get_candidate_debver(package_num)
     }
 
     pub fn get_installed_debver(&self, package_name: &str) -> Option<&DebianVersionNum> {
-        let package_num = self.package_name_to_num.get(package_name);
-        return match package_num {
-            None => None,
-            Some(x) => match self.installed_debvers.get(x) {
-                None => None,
-                Some(y) => Some(y)
-            }
+        let package_num = self.interner.num(package_name)?;
+        self.installed_debver(package_num)
+    }
+
+    /// Returns the relations of the given kind declared by the candidate version of package_num (empty if there are none).
+    pub fn get_relations(&self, kind: RelationKind, package_num: u32) -> &[Dependency] {
+        match self.get_candidate(package_num) {
+            None => &[],
+            Some(candidate) => candidate.get_relations(kind)
         }
     }
 
-    pub fn get_md5sum(&self, package_name: &str) -> Option<&str> {
-        let package_num = self.package_name_to_num.get(package_name);
-        return match package_num {
-            None => None,
-            Some(x) => match self.md5sums.get(x) {
-                None => None,
-                Some(y) => Some(y)
+    /// Returns the relations of the given kind declared by the installed version of package_num (by its primary
+    /// instance; get_installed_instances has those of each architecture).
+    pub fn get_installed_relations(&self, kind: RelationKind, package_num: u32) -> &[Dependency] {
+        match self.primary_instance(package_num) {
+            None => &[],
+            Some(instance) => instance.get_relations(kind)
+        }
+    }
+
+    /// Returns whether version meets the version constraint of relation (always true if it is unversioned), or None if
+    /// the version of the relation is not a version number. Loaders check it, so only a damaged snapshot can hold one.
+    pub fn rel_version_matches(&self, relation: &RelVersionedPackageNum, version: &DebianVersionNum) -> Option<bool> {
+        match &relation.rel_version {
+            None => Some(true),
+            Some((op, version_string)) => {
+                let v = version_string.parse::<DebianVersionNum>().ok()?;
+                Some(debversion::cmp_debversion_with_op(op, version, &v))
             }
         }
     }
 
-    // helper functions; these aren't structs so I can't make them implement Fmt::Display.
-    fn deps2str(&self, deps: &Vec<Dependency>) -> String {
-        return deps.iter().map(|dep| self.dep2str(dep)).format(", ").to_string();
+    /// Iterates over the relations of all the given kinds declared by package_num.
+    pub fn get_relations_of_kinds<'a>(&'a self, kinds: &'a [RelationKind], package_num: u32) -> impl Iterator<Item = &'a Dependency> + 'a {
+        kinds.iter().flat_map(move |k| self.get_relations(*k, package_num).iter())
     }
 
-    fn dep2str(&self, dep: &Dependency) -> String {
-        return dep.iter().map(|d| {
-            let pn = self.get_package_name(d.package_num);
-            match &d.rel_version {
-                None => String::from(pn),
-                Some((rel, ver)) => format!("{} ({} {})", pn, rel.to_string(), ver)
-            }
-        }).format(" | ").to_string();
+    /// Returns the md5sum of the candidate version of package_name.
+    pub fn get_md5sum(&self, package_name: &str) -> Option<&str> {
+        let package_num = self.interner.num(package_name)?;
+        self.get_candidate(package_num)?.md5sum.as_deref()
     }
 
-    // output commands
-    pub fn print_deps(&self, package_name: &str) {
-        if !self.package_exists(package_name) {
-            println!("no such package {}", package_name);
-            return;
-        }
-        let deps : &Vec<Dependency> = &*self.dependencies.get(self.get_package_num(package_name)).unwrap();
-        println!("{:?} depends on {:?}", package_name, self.deps2str(deps));
+    /// Returns the value of an arbitrary field (Section, Priority, Maintainer, Homepage, ...) from the
+    /// candidate's stanza of package_name, falling back to its installed stanza.
+    pub fn get_field(&self, package_name: &str, field: &str) -> Option<String> {
+        let package_num = self.interner.num(package_name)?;
+        self.get_candidate(package_num).and_then(|c| c.paragraph.get(field))
+            .or_else(|| self.get_installed_paragraphs(package_num).first().and_then(|p| p.get(field)).map(String::from))
     }
 
-    pub fn print_transitive_dep_solution(&self, package_name: &str) {
-        if !self.package_exists(package_name) {
-            println!("no such package {}", package_name);
-            return;
+    /// The stanzas of package_num in the status file, one per architecture, with all their fields in file order.
+    pub fn get_installed_paragraphs(&self, package_num: u32) -> &[Paragraph] {
+        match self.installed_paragraphs.get(package_num) {
+            None => &[],
+            Some(paragraphs) => paragraphs
         }
-        let dep_solution : Vec<i32> = self.transitive_dep_solution(package_name);
-        println!("{:?} transitive dependency solution: {:?}", package_name, dep_solution.iter().map(|dep| self.get_package_name(*dep)).format(", ").to_string());
     }
 
-    pub fn print_how_to_install(&self, package_name: &str) {
-        if !self.package_exists(package_name) {
-            println!("no such package {}", package_name);
-            return;
-        }
-        println!("Package {}:", package_name);
-        let pkgs_to_install:Vec<i32> = self.compute_how_to_install(package_name);
-        println!("{:?} to install: {:?}", package_name, pkgs_to_install.iter().map(|dep| self.get_package_name(*dep)).format(", ").to_string());
+    // helper functions; these aren't structs so I can't make them implement Fmt::Display.
+    /// Relations as they would be written in a control field: "libc6 (>= 2.33), gpgv | gpgv2".
+    /// None if a relation names a package this Packages did not number, like those of rel2str.
+    pub fn deps2str(&self, deps: &[Dependency]) -> Option<String> {
+        Some(deps.iter().map(|dep| self.dep2str(dep)).collect::<Option<Vec<_>>>()?.join(", "))
     }
 
-    pub fn print_info(&self, package_name: &str) {
-        if !self.package_exists(package_name) {
-            println!("no such package {}", package_name);
-            return;
-        }
-        println!("Package: {}", package_name);
-        let a = self.get_available_debver(package_name);
-        let i = self.get_installed_debver(package_name);
-        match a {
-            None => (),
-            Some(a) => {
-                println!("Version: {}", a.to_string());
-                println!("MD5Sum: {}", self.get_md5sum(package_name).unwrap().to_string());
-                println!("Depends: {}", self.deps2str(&*self.dependencies.get(self.get_package_num(package_name)).unwrap()));
-            }
-        }
-        match i {
-            None => (),
-            Some(i) => { println!("Installed-Version: {}", i.to_string()) }
-        }
-        match (a, i) {
-            (Some(aa), Some(ii)) =>
-                { println!("Newer-Available: {:?}", aa > ii); }
-            _ => ()
+    pub fn dep2str(&self, dep: &Dependency) -> Option<String> {
+        Some(dep.iter().map(|d| self.rel2str(d)).collect::<Option<Vec<_>>>()?.join(" | "))
+    }
+
+    /// None if rel was not loaded by this Packages and names a package it did not number.
+    pub fn rel2str(&self, rel: &RelVersionedPackageNum) -> Option<String> {
+        self.interner.get(rel.package_num).map(|_| self.relation_text(rel))
+    }
+
+    // rel2str for a relation this Packages loaded itself
+    pub(super) fn relation_text(&self, rel: &RelVersionedPackageNum) -> String {
+        let pn = match &rel.arch {
+            None => self.shown_name(rel.package_num).to_string(),
+            Some(arch) => format!("{}:{}", self.shown_name(rel.package_num), arch)
+        };
+        match &rel.rel_version {
+            None => pn,
+            Some((op, ver)) => format!("{} ({} {})", pn, op, ver)
         }
     }
 
-    // generate output for package-verifier
-    pub fn output_md5s(&self, fname: &str) {
+    /// Writes the name, version and MD5sum of every available version to fname as CSV, for package-verifier.
+    pub fn output_md5s(&self, fname: &str) -> io::Result<()> {
         let path = Path::new(fname);
         let mut md5s : String = "name,version,hash\n".to_owned();
-        for pn in self.get_package_names() {
-            match (self.get_available_debver(pn), self.get_md5sum(pn)) {
-                (Some(v), Some(m)) => {
-                    let row = format!("{},{},{}\n",pn,v.to_string(),m);
+        for (package_num, pn) in self.interner.names().enumerate() {
+            for v in self.get_available_versions(package_num as u32) {
+                if let Some(m) = &v.md5sum {
+                    let row = format!("{},{},{}\n",pn,v.version,m);
                     md5s.push_str(&row)
                 }
-                (_, _) => ()
             }
         }
-        std::fs::write(path, md5s).unwrap();
+        std::fs::write(path, md5s)
     }
 
     // provided parse function to let students do the async io part independently
-    pub fn parse_csv(&mut self, filename: &str) {
-        let mut rdr = csv::Reader::from_path(filename).unwrap();
+    pub fn parse_csv(&mut self, filename: &str) -> Result<LoadReport, LoadError> {
+        let file = parsers::open_decompressed(filename).map_err(|e| LoadError::io(filename, e))?;
+        let mut report = LoadReport::new(filename);
+        self.record_loaded_file(filename, &mut report.warnings);
+        let origin = self.add_origin(filename, None);
+        let mode = self.load_mode;
+        let mut rdr = csv::Reader::from_reader(file);
         for line in rdr.records() {
-            let line = line.unwrap();
-            let package_name = String::from(line.get(0).unwrap());
-            let debver = String::from(line.get(1).unwrap()).parse::<debversion::DebianVersionNum>().unwrap();
-            let md5sum = String::from(line.get(2).unwrap());
-
-            let package_num = self.get_package_num_inserting(&package_name);
-            self.available_debvers.insert(package_num, debver);
-            self.md5sums.insert(package_num, md5sum);
+            // errors reading the file end it; the others (e.g. a record with too few fields) are about one record
+            let line = match line {
+                Ok(l) => l,
+                Err(e) if e.is_io_error() => return Err(LoadError::io(filename, e.into())),
+                Err(e) => {
+                    let line_no = e.position().map_or(0, |p| p.line() as usize);
+                    report.skip(mode, LoadError::malformed(filename, line_no, None, e.to_string()))?;
+                    continue;
+                }
+            };
+            let line_no = line.position().map_or(0, |p| p.line() as usize);
+            let (package_name, version, md5sum) = match (line.get(0), line.get(1), line.get(2)) {
+                (Some(p), Some(v), Some(m)) => (p, v, m),
+                _ => {
+                    report.skip(mode, LoadError::malformed(filename, line_no, None, String::from("expected name,version,hash")))?;
+                    continue;
+                }
+            };
+            let debver = match version.parse::<debversion::DebianVersionNum>() {
+                Ok(v) => v,
+                Err(e) => {
+                    report.skip(mode, LoadError::malformed(filename, line_no, Some(package_name), e.to_string()))?;
+                    continue;
+                }
+            };
 
+            let package_num = self.get_package_num_inserting(package_name);
+            let mut paragraph = Paragraph::new(line_no);
+            paragraph.push("Package", package_name, line_no);
+            paragraph.push("Version", &debver.to_string(), line_no);
+            paragraph.push("MD5sum", md5sum, line_no);
+            self.add_available_version(package_num, AvailableVersion {
+                version : debver, md5sum : Some(Text::from(md5sum)), architecture : None, multi_arch : MultiArch::No, relations : HashMap::new(), provides : vec![],
+                paragraph : StanzaText::Owned(paragraph),
+                origins : vec![origin]
+            }, &mut report.warnings);
+            report.loaded += 1;
         }
-
-        println!("Packages available: {}", self.available_debvers.keys().len());
+        Ok(report)
     }
 }
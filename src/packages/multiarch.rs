@@ -0,0 +1,282 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::debversion::DebianVersionNum;
+
+use crate::Packages;
+use crate::packages::{Dependency, RelVersionedPackageNum, RelationKind};
+use crate::packages::status::PackageStatus;
+use crate::packages::sources::SourceRef;
+
+// Multiarch lets packages of several architectures (e.g. amd64 and i386) be installed side by side; see
+// https://wiki.debian.org/Multiarch/Implementation. Architecture: all packages behave as if they were native.
+
+/// The Multi-Arch field of a binary package.
+#[derive(Clone,Copy,PartialEq,Eq,Debug,Serialize,Deserialize)]
+pub enum MultiArch {
+    // the default: only satisfies dependencies from packages of its own architecture
+    No,
+    // can be installed for several architectures at once (libraries)
+    Same,
+    // satisfies dependencies from packages of any architecture (tools)
+    Foreign,
+    // like No, except that "pkg:any" dependencies accept any architecture
+    Allowed
+}
+
+impl FromStr for MultiArch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "no" => Ok(MultiArch::No),
+            "same" => Ok(MultiArch::Same),
+            "foreign" => Ok(MultiArch::Foreign),
+            "allowed" => Ok(MultiArch::Allowed),
+            _ => Err(format!("bad Multi-Arch {:?}", s))
+        }
+    }
+}
+
+impl fmt::Display for MultiArch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultiArch::No => write!(f, "no"),
+            MultiArch::Same => write!(f, "same"),
+            MultiArch::Foreign => write!(f, "foreign"),
+            MultiArch::Allowed => write!(f, "allowed")
+        }
+    }
+}
+
+/// The architecture qualifier of a relation: "pkg:any", "pkg:native" or "pkg:i386". Unqualified relations have none.
+#[derive(Clone,PartialEq,Eq,Debug,Serialize,Deserialize)]
+pub enum ArchQualifier {
+    Any,
+    Native,
+    Arch(String)
+}
+
+impl FromStr for ArchQualifier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err(String::from("empty architecture qualifier")),
+            "any" => Ok(ArchQualifier::Any),
+            "native" => Ok(ArchQualifier::Native),
+            arch => Ok(ArchQualifier::Arch(arch.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for ArchQualifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchQualifier::Any => write!(f, "any"),
+            ArchQualifier::Native => write!(f, "native"),
+            ArchQualifier::Arch(arch) => write!(f, "{}", arch)
+        }
+    }
+}
+
+/// Does the Debian architecture arch match pattern, which is an architecture or a wildcard such as any, linux-any or
+/// any-amd64? Architectures without an OS part (amd64) run on Linux.
+pub fn arch_matches_wildcard(arch: &str, pattern: &str) -> bool {
+    if pattern == "any" || pattern == arch { return true }
+    let split = |a| match a {
+        "any" => ("any", "any"),
+        a => a.split_once('-').unwrap_or(("linux", a))
+    };
+    let ((os, cpu), (pattern_os, pattern_cpu)) = (split(arch), split(pattern));
+    (pattern_os == "any" || pattern_os == os) && (pattern_cpu == "any" || pattern_cpu == cpu)
+}
+
+/// One architecture of a package in the status file. Multi-Arch: same packages have a stanza per installed
+/// architecture; the version is that of the stanza, even if the package is not (fully) installed. Each architecture
+/// has its own version and relations: libc6:i386 may lag behind libc6:amd64 during an upgrade.
+#[derive(Serialize,Deserialize)]
+pub struct InstalledInstance {
+    pub arch : Option<String>,
+    pub multi_arch : MultiArch,
+    pub version : Option<String>,
+    // the version, parsed
+    pub debver : Option<DebianVersionNum>,
+    pub status : PackageStatus,
+    pub source : SourceRef,
+    // the relations the stanza declares, by kind; only kept for fully installed instances
+    pub relations : Vec<(RelationKind, Vec<Dependency>)>
+}
+
+impl InstalledInstance {
+    /// The relations of the given kind that this instance declares.
+    pub fn get_relations(&self, kind: RelationKind) -> &[Dependency] {
+        match self.relations.iter().find(|(k, _)| *k == kind) {
+            None => &[],
+            Some((_, deps)) => deps
+        }
+    }
+}
+
+impl Packages {
+    /// The architecture of this system: that of the installed dpkg, or else the first one seen in a Packages file.
+    pub fn native_arch(&self) -> Option<&str> {
+        self.native_arch.as_deref()
+    }
+
+    // what an architecture stands for when matching: Architecture: all (or no architecture) means native
+    fn effective_arch<'a>(&'a self, arch: Option<&'a str>) -> Option<&'a str> {
+        match arch {
+            None | Some("all") => self.native_arch(),
+            arch => arch
+        }
+    }
+
+    /// Can a version of architecture arch be installed (as opposed to needing a foreign architecture)?
+    pub fn is_native_arch(&self, arch: Option<&str>) -> bool {
+        match (self.effective_arch(arch), self.native_arch()) {
+            (Some(a), Some(native)) => a == native,
+            _ => true
+        }
+    }
+
+    /// Does a package of architecture arch with the given Multi-Arch field satisfy relation, when the relation is
+    /// declared by a package of architecture from_arch (None meaning native)? Versions are not considered here.
+    /// Without architecture information on either side, any architecture matches.
+    pub fn arch_satisfies(&self, relation: &RelVersionedPackageNum, from_arch: Option<&str>, arch: Option<&str>, multi_arch: MultiArch) -> bool {
+        let (from, target) = (self.effective_arch(from_arch), self.effective_arch(arch));
+        let same = match (from, target) {
+            (Some(f), Some(t)) => f == t,
+            _ => true
+        };
+        match &relation.arch {
+            None => same || multi_arch == MultiArch::Foreign,
+            Some(ArchQualifier::Any) => same || matches!(multi_arch, MultiArch::Foreign | MultiArch::Allowed),
+            Some(ArchQualifier::Native) => self.is_native_arch(arch),
+            Some(ArchQualifier::Arch(a)) => target.is_none_or(|t| t == a)
+        }
+    }
+
+    /// The fully installed architectures of package_num that satisfy the architecture part of relation, declared by
+    /// a package of architecture from_arch.
+    pub(super) fn installed_arch_satisfiers<'a>(&'a self, relation: &'a RelVersionedPackageNum, from_arch: Option<&'a str>, package_num: u32)
+        -> impl Iterator<Item = &'a InstalledInstance> + 'a {
+        self.get_installed_instances(package_num).iter()
+            .filter(move |i| i.status.is_installed() && self.arch_satisfies(relation, from_arch, i.arch.as_deref(), i.multi_arch))
+    }
+
+    /// The architecture of the candidate of package_num, which declares the relations that get_relations returns.
+    pub fn candidate_arch(&self, package_num: u32) -> Option<&str> {
+        self.get_candidate(package_num).and_then(|c| c.architecture.as_deref())
+    }
+
+    pub fn get_installed_instances(&self, package_num: u32) -> &[InstalledInstance] {
+        match self.installed_instances.get(package_num) {
+            None => &[],
+            Some(instances) => instances
+        }
+    }
+
+    /// The instance that stands for package_num where packages count rather than architectures (its installed
+    /// version, and the relations the planners follow): the fully installed one of the native architecture, or else
+    /// the first fully installed one.
+    pub fn primary_instance(&self, package_num: u32) -> Option<&InstalledInstance> {
+        let mut installed = self.get_installed_instances(package_num).iter().filter(|i| i.status.is_installed() && i.debver.is_some());
+        let first = installed.clone().next();
+        installed.find(|i| self.is_native_arch(i.arch.as_deref())).or(first)
+    }
+
+    /// Records an instance from the status file, replacing an earlier one of the same architecture.
+    pub(super) fn add_installed_instance(&mut self, package_num: u32, instance: InstalledInstance) {
+        let instances = self.installed_instances.entry(package_num);
+        match instances.iter_mut().find(|i| i.arch == instance.arch) {
+            Some(existing) => *existing = instance,
+            None => instances.push(instance)
+        }
+    }
+
+    /// "libc6" for native and Architecture: all packages, "libc6:i386" for the others; None if no package has that
+    /// number.
+    pub fn qualified_name(&self, package_num: u32, arch: Option<&str>) -> Option<String> {
+        Some(self.qualify(self.interner.get(package_num)?, arch))
+    }
+
+    // qualified_name, given the package name
+    pub(super) fn qualify(&self, name: &str, arch: Option<&str>) -> String {
+        match arch {
+            Some(a) if !self.is_native_arch(Some(a)) => format!("{}:{}", name, a),
+            _ => name.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packages::RelationKind;
+    use crate::packages::testutil::{load, num};
+
+    fn installed(name: &str, arch: &str, multi_arch: &str, version: &str, depends: &str) -> String {
+        format!("Package: {}\nStatus: install ok installed\nArchitecture: {}\nMulti-Arch: {}\nVersion: {}\nDepends: {}\n\n",
+                name, arch, multi_arch, version, depends)
+    }
+
+    // each broken instance, qualified, with the relations it does not meet
+    fn broken(status: &str) -> Vec<(String, Vec<String>)> {
+        let packages = load("", status);
+        packages.check_installed().iter().map(|b| (packages.qualified_name(b.package_num, b.arch).unwrap(),
+            b.unmet.iter().map(|u| packages.dep2str(u.relation).unwrap()).collect())).collect()
+    }
+
+    const DPKG : &str = "Package: dpkg\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1.21\n\n";
+
+    #[test]
+    fn foreign_packages_satisfy_every_architecture() {
+        let status = [DPKG, &installed("make", "amd64", "foreign", "4.3", "dpkg"), &installed("libz", "amd64", "no", "1.3", "dpkg"),
+                      &installed("app", "i386", "no", "1.0", "make (>= 4), libz")].concat();
+        assert_eq!(broken(&status), [(String::from("app:i386"), vec![String::from("libz")])]);
+        let packages = load("", &status);
+        let depends = packages.get_installed_relations(RelationKind::Depends, num(&packages, "app"));
+        assert_eq!(packages.dep_is_satisfied_2(&depends[0], Some("i386")), Some(num(&packages, "make")));
+        assert_eq!(packages.dep_is_satisfied_2(&depends[1], Some("i386")), None);
+        // from a native package, libz would do
+        assert_eq!(packages.dep_is_satisfied_2(&depends[1], None), Some(num(&packages, "libz")));
+        assert_eq!(packages.dep_is_satisfied_2(&depends[1], Some("amd64")), Some(num(&packages, "libz")));
+    }
+
+    #[test]
+    fn any_qualifier_needs_allowed() {
+        let status = [DPKG, &installed("python3", "amd64", "allowed", "3.11", "dpkg"), &installed("perl", "amd64", "no", "5.36", "dpkg"),
+                      &installed("plugin", "i386", "same", "1.0", "python3:any (>= 3.9)"), &installed("script", "i386", "no", "1.0", "perl:any"),
+                      &installed("binding", "i386", "no", "1.0", "python3")].concat();
+        assert_eq!(broken(&status), [(String::from("binding:i386"), vec![String::from("python3")]),
+                                     (String::from("script:i386"), vec![String::from("perl:any")])]);
+    }
+
+    #[test]
+    fn same_package_at_two_versions() {
+        // the i386 instance comes first, and lags behind
+        let status = [DPKG, &installed("libfoo", "i386", "same", "1.0", "libc6-i386"), &installed("libfoo", "amd64", "same", "2.0", "dpkg"),
+                      &installed("app", "amd64", "no", "1.0", "libfoo (>= 2)"), &installed("app32", "i386", "no", "1.0", "libfoo (>= 2)")].concat();
+        let packages = load("", &status);
+        let libfoo = num(&packages, "libfoo");
+        let instances : Vec<(Option<&str>, String)> = packages.get_installed_instances(libfoo).iter()
+            .map(|i| (i.arch.as_deref(), i.debver.as_ref().unwrap().to_string())).collect();
+        assert_eq!(instances, [(Some("i386"), String::from("1.0")), (Some("amd64"), String::from("2.0"))]);
+        // the native instance stands for the package, with its own relations
+        assert_eq!(packages.installed_debver(libfoo).unwrap().to_string(), "2.0");
+        assert_eq!(packages.deps2str(packages.get_installed_relations(RelationKind::Depends, libfoo)).unwrap(), "dpkg");
+        assert_eq!(packages.installed_count(), 4);
+        let report = packages.check_installed();
+        let mut unmet : Vec<(String, String, Vec<String>)> = vec![];
+        for b in &report {
+            for u in &b.unmet {
+                let wrong_versions = u.wrong_versions.iter().map(|(p, v)| format!("{} {}", packages.get_package_name(*p).unwrap(), v)).collect();
+                unmet.push((packages.qualified_name(b.package_num, b.arch).unwrap(), packages.dep2str(u.relation).unwrap(), wrong_versions));
+            }
+        }
+        assert_eq!(unmet, [(String::from("app32:i386"), String::from("libfoo (>= 2)"), vec![String::from("libfoo 1.0")]),
+                           (String::from("libfoo:i386"), String::from("libc6-i386"), vec![])]);
+    }
+}
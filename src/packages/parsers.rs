@@ -1,141 +1,497 @@
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::Path;
 
-use itertools::assert_equal;
+use flate2::bufread::MultiGzDecoder;
+use rayon::prelude::*;
 use regex::Regex;
+use xz2::bufread::XzDecoder;
 
 use crate::Packages;
-use crate::packages::RelVersionedPackageNum;
+use crate::packages::{Dependency, RelVersionedPackageNum, RelationKind};
+use crate::packages::status::PackageStatus;
+use crate::packages::multiarch::{ArchQualifier, InstalledInstance, MultiArch};
+use crate::packages::providers::Provided;
+use crate::packages::repository::AvailableVersion;
+use crate::packages::release::checked_release;
+use crate::packages::sources::SourceRef;
+use crate::packages::errors::{LoadError, LoadReport, Stanza};
+use crate::packages::mapped::{IndexStorage, StanzaText, Text};
 
-use rpkg::debversion::{self, DebianVersionNum};
+use crate::debversion;
+use crate::deb822::{Paragraph, ParagraphReader};
 
-const KEYVAL_REGEX : &str = r"(?P<key>(\w|-)+): (?P<value>.+)";
-const PKGNAME_AND_VERSION_REGEX : &str = r"(?P<pkg>(\w|\.|\+|-)+)( \((?P<op>(<|=|>)(<|=|>)?) (?P<ver>.*)\))?";
+// "python3:any (>= 3.6~)": package name, optional architecture qualifier, optional version constraint
+// (the whole alternative, so that "(>= 1) foo", "foo bar" and "foo (>= 1" are errors rather than read in part)
+pub(super) const PKGNAME_AND_VERSION_REGEX : &str = r"^(?P<pkg>[\w.+-]+)(:(?P<arch>[\w-]+))?(\s*\(\s*(?P<op>[<=>]{1,2})\s*(?P<ver>[^)]*)\))?$";
 
 impl Packages {
-    /// Loads packages and version numbers from a file, calling get_package_num_inserting on the package name
-    /// and inserting the appropriate value into the installed_debvers map with the parsed version number.
-    pub fn parse_installed(&mut self, filename: &str) {
-        let kv_regexp = Regex::new(KEYVAL_REGEX).unwrap();
-        if let Ok(lines) = read_lines(filename) {
-            let mut current_package_num = 0;
-            for line in lines {
-                if let Ok(ip) = line {
-                    // do something with ip
-                    match kv_regexp.captures(&ip) {
-                        None => (),
-                        Some(caps) => {
-                            let (key, value) = (caps.name("key").unwrap().as_str(), caps.name("value").unwrap().as_str());
-                            if key == "Package" {
-                                current_package_num = self.get_package_num_inserting(&value);
-                            } else if key == "Version" {
-                                let debver = value.trim().parse::<debversion::DebianVersionNum>().unwrap();
-                                self.installed_debvers.insert(current_package_num, debver);
-                            }
-                        }
-                    }
-                }
+    /// Loads packages and version numbers from a file, calling get_package_num_inserting on the package name.
+    /// Every stanza, with its dpkg Status, Architecture, Multi-Arch and parsed version, goes into installed_instances
+    /// (one per architecture), but only fully installed ones count as installed (and have their relations recorded with
+    /// them, and their Provides in installed_providers). The whole stanza is kept in installed_paragraphs.
+    /// The architecture of the installed dpkg is the native architecture.
+    pub fn parse_installed(&mut self, filename: &str) -> Result<LoadReport, LoadError> {
+        let pkgver_regexp = Regex::new(PKGNAME_AND_VERSION_REGEX).unwrap();
+
+        let paragraphs = read_paragraphs(filename).map_err(|e| LoadError::io(filename, e))?;
+        let mut report = LoadReport::new(filename);
+        self.record_loaded_file(filename, &mut report.warnings);
+        report.load_paragraphs(self.load_mode, paragraphs, |para, warnings| self.load_installed_stanza(&pkgver_regexp, filename, para, warnings))?;
+        Ok(report)
+    }
+
+    // one stanza of a status file; nothing is recorded unless the whole stanza parses
+    fn load_installed_stanza(&mut self, pkgver_regexp: &Regex, filename: &str, para: Paragraph, warnings: &mut Vec<String>) -> Result<(), String> {
+        let name = para.get("Package").ok_or("no Package field")?;
+        let version = para.get("Version").map(|v| v.parse::<debversion::DebianVersionNum>()).transpose().map_err(|e| e.to_string())?;
+        let status = match para.get("Status").map(|s| s.parse::<PackageStatus>()) {
+            None => PackageStatus::INSTALLED,
+            Some(Ok(status)) => status,
+            Some(Err(e)) => {
+                warnings.push(format!("{}: line {}: {}; assuming installed", filename, para.line(), e));
+                PackageStatus::INSTALLED
             }
+        };
+        // names are only interned once the stanza is known to parse, so a rejected one leaves none behind
+        let (relations, provides) = if status.is_installed() {
+            let provides = para.get_folded("Provides").map(|provides| parse_dependencies_text(pkgver_regexp, &provides)).transpose()?;
+            (parse_relation_fields_text(pkgver_regexp, &para)?, provides)
+        } else {
+            (vec![], None)
+        };
+        let provides = provides.map(|provides| Provided::from_dependencies(self.intern_dependencies(provides)));
+        let relations = relations.into_iter().map(|(kind, deps)| (kind, self.intern_dependencies(deps))).collect();
+        let package_num = self.get_package_num_inserting(name);
+        self.clear_candidates();
+        let source = SourceRef::of_paragraph(name, &para);
+        self.add_source_binary(&source.name, package_num);
+        let arch = para.get("Architecture").map(String::from);
+        let multi_arch = self.parse_multi_arch(filename, para.line(), para.get("Multi-Arch"), warnings);
+        if status.is_installed() && name == "dpkg" {
+            self.native_arch = arch.clone();
         }
-        println!("Packages installed: {}", self.installed_debvers.keys().len());
+        self.add_installed_instance(package_num, InstalledInstance {
+            arch, multi_arch, version : para.get("Version").map(String::from), debver : version, status, source, relations
+        });
+        if let Some(provides) = provides {
+            Packages::record_provides(&mut self.installed_providers, package_num, provides);
+        }
+        let paragraphs = self.installed_paragraphs.entry(package_num);
+        match paragraphs.iter_mut().find(|p| p.get("Architecture") == para.get("Architecture")) {
+            Some(existing) => *existing = para,
+            None => paragraphs.push(para)
+        }
+        Ok(())
+    }
+
+    /// Loads packages, version numbers, relations, and md5sums from a file, calling get_package_num_inserting on the package name
+    /// and adding an AvailableVersion (which also keeps Provides and the whole stanza) for each stanza to the repository.
+    /// Versions already loaded from other files are kept, so several indices (e.g. stable and backports) can be combined.
+    /// Each version records the file it came from, described by the archive's Release file if release_filename is given.
+    /// In that case the file must match its size and hash in the Release file, or it is not loaded.
+    /// How the file is loaded depends on the index storage setting: see parse_packages_parallel and
+    /// parse_packages_mapped, which give the same result.
+    pub fn parse_packages(&mut self, filename: &str, release_filename: Option<&str>) -> Result<LoadReport, LoadError> {
+        match self.index_storage {
+            IndexStorage::Owned => self.parse_packages_parallel(filename, release_filename),
+            IndexStorage::Mapped => self.parse_packages_mapped(filename, release_filename)
+        }
+    }
+
+    /// Like parse_packages, parsing stanzas on a pool of threads; the result is the same as that of
+    /// parse_packages_sequential.
+    pub fn parse_packages_parallel(&mut self, filename: &str, release_filename: Option<&str>) -> Result<LoadReport, LoadError> {
+        self.parse_packages_with(filename, release_filename, true)
+    }
+
+    /// Like parse_packages, parsing one stanza after the other on this thread.
+    pub fn parse_packages_sequential(&mut self, filename: &str, release_filename: Option<&str>) -> Result<LoadReport, LoadError> {
+        self.parse_packages_with(filename, release_filename, false)
     }
 
-    /// Loads packages, version numbers, dependencies, and md5sums from a file, calling get_package_num_inserting on the package name
-    /// and inserting the appropriate values into the dependencies, md5sum, and available_debvers maps.
-    pub fn parse_packages(&mut self, filename: &str) {
-        let kv_regexp = Regex::new(KEYVAL_REGEX).unwrap();
+    fn parse_packages_with(&mut self, filename: &str, release_filename: Option<&str>, parallel: bool) -> Result<LoadReport, LoadError> {
         let pkgver_regexp = Regex::new(PKGNAME_AND_VERSION_REGEX).unwrap();
 
-        if let Ok(lines) = read_lines(filename) {
-            let mut current_package_num = 0;
-            for line in lines {
-                if let Ok(ip) = line {
-                    match kv_regexp.captures(&ip) {
-                        None => (),
-                        Some(caps) => {
-                            let (key, value) = (caps.name("key").unwrap().as_str(), caps.name("value").unwrap().as_str());
-                            if key == "Package" {
-                                current_package_num = self.get_package_num_inserting(&value);
-                            } else if key == "Version" {
-                                let debver = value.trim().parse::<debversion::DebianVersionNum>().unwrap();
-                                self.available_debvers.insert(current_package_num, debver);
-                            } else if key == "MD5sum" {
-                                self.md5sums.insert(current_package_num, value.to_string());
-                            } else if key == "Depends" {
-                                // Split the string based on commas
-                                let parts: Vec<&str> = value.split(',').map(|s| s.trim()).collect();
-
-                                // Filter out empty strings from the split
-                                let non_empty_parts: Vec<&str> = parts.into_iter().filter(|&s| !s.is_empty()).collect();
-
-                                let mut dependency_vect: Vec<Vec<RelVersionedPackageNum>> = Vec::new();
-
-                                for element in &non_empty_parts {
-                                    let mut alternatives_vect: Vec<RelVersionedPackageNum> = Vec::new();
-                                    // Split the string based on |
-                                    let alternatives: Vec<&str> = element.split('|').map(|s| s.trim()).collect();
-
-                                    for alternative in &alternatives {
-                                        // Regex on Alternative to parse op, pkg, version
-                                        match pkgver_regexp.captures(&alternative) {
-                                            None => (),
-                                            Some(caps) => {
-                                                // Parse name, op, ver to create RelVersionedPackageNum
-                                                let package_name = caps.name("pkg").expect("Package name not found in regex match").as_str();
-                                                let rel_version = caps.name("op").map(|op| {
-                                                    (
-                                                        op.as_str().parse::<debversion::VersionRelation>().expect("Error parsing version relation"),
-                                                        caps.name("ver").map_or_else(|| "".to_string(), |ver| ver.as_str().to_string()),
-                                                    )
-                                                });
-                                                let package_num = self.get_package_num_inserting(&package_name);
-                                                let final_package = RelVersionedPackageNum {
-                                                    package_num,
-                                                    rel_version,
-                                                };
-                                                alternatives_vect.push(final_package);
-                                            }
-                                        }
-                                    }
-
-                                    dependency_vect.push(alternatives_vect);
-                                }
-                                self.dependencies.insert(current_package_num, dependency_vect);
-                                
-                                // TEST TO SEE OUTPUT OF DEPENDENCIES
-                                // println!("******************DEPENDENCIES:");
-                                // for alternatives_vect in &dependency_vect {
-                                //     println!("****NEW DEPENDENCY****");
-                                //     for package in alternatives_vect {
-                                //         if alternatives_vect.len() > 1{
-                                //             println!("////////////--------------//////-------||||")
-                                //         }
-                                //         // Print information about each RelVersionedPackageNum
-                                //         println!("Package Num: {}", package.package_num);
-                                
-                                //         if let Some((op, ver)) = &package.rel_version {
-                                //             println!("  Version Relation: {}", op);
-                                //             println!("  Version: {}", ver);
-                                //         } else {
-                                //             println!("  Version information not available");
-                                //         }
-                                //     }
-                                // }
-                            }
-                        }
-                    }
-                }
+        let mut report = LoadReport::new(filename);
+        // the file is read once: checked against the Release file, then decompressed from memory
+        let raw = fs::read(filename).map_err(|e| LoadError::io(filename, e))?;
+        let release = checked_release(filename, &raw, release_filename, &mut report.warnings)?;
+        // a file that cannot be decompressed in full (e.g. a damaged compressed index) is parsed as it is decompressed,
+        // so that the stanzas before the damage are loaded as usual
+        let stanzas : Box<dyn Iterator<Item = io::Result<ParsedStanza>>> = match parallel.then(|| read_to_end(filename, &raw).ok()).flatten() {
+            // every chunk holds whole stanzas, and results are put back in file order before anything is loaded
+            Some(contents) => Box::new(stanza_chunks(&contents).par_iter()
+                .map(|(lines_before, chunk)| ParagraphReader::with_line_offset(*chunk, *lines_before)
+                    .map(|para| para.map(|p| ParsedStanza::parse(&pkgver_regexp, p))).collect::<Vec<_>>())
+                .collect::<Vec<_>>().into_iter().flatten()),
+            None => {
+                let paragraphs = ParagraphReader::new(decompress(filename, &raw[..]).map_err(|e| LoadError::io(filename, e))?);
+                Box::new(paragraphs.map(|para| para.map(|p| ParsedStanza::parse(&pkgver_regexp, p))))
             }
+        };
+        self.record_loaded_file(filename, &mut report.warnings);
+        if let Some(r) = release_filename { self.record_loaded_file(r, &mut report.warnings) }
+        let origin = self.add_origin(filename, release);
+        let mode = self.load_mode;
+        report.load_paragraphs(mode, stanzas, |stanza, warnings| self.load_available_stanza(filename, origin, stanza, warnings))?;
+        Ok(report)
+    }
+
+    // one stanza of a Packages file; nothing is recorded, not even the package names it mentions, unless the whole
+    // stanza parses. Names are interned in the same order whether the stanza was parsed here or on another thread.
+    fn load_available_stanza(&mut self, filename: &str, origin: usize, stanza: ParsedStanza, warnings: &mut Vec<String>) -> Result<(), String> {
+        let ParsedStanza { para, fields } = stanza;
+        let StanzaFields { version, relations, provides } = fields?;
+        let relations = relations.into_iter().map(|(kind, deps)| (kind, self.intern_dependencies(deps))).collect();
+        let provides = Provided::from_dependencies(self.intern_dependencies(provides));
+        let name = para.get("Package").unwrap();
+        let package_num = self.get_package_num_inserting(name);
+        let source = SourceRef::of_paragraph(name, &para);
+        let md5sum = para.get("MD5sum").map(Text::from);
+        let architecture = para.get("Architecture").map(String::from);
+        let multi_arch = self.parse_multi_arch(filename, para.line(), para.get("Multi-Arch"), warnings);
+        self.record_available_version(package_num, source, AvailableVersion {
+            version, md5sum, architecture, multi_arch, relations, provides, paragraph : StanzaText::Owned(para), origins : vec![origin]
+        }, warnings);
+        Ok(())
+    }
+
+    // adds a version loaded from a Packages stanza, which also tells the source package and the native architecture
+    pub(super) fn record_available_version(&mut self, package_num: u32, source: SourceRef, available: AvailableVersion, warnings: &mut Vec<String>) {
+        self.add_source_binary(&source.name, package_num);
+        if self.native_arch.is_none() && available.architecture.as_deref().is_some_and(|a| a != "all") {
+            self.native_arch = available.architecture.clone();
+        }
+        self.add_available_version(package_num, available, warnings);
+    }
+
+    // the Multi-Arch field of the stanza at line, "no" if absent or bad (with a warning)
+    pub(super) fn parse_multi_arch(&self, filename: &str, line: usize, value: Option<&str>, warnings: &mut Vec<String>) -> MultiArch {
+        match value.map(|m| m.parse::<MultiArch>()) {
+            None => MultiArch::No,
+            Some(Ok(m)) => m,
+            Some(Err(e)) => { warnings.push(format!("{}: line {}: {}", filename, line, e)); MultiArch::No }
+        }
+    }
+
+    // interns the package names of deps, in order
+    fn intern_dependencies(&mut self, deps: Vec<DependencyText>) -> Vec<Dependency> {
+        deps.into_iter().map(|dep| dep.into_iter().map(|relation| self.intern_relation(relation)).collect()).collect()
+    }
+
+    pub(super) fn intern_relation(&mut self, relation: RelationText) -> RelVersionedPackageNum {
+        let RelationText { package_name, rel_version, arch } = relation;
+        let rel_version = rel_version.map(|(op, version)| (op, Text::from(version)));
+        RelVersionedPackageNum { package_num : self.get_package_num_inserting(&package_name), rel_version, arch }
+    }
+}
+
+/// One alternative of a relation as written, before its package name is interned.
+pub(super) struct RelationText {
+    package_name : String,
+    rel_version : Option<(debversion::VersionRelation, String)>,
+    arch : Option<ArchQualifier>
+}
+type DependencyText = Vec<RelationText>;
+
+// what a Packages stanza holds besides its paragraph, parsed without touching Packages
+struct StanzaFields {
+    version : debversion::DebianVersionNum,
+    // in the order of RelationKind::ALL, which is the order their names are interned in
+    relations : Vec<(RelationKind, Vec<DependencyText>)>,
+    provides : Vec<DependencyText>
+}
+
+/// A stanza of a Packages file, parsed as far as possible without Packages so that stanzas can be parsed on several
+/// threads at once; load_available_stanza interns its names and records it.
+struct ParsedStanza {
+    para : Paragraph,
+    // why the stanza is malformed, if it is
+    fields : Result<StanzaFields, String>
+}
+
+impl ParsedStanza {
+    fn parse(pkgver_regexp: &Regex, para: Paragraph) -> ParsedStanza {
+        let fields = StanzaFields::parse(pkgver_regexp, &para);
+        ParsedStanza { para, fields }
+    }
+}
+
+impl Stanza for ParsedStanza {
+    fn line(&self) -> usize {
+        self.para.line()
+    }
+
+    fn package(&self) -> Option<String> {
+        self.para.get("Package").map(String::from)
+    }
+}
+
+impl StanzaFields {
+    fn parse(pkgver_regexp: &Regex, para: &Paragraph) -> Result<StanzaFields, String> {
+        para.get("Package").ok_or("no Package field")?;
+        let version = para.get("Version").ok_or("no Version field")?.parse::<debversion::DebianVersionNum>().map_err(|e| e.to_string())?;
+        let relations = parse_relation_fields_text(pkgver_regexp, para)?;
+        let provides = match para.get_folded("Provides") {
+            None => vec![],
+            Some(provides) => parse_dependencies_text(pkgver_regexp, &provides)?
+        };
+        Ok(StanzaFields { version, relations, provides })
+    }
+}
+
+// the relation fields present in para, in the order of RelationKind::ALL
+fn parse_relation_fields_text(pkgver_regexp: &Regex, para: &Paragraph) -> Result<Vec<(RelationKind, Vec<DependencyText>)>, String> {
+    let mut result = vec![];
+    for kind in RelationKind::ALL.iter() {
+        if let Some(value) = para.get_folded(kind.field_name()) {
+            let deps = parse_dependencies_text(pkgver_regexp, &value).map_err(|e| format!("{}: {}", kind.field_name(), e))?;
+            result.push((*kind, deps));
+        }
+    }
+    Ok(result)
+}
+
+fn parse_dependencies_text(pkgver_regexp: &Regex, value: &str) -> Result<Vec<DependencyText>, String> {
+    value.split(',').map(|s| s.trim()).filter(|s| !s.is_empty())
+        .map(|element| element.split('|').map(|s| s.trim())
+            .map(|alternative| parse_relation_text(pkgver_regexp, alternative)).collect())
+        .collect()
+}
+
+/// Parses one alternative of a relation field, such as "python3:any (>= 3.6~)". The version is checked here, so that
+/// later comparisons can rely on it.
+pub(super) fn parse_relation_text(pkgver_regexp: &Regex, alternative: &str) -> Result<RelationText, String> {
+    // Regex on alternative to parse op, pkg, version
+    let caps = pkgver_regexp.captures(alternative).ok_or_else(|| format!("bad relation {:?}", alternative))?;
+    let package_name = caps.name("pkg").expect("Package name not found in regex match").as_str().to_string();
+    let rel_version = match caps.name("op") {
+        None => None,
+        Some(op) => {
+            let op = op.as_str().parse::<debversion::VersionRelation>().map_err(|e| format!("{} in {:?}", e, alternative))?;
+            let version = caps.name("ver").map_or("", |ver| ver.as_str().trim());
+            version.parse::<debversion::DebianVersionNum>().map_err(|e| format!("{} in {:?}", e, alternative))?;
+            Some((op, version.to_string()))
         }
-        println!("Packages available: {}", self.available_debvers.keys().len());
+    };
+    let arch = caps.name("arch").map(|a| a.as_str().parse::<ArchQualifier>()).transpose().map_err(|e| format!("{} in {:?}", e, alternative))?;
+    Ok(RelationText { package_name, rel_version, arch })
+}
+
+// about this many chunks per thread, so that threads that finish early can take over the work of others
+const CHUNKS_PER_THREAD : usize = 8;
+
+// contents split into chunks of whole stanzas, each with the number of lines before it; chunks end after a blank line
+// (nothing but whitespace, so "\r" too), which always ends a stanza
+fn stanza_chunks(contents: &[u8]) -> Vec<(usize, &[u8])> {
+    let target = contents.len() / (rayon::current_num_threads() * CHUNKS_PER_THREAD) + 1;
+    let mut chunks = vec![];
+    let (mut start, mut lines_before) = (0, 0);
+    while start < contents.len() {
+        let end = blank_line_end(contents, (start + target).min(contents.len())).unwrap_or(contents.len());
+        let chunk = &contents[start..end];
+        chunks.push((lines_before, chunk));
+        lines_before += chunk.iter().filter(|&&c| c == b'\n').count();
+        start = end;
     }
+    chunks
 }
 
+// the end of the first blank line after the line holding contents[from], if there is one
+fn blank_line_end(contents: &[u8], from: usize) -> Option<usize> {
+    let mut line_start = from + contents[from..].iter().position(|&c| c == b'\n')? + 1;
+    loop {
+        let len = contents[line_start..].iter().position(|&c| c == b'\n')?;
+        if contents[line_start..line_start + len].iter().all(u8::is_ascii_whitespace) {
+            return Some(line_start + len + 1);
+        }
+        line_start += len + 1;
+    }
+}
+
+// the whole decompressed contents of filename, given as read
+fn read_to_end(filename: &str, raw: &[u8]) -> io::Result<Vec<u8>> {
+    let mut contents = vec![];
+    decompress(filename, raw)?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
 
-// standard template code downloaded from the Internet somewhere
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
+pub(super) const GZIP_MAGIC : &[u8] = &[0x1f, 0x8b];
+pub(super) const XZ_MAGIC : &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0];
+
+/// Opens filename for reading, decompressing gzip and xz files (like Packages.gz and Packages.xz on mirrors) as they
+/// are read. The format comes from the magic bytes at the start of the file; a .gz or .xz file without them is an error.
+pub(super) fn open_decompressed<P>(filename: P) -> io::Result<Box<dyn BufRead>>
 where P: AsRef<Path>, {
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+    let file = BufReader::new(File::open(filename.as_ref())?);
+    decompress(filename, file)
+}
+
+/// Like open_decompressed, for the contents of filename read from file.
+pub(super) fn decompress<'a, P, R>(filename: P, mut file: R) -> io::Result<Box<dyn BufRead + 'a>>
+where P: AsRef<Path>, R: BufRead + 'a, {
+    let magic = file.fill_buf()?;
+    let (gzip, xz) = (magic.starts_with(GZIP_MAGIC), magic.starts_with(XZ_MAGIC));
+    match filename.as_ref().extension().and_then(|e| e.to_str()) {
+        Some("gz") if !gzip => return Err(io::Error::new(io::ErrorKind::InvalidData, "not in gzip format")),
+        Some("xz") if !xz => return Err(io::Error::new(io::ErrorKind::InvalidData, "not in xz format")),
+        _ => ()
+    }
+    if gzip {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+    } else if xz {
+        Ok(Box::new(BufReader::new(XzDecoder::new_multi_decoder(file))))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+pub(super) fn read_paragraphs<P>(filename: P) -> io::Result<ParagraphReader<Box<dyn BufRead>>>
+where P: AsRef<Path>, {
+    Ok(ParagraphReader::new(open_decompressed(filename)?))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packages::LoadMode;
+    use crate::packages::testutil::{gzip, load, num, xz, TempFile};
+
+    fn parse(alternative: &str) -> Result<RelationText, String> {
+        parse_relation_text(&Regex::new(PKGNAME_AND_VERSION_REGEX).unwrap(), alternative)
+    }
+
+    #[test]
+    fn relation_parts() {
+        let r = parse("python3:any (>= 3.6~)").unwrap();
+        assert_eq!(r.package_name, "python3");
+        assert_eq!(r.arch, Some(ArchQualifier::Any));
+        let (op, version) = r.rel_version.unwrap();
+        assert_eq!((op.to_string(), version.as_str()), (String::from(">="), "3.6~"));
+        let r = parse("libc6( << 2.36 )").unwrap();
+        assert_eq!((r.package_name.as_str(), r.rel_version.unwrap().1.as_str()), ("libc6", "2.36"));
+        let r = parse("g++-12:i386").unwrap();
+        assert_eq!((r.package_name.as_str(), r.arch, r.rel_version.is_none()), ("g++-12", Some(ArchQualifier::Arch(String::from("i386"))), true));
+    }
+
+    #[test]
+    fn malformed_relations_are_errors() {
+        for alternative in ["(>= 1) foo", "foo bar baz", "foo (>= 1", "foo (>= 1) bar", "foo:", "foo:(>= 1)", "foo ()", "foo (1)", ""] {
+            assert!(parse(alternative).is_err(), "{:?} parsed", alternative);
+        }
+        assert_eq!(parse("foo (=> 2)").err().unwrap(), "bad version relation \"=>\" in \"foo (=> 2)\"");
+    }
+
+    #[test]
+    fn installed_relations() {
+        let packages = load("", "Package: a\nStatus: install ok installed\nVersion: 1\nDepends: b:any (>= 2), c | d\n");
+        let depends = packages.get_installed_relations(RelationKind::Depends, num(&packages, "a"));
+        assert_eq!(depends.len(), 2);
+        assert_eq!(depends[0][0].rel_version().map(|(op, v)| (op.to_string(), v)), Some((String::from(">="), "2")));
+        assert_eq!(depends[1].iter().map(|r| packages.get_package_name(r.package_num()).unwrap()).collect::<Vec<_>>(), ["c", "d"]);
+    }
+
+    #[test]
+    fn malformed_relation_skips_the_stanza() {
+        let index = TempFile::new("Package: a\nVersion: 1\nDepends: b (>= 1) c\n\nPackage: d\nVersion: 1\nDepends: b\n");
+        let mut packages = Packages::new();
+        let report = packages.parse_packages(index.path(), None).unwrap();
+        assert_eq!(report.loaded, 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].line, Some(1));
+        assert!(packages.get_available_versions(num(&packages, "d")).len() == 1);
+        assert!(packages.package_num("a").is_none() && packages.package_num("c").is_none());
+    }
+
+    // Provides is parsed before the relation fields, but nothing is interned until all of them parse
+    #[test]
+    fn rejected_installed_stanza_leaves_no_names() {
+        let status = TempFile::new("Package: a\nStatus: install ok installed\nVersion: 1\nProvides: virtual\nDepends: b, c (>= 1) d\n\n\
+                                    Package: e\nStatus: install ok installed\nVersion: 1\n");
+        let mut packages = Packages::new();
+        let report = packages.parse_installed(status.path()).unwrap();
+        assert_eq!((report.loaded, report.skipped.len()), (1, 1));
+        assert_eq!(packages.get_package_names(), ["e"]);
+    }
+
+    // strict mode stops at the bad stanza without undoing what came before it
+    #[test]
+    fn strict_mode_keeps_stanzas_before_the_error() {
+        let index = TempFile::new("Package: a\nVersion: 1\nDepends: b\n\nPackage: c\nVersion: 1\nDepends: (>= 1) d\n\nPackage: e\nVersion: 1\n");
+        let mut packages = Packages::new();
+        packages.set_load_mode(LoadMode::Strict);
+        let error = packages.parse_packages(index.path(), None).err().unwrap();
+        assert_eq!((error.line, error.package.as_deref()), (Some(5), Some("c")));
+        assert_eq!(packages.get_available_versions(num(&packages, "a")).len(), 1);
+        assert!(packages.package_num("b").is_some());
+        assert!(packages.package_num("c").is_none() && packages.package_num("e").is_none());
+        assert_eq!(packages.loaded_files().len(), 1);
+    }
+
+    // a CRLF index with a malformed line and a stanza at the very end without a newline, long enough to be chunked
+    fn crlf_index() -> String {
+        let mut contents : String = (0..40).map(|i| format!("Package: p{}\r\nVersion: 1.{}\r\nDepends: p{} | q\r\n\r\n", i, i, i + 1)).collect();
+        contents.push_str("Package: broken\r\nnot a field\r\nVersion: 1\r\n\r\n");
+        contents.push_str("Package: last\r\nVersion: 2\r\nProvides: q");
+        contents
+    }
+
+    #[test]
+    fn chunks_end_at_crlf_blank_lines() {
+        let contents = crlf_index();
+        let chunks = stanza_chunks(contents.as_bytes());
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.iter().map(|(_, c)| c.len()).sum::<usize>(), contents.len());
+        for (lines_before, chunk) in &chunks[1..] {
+            assert!(chunk.starts_with(b"Package: "));
+            let offset = chunk.as_ptr() as usize - contents.as_ptr() as usize;
+            assert!(contents[..offset].ends_with("\r\n\r\n"));
+            assert_eq!(*lines_before, contents[..offset].matches('\n').count());
+        }
+    }
+
+    #[test]
+    fn parallel_load_matches_sequential() {
+        let index = TempFile::new(crlf_index());
+        let (mut sequential, mut parallel) = (Packages::new(), Packages::new());
+        let sequential_report = sequential.parse_packages_sequential(index.path(), None).unwrap();
+        let parallel_report = parallel.parse_packages_parallel(index.path(), None).unwrap();
+        let summary = |r: &LoadReport| (r.loaded, r.skipped.iter().map(|e| e.to_string()).collect::<Vec<_>>(), r.warnings.clone());
+        assert_eq!(sequential_report.loaded, 41);
+        assert_eq!(sequential_report.skipped.len(), 1);
+        assert_eq!(summary(&sequential_report), summary(&parallel_report));
+        assert_eq!(sequential.parse_digest(), parallel.parse_digest());
+    }
+
+    fn read_all(file: &TempFile) -> io::Result<String> {
+        let mut text = String::new();
+        open_decompressed(file.path())?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn decompression() {
+        let index = "Package: a\nVersion: 1\n\nPackage: b\nVersion: 2\n";
+        for file in [TempFile::with_extension(gzip(index.as_bytes()), ".gz"), TempFile::with_extension(xz(index.as_bytes()), ".xz"),
+                     TempFile::new(index)] {
+            assert_eq!(read_all(&file).unwrap(), index);
+        }
+        // the magic bytes decide, not the name
+        assert_eq!(read_all(&TempFile::new(gzip(index.as_bytes()))).unwrap(), index);
+        assert_eq!(read_all(&TempFile::with_extension(xz(index.as_bytes()), ".gz")).unwrap_err().to_string(), "not in gzip format");
+        assert_eq!(read_all(&TempFile::with_extension(index, ".xz")).unwrap_err().to_string(), "not in xz format");
+        // gzip members and xz streams that follow each other are read as one file
+        let (first, second) = index.split_at(20);
+        let gz = [gzip(first.as_bytes()), gzip(second.as_bytes())].concat();
+        assert_eq!(read_all(&TempFile::with_extension(gz, ".gz")).unwrap(), index);
+        let streams = [xz(first.as_bytes()), xz(second.as_bytes())].concat();
+        assert_eq!(read_all(&TempFile::with_extension(streams, ".xz")).unwrap(), index);
+        let compressed = TempFile::with_extension(xz(index.as_bytes()), ".xz");
+        assert_eq!(read_paragraphs(compressed.path()).unwrap().count(), 2);
+        // an empty file has no magic, so it is read as is
+        assert_eq!(read_all(&TempFile::new("")).unwrap(), "");
+    }
+
 }
@@ -0,0 +1,627 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::debversion::DebianVersionNum;
+
+use crate::Packages;
+use crate::packages::{Dependency, MultiArch, RelationKind, RelVersionedPackageNum};
+use crate::packages::preferences::DOWNGRADE_PRIORITY;
+use crate::packages::repository::AvailableVersion;
+use crate::packages::sat::{self, Lit, Sat};
+
+// solve_install plans an installation with a SAT solver. Each version a package could have afterwards (its installed
+// version, or an available version apt could pick) is a boolean variable, and the clauses say that:
+//   - the package asked for gets one of its versions;
+//   - a package has at most one version;
+//   - a chosen version has, for each relation of the followed kinds (only Pre-Depends and Depends for installed
+//     versions), a chosen version of some alternative that meets the version constraint, or of a provider of it;
+//   - no two chosen versions are in Conflicts or Breaks.
+// Installed packages are variables too, since the plan may have to upgrade or remove them: those the new versions
+// conflict with, and those whose relations involve the packages of the plan. Leaving out a version of an installed
+// package removes it. Relations that the installed system already violates are left alone, so that a broken system
+// does not make every plan remove things.
+//
+// The decisions follow apt: the package asked for (for plan_upgrade, the candidates of upgradable packages), the first
+// satisfying alternative of each relation, installed versions before candidates before other versions, keeping
+// installed packages, then leaving everything else out. Conflict-driven learning
+// backtracks when that fails, so the plan is always correct; if there is none, the clauses behind the proof
+// explain why.
+
+/// How how-to-install plans: greedily (compute_how_to_install, fast but unchecked) or with the SAT solver.
+#[derive(Clone,Copy,PartialEq,Eq,Debug,Default)]
+pub enum SolverMode {
+    #[default]
+    Sat,
+    Greedy
+}
+
+impl FromStr for SolverMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sat" => Ok(SolverMode::Sat),
+            "greedy" => Ok(SolverMode::Greedy),
+            _ => Err(format!("unknown solver {:?}: expected sat or greedy", s))
+        }
+    }
+}
+
+impl fmt::Display for SolverMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolverMode::Sat => write!(f, "sat"),
+            SolverMode::Greedy => write!(f, "greedy")
+        }
+    }
+}
+
+/// What a plan from solve_install does to one package.
+pub enum Change<'a> {
+    Install { package_num : u32, version : &'a AvailableVersion },
+    /// to another available version; newer, unless a pin of priority 1000 or more allows a downgrade
+    Upgrade { package_num : u32, from : &'a DebianVersionNum, to : &'a AvailableVersion },
+    Remove { package_num : u32, version : &'a DebianVersionNum }
+}
+
+impl Change<'_> {
+    pub fn package_num(&self) -> u32 {
+        match self {
+            Change::Install { package_num, .. } | Change::Upgrade { package_num, .. } | Change::Remove { package_num, .. } => *package_num
+        }
+    }
+}
+
+/// One of the constraints that together leave no plan for solve_install.
+#[derive(Clone)]
+pub enum Reason<'a> {
+    /// the package asked for has to be installed, in one of these versions (none if nothing can be installed); or,
+    /// for plan_upgrade without removals, an installed package has to stay installed
+    Install { package_num : u32, versions : Vec<&'a DebianVersionNum> },
+    /// a package cannot have both versions
+    OneVersion { package_num : u32, first : &'a DebianVersionNum, second : &'a DebianVersionNum },
+    /// a version (installed, or available) needs one of the alternatives of relation
+    Depends { package_num : u32, version : &'a DebianVersionNum, installed : bool, kind : RelationKind, relation : &'a Dependency },
+    /// a version cannot be installed alongside a version of target that relation matches
+    Conflicts { package_num : u32, version : &'a DebianVersionNum, installed : bool, kind : RelationKind,
+                relation : &'a RelVersionedPackageNum, target : u32, target_version : &'a DebianVersionNum, target_installed : bool }
+}
+
+// a version a package can have once the plan is carried out
+#[derive(Clone,Copy)]
+pub(super) struct Choice<'a> {
+    pub(super) package_num : u32,
+    pub(super) version : &'a DebianVersionNum,
+    // None for the installed version, whose relations come from the status file
+    pub(super) available : Option<&'a AvailableVersion>
+}
+
+// The clauses of a problem, and the choices they are about. An encoder that is searched has the goal as its first
+// clause; the one for the whole repository (see installability.rs) has no goal, and is restricted to the closure of
+// each version instead.
+pub(super) struct Encoder<'a> {
+    pub(super) packages : &'a Packages,
+    // whether the installed packages take part (installing on this system), or only the available versions do
+    system : bool,
+    // the kinds of relations followed for available versions
+    kinds : Vec<RelationKind>,
+    pub(super) choices : Vec<Choice<'a>>,
+    // the choices of each package of the problem, most preferred first
+    pub(super) by_package : HashMap<u32, Vec<usize>>,
+    // the packages of the problem, in the order they were reached
+    pub(super) order : Vec<u32>,
+    // installed packages whose installed relations of a required or a negative kind name each package
+    required_by : HashMap<u32, Vec<u32>>,
+    conflicted_by : HashMap<u32, Vec<u32>>,
+    // the virtual packages each installed package provides
+    installed_provides : HashMap<u32, Vec<u32>>,
+    // what each clause stands for, and its literals, those of relations in order of preference
+    pub(super) reasons : Vec<Reason<'a>>,
+    pub(super) literals : Vec<Vec<Lit>>,
+    // the choices search makes first, in order: from each group, the first one left, unless one is made already
+    pub(super) wishes : Vec<Vec<Lit>>,
+    // the dependency clauses of each choice, and the Conflicts and Breaks clauses it declares
+    depends : Vec<Vec<usize>>,
+    conflicts : Vec<Vec<usize>>,
+    // the choices the dependency clauses of each choice name, worked out once for the whole repository
+    targets : OnceLock<Vec<Vec<usize>>>
+}
+
+impl<'a> Encoder<'a> {
+    pub(super) fn new(packages: &'a Packages, kinds: &[RelationKind], system: bool) -> Encoder<'a> {
+        let mut required_by : HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut conflicted_by : HashMap<u32, Vec<u32>> = HashMap::new();
+        let installed = packages.installed_versions().filter(|_| system);
+        for (p, _) in installed {
+            for (kinds, index) in [(&RelationKind::REQUIRED, &mut required_by), (&RelationKind::NEGATIVE, &mut conflicted_by)] {
+                for kind in kinds {
+                    for alt in packages.get_installed_relations(*kind, p).iter().flatten() {
+                        index.entry(alt.package_num).or_default().push(p);
+                    }
+                }
+            }
+        }
+        let mut installed_provides : HashMap<u32, Vec<u32>> = HashMap::new();
+        for (virtual_num, providers) in packages.installed_providers.iter().filter(|_| system) {
+            for provider in providers {
+                installed_provides.entry(provider.package_num).or_default().push(virtual_num);
+            }
+        }
+        Encoder {
+            packages, system, kinds : kinds.to_vec(), choices : vec![], by_package : HashMap::new(), order : vec![],
+            required_by, conflicted_by, installed_provides, reasons : vec![], literals : vec![], wishes : vec![], depends : vec![],
+            conflicts : vec![], targets : OnceLock::new()
+        }
+    }
+
+    fn is_installed(choice: &Choice) -> bool {
+        choice.available.is_none()
+    }
+
+    // the relations a choice declares, of the followed kinds (positive) or of negative kinds
+    fn relations(&self, choice: &Choice<'a>, negative: bool) -> Vec<(RelationKind, &'a Dependency)> {
+        let kinds : &[RelationKind] = match (negative, choice.available) {
+            (true, _) => &RelationKind::NEGATIVE,
+            (false, None) => &RelationKind::REQUIRED,
+            (false, Some(_)) => &self.kinds
+        };
+        let packages = self.packages;
+        kinds.iter().flat_map(|kind| {
+            let deps = match choice.available {
+                None => packages.get_installed_relations(*kind, choice.package_num),
+                Some(v) => v.get_relations(*kind)
+            };
+            deps.iter().map(move |d| (*kind, d))
+        }).collect()
+    }
+
+    // the virtual packages a choice provides, with the provided versions
+    fn provides(&self, choice: &Choice<'a>) -> Vec<(u32, Option<&'a DebianVersionNum>)> {
+        match choice.available {
+            Some(v) => v.provides.iter().map(|p| (p.package_num, p.version.as_ref())).collect(),
+            None => {
+                let packages = self.packages;
+                self.installed_provides.get(&choice.package_num).into_iter().flatten()
+                    .filter_map(|virtual_num| packages.get_installed_providers(*virtual_num).iter()
+                        .find(|p| p.package_num == choice.package_num).map(|p| (*virtual_num, p.version.as_ref())))
+                    .collect()
+            }
+        }
+    }
+
+    // the packages that provide virtual_num in some version, installed or available
+    fn providers_of(&self, virtual_num: u32) -> Vec<u32> {
+        let mut providers : Vec<u32> = self.packages.available_providers.get(virtual_num).cloned().unwrap_or_default();
+        for p in self.packages.get_installed_providers(virtual_num).iter().filter(|_| self.system) {
+            if !providers.contains(&p.package_num) {
+                providers.push(p.package_num);
+            }
+        }
+        providers
+    }
+
+    // the architecture and Multi-Arch of a choice: those of the available version, or of the primary installed instance
+    fn arch(&self, choice: &Choice<'a>) -> (Option<&'a str>, MultiArch) {
+        match choice.available {
+            Some(v) => (v.architecture.as_deref(), v.multi_arch),
+            None => self.packages.primary_instance(choice.package_num).map_or((None, MultiArch::No), |i| (i.arch.as_deref(), i.multi_arch))
+        }
+    }
+
+    // the choices that satisfy relation, declared by a choice of architecture from_arch: versions of its package that
+    // meet the constraint, then versions of providers with a suitable provide (following provider_satisfies), of an
+    // architecture the relation accepts. Negative relations pass declared_by, as they don't apply to the package that
+    // declares them; unless qualified, they hold against every architecture
+    fn matching(&self, relation: &RelVersionedPackageNum, declared_by: Option<u32>, from_arch: Option<&str>) -> Vec<usize> {
+        let packages = self.packages;
+        let arch_satisfies = |c: usize| {
+            let (arch, multi_arch) = self.arch(&self.choices[c]);
+            (declared_by.is_some() && relation.arch.is_none()) || packages.arch_satisfies(relation, from_arch, arch, multi_arch)
+        };
+        let mut result = vec![];
+        if declared_by != Some(relation.package_num) {
+            for &c in self.by_package.get(&relation.package_num).into_iter().flatten() {
+                if packages.rel_version_matches(relation, self.choices[c].version) == Some(true) && arch_satisfies(c) {
+                    result.push(c);
+                }
+            }
+        }
+        for provider in self.providers_of(relation.package_num) {
+            if declared_by == Some(provider) { continue }
+            for &c in self.by_package.get(&provider).into_iter().flatten() {
+                let provided = self.provides(&self.choices[c]).into_iter().find(|(v, _)| *v == relation.package_num);
+                let satisfies = match (provided, &relation.rel_version) {
+                    (None, _) => false,
+                    (Some(_), None) => true,
+                    (Some((_, None)), Some(_)) => false,
+                    (Some((_, Some(v))), Some(_)) => packages.rel_version_matches(relation, v) == Some(true)
+                };
+                if satisfies && arch_satisfies(c) && !result.contains(&c) {
+                    result.push(c);
+                }
+            }
+        }
+        result
+    }
+
+    // makes package_num part of the problem, with a choice for its installed version and each available version apt
+    // could pick (native architecture, no negative pin, no downgrade without a pin of 1000); without the system, every
+    // available version of the native architecture, newest first
+    pub(super) fn add_package(&mut self, package_num: u32, queue: &mut VecDeque<u32>) {
+        if self.by_package.contains_key(&package_num) { return }
+        let packages = self.packages;
+        let installed = packages.installed_debver(package_num).filter(|_| self.system);
+        let candidate = packages.get_candidate_debver(package_num).filter(|_| self.system);
+        let mut available : Vec<(i32, &AvailableVersion)> = packages.get_available_versions(package_num).iter()
+            .filter(|v| packages.is_native_arch(v.architecture.as_deref()) && Some(&v.version) != installed)
+            .map(|v| (if self.system { packages.version_priority(package_num, &v.version) } else { 0 }, v))
+            .filter(|(priority, v)| *priority >= 0 && !(*priority < DOWNGRADE_PRIORITY && installed.is_some_and(|i| v.version < *i)))
+            .collect();
+        // the candidate first, then by priority and version
+        available.sort_by(|(p1, v1), (p2, v2)| (Some(&v2.version) == candidate).cmp(&(Some(&v1.version) == candidate))
+            .then(p2.cmp(p1)).then(v2.version.cmp(&v1.version)));
+        available.dedup_by(|a, b| a.1.version == b.1.version);
+        let mut nums = vec![];
+        if let Some(version) = installed {
+            nums.push(self.choices.len());
+            self.choices.push(Choice { package_num, version, available : None });
+        }
+        for (_, v) in available {
+            nums.push(self.choices.len());
+            self.choices.push(Choice { package_num, version : &v.version, available : Some(v) });
+        }
+        self.by_package.insert(package_num, nums);
+        self.order.push(package_num);
+        queue.push_back(package_num);
+    }
+
+    // everything the choices of package_num could involve: the alternatives of their relations and their providers,
+    // installed packages they conflict with, and installed packages whose relations name them
+    pub(super) fn expand(&mut self, package_num: u32, queue: &mut VecDeque<u32>) {
+        let packages = self.packages;
+        let mut reached = vec![];
+        for &c in &self.by_package[&package_num] {
+            let choice = &self.choices[c];
+            for (_, dep) in self.relations(choice, false) {
+                for alt in dep {
+                    reached.push(alt.package_num);
+                    reached.extend(self.providers_of(alt.package_num));
+                }
+            }
+            for (_, dep) in self.relations(choice, true) {
+                for alt in dep {
+                    if packages.installed_debver(alt.package_num).is_some() {
+                        reached.push(alt.package_num);
+                    }
+                    reached.extend(packages.get_installed_providers(alt.package_num).iter().map(|p| p.package_num));
+                }
+            }
+            let names = std::iter::once(package_num).chain(self.provides(choice).into_iter().map(|(v, _)| v));
+            for name in names {
+                reached.extend(self.conflicted_by.get(&name).into_iter().flatten());
+                if Encoder::is_installed(choice) {
+                    reached.extend(self.required_by.get(&name).into_iter().flatten());
+                }
+            }
+        }
+        for p in reached {
+            self.add_package(p, queue);
+        }
+    }
+
+    pub(super) fn add_clause(&mut self, literals: Vec<Lit>, reason: Reason<'a>) -> usize {
+        self.literals.push(literals);
+        self.reasons.push(reason);
+        self.literals.len() - 1
+    }
+
+    fn add_goal(&mut self, goal: u32) {
+        let goal_literals : Vec<Lit> = self.by_package[&goal].iter().map(|c| sat::lit(*c, true)).collect();
+        let versions = self.by_package[&goal].iter().map(|c| self.choices[*c].version).collect();
+        self.wishes.push(goal_literals.clone());
+        self.add_clause(goal_literals, Reason::Install { package_num : goal, versions });
+    }
+
+    // at most one version of each package
+    fn add_one_version(&mut self) {
+        for p in self.order.clone() {
+            let nums = self.by_package[&p].clone();
+            for (i, a) in nums.iter().enumerate() {
+                for b in &nums[i + 1..] {
+                    let reason = Reason::OneVersion { package_num : p, first : self.choices[*a].version, second : self.choices[*b].version };
+                    self.add_clause(vec![sat::lit(*a, false), sat::lit(*b, false)], reason);
+                }
+            }
+        }
+    }
+
+    // the clauses of the relations of every choice
+    pub(super) fn add_relations(&mut self) {
+        let packages = self.packages;
+        let order = self.order.clone();
+        self.depends = vec![vec![]; self.choices.len()];
+        self.conflicts = vec![vec![]; self.choices.len()];
+        let mut conflicting = HashSet::new();
+        for p in &order {
+            for c in self.by_package[p].clone() {
+                let choice = &self.choices[c];
+                let (version, installed) = (choice.version, Encoder::is_installed(choice));
+                let (positive, negative) = (self.relations(choice, false), self.relations(choice, true));
+                let (from_arch, _) = self.arch(choice);
+                for (kind, dep) in positive {
+                    // already broken on the installed system; not for the plan to fix
+                    if installed && !dep.iter().any(|alt| packages.installed_satisfier(alt, from_arch).is_some()) { continue }
+                    let mut literals = vec![sat::lit(c, false)];
+                    for alt in dep {
+                        literals.extend(self.matching(alt, None, from_arch).into_iter().map(|s| sat::lit(s, true)));
+                    }
+                    let reason = Reason::Depends { package_num : *p, version, installed, kind, relation : dep };
+                    let clause = self.add_clause(literals, reason);
+                    self.depends[c].push(clause);
+                }
+                for (kind, dep) in negative {
+                    for alt in dep {
+                        for target in self.matching(alt, Some(*p), from_arch) {
+                            let target_choice = &self.choices[target];
+                            let (target_num, target_version) = (target_choice.package_num, target_choice.version);
+                            let target_installed = Encoder::is_installed(target_choice);
+                            // both installed already: not for the plan to fix either
+                            if (installed && target_installed) || !conflicting.insert((c, target)) { continue }
+                            let reason = Reason::Conflicts { package_num : *p, version, installed, kind, relation : alt,
+                                target : target_num, target_version, target_installed };
+                            let clause = self.add_clause(vec![sat::lit(c, false), sat::lit(target, false)], reason);
+                            self.conflicts[c].push(clause);
+                        }
+                    }
+                }
+            }
+        }
+        self.add_one_version();
+    }
+
+    fn encode(&mut self, goal: u32) {
+        let mut queue = VecDeque::new();
+        self.add_package(goal, &mut queue);
+        while let Some(p) = queue.pop_front() {
+            self.expand(p, &mut queue);
+        }
+        self.add_goal(goal);
+        self.add_relations();
+    }
+
+    // the choices the dependency clauses of each choice name
+    fn targets(&self) -> &[Vec<usize>] {
+        self.targets.get_or_init(|| self.depends.iter().map(|clauses| {
+            let mut seen = HashSet::new();
+            clauses.iter().flat_map(|d| &self.literals[*d][1..]).map(|l| sat::lit_var(*l)).filter(|v| seen.insert(*v)).collect()
+        }).collect())
+    }
+
+    /// Every choice, each before the choices its dependencies name unless they depend on it in turn: the strongly
+    /// connected components of the dependency graph (by Tarjan's algorithm, without recursion), dependents first.
+    pub(super) fn dependents_first(&self) -> Vec<usize> {
+        let targets = self.targets();
+        let n = targets.len();
+        let (mut index, mut low, mut on_stack) = (vec![usize::MAX; n], vec![0; n], vec![false; n]);
+        let (mut stack, mut order, mut next) = (vec![], vec![], 0);
+        for root in 0..n {
+            if index[root] != usize::MAX { continue }
+            // the choices being visited, with the number of their targets visited so far
+            let mut calls = vec![(root, 0)];
+            while let Some((v, i)) = calls.pop() {
+                if i == 0 {
+                    (index[v], low[v]) = (next, next);
+                    next += 1;
+                    stack.push(v);
+                    on_stack[v] = true;
+                } else if on_stack[targets[v][i - 1]] {
+                    low[v] = low[v].min(low[targets[v][i - 1]]);
+                }
+                if let Some(w) = targets[v].get(i) {
+                    calls.push((v, i + 1));
+                    if index[*w] == usize::MAX { calls.push((*w, 0)) }
+                    continue;
+                }
+                // v is the first choice of its component to be visited; the component is done, after its dependencies
+                if low[v] == index[v] {
+                    while let Some(w) = stack.pop() {
+                        on_stack[w] = false;
+                        order.push(w);
+                        if w == v { break }
+                    }
+                }
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    /// The problem of installing the choice var alone, with the choices it could involve (through the dependencies
+    /// of what is chosen) and the clauses between them, renumbered; also the number each choice had here.
+    pub(super) fn restrict(&self, var: usize) -> (Encoder<'a>, Vec<usize>) {
+        let targets = self.targets();
+        let mut local : HashMap<usize, usize> = HashMap::from([(var, 0)]);
+        let mut vars = vec![var];
+        let mut i = 0;
+        while i < vars.len() {
+            for v in &targets[vars[i]] {
+                if let Entry::Vacant(e) = local.entry(*v) {
+                    e.insert(vars.len());
+                    vars.push(*v);
+                }
+            }
+            i += 1;
+        }
+        let mut restricted = Encoder::new(self.packages, &self.kinds, self.system);
+        restricted.choices = vars.iter().map(|v| self.choices[*v]).collect();
+        for v in &vars {
+            let p = self.choices[*v].package_num;
+            if let Entry::Vacant(e) = restricted.by_package.entry(p) {
+                e.insert(self.by_package[&p].iter().filter_map(|c| local.get(c).copied()).collect());
+                restricted.order.push(p);
+            }
+        }
+        let renumber = |l: &Lit| sat::lit(local[&sat::lit_var(*l)], sat::is_positive(*l));
+        let goal = self.choices[var];
+        restricted.add_clause(vec![sat::lit(0, true)], Reason::Install { package_num : goal.package_num, versions : vec![goal.version] });
+        restricted.depends = vec![vec![]; vars.len()];
+        restricted.conflicts = vec![vec![]; vars.len()];
+        for (c, v) in vars.iter().enumerate() {
+            for d in &self.depends[*v] {
+                let clause = restricted.add_clause(self.literals[*d].iter().map(renumber).collect(), self.reasons[*d].clone());
+                restricted.depends[c].push(clause);
+            }
+            for d in &self.conflicts[*v] {
+                if self.literals[*d].iter().all(|l| local.contains_key(&sat::lit_var(*l))) {
+                    let clause = restricted.add_clause(self.literals[*d].iter().map(renumber).collect(), self.reasons[*d].clone());
+                    restricted.conflicts[c].push(clause);
+                }
+            }
+        }
+        restricted.add_one_version();
+        (restricted, vars)
+    }
+
+    /// Searches for a model of the clauses, making the decisions described at the top of the file, wishes first. Returns the value of each choice, or the clauses that rule out any model.
+    pub(super) fn search(&self) -> Result<Vec<bool>, Vec<usize>> {
+        let mut sat = Sat::new(self.choices.len());
+        for literals in &self.literals {
+            sat.add_clause(literals.clone());
+        }
+        let installed_packages : Vec<u32> = self.order.iter().copied()
+            .filter(|p| self.system && self.packages.installed_debver(*p).is_some()).collect();
+        let mut seen_conflicts = 0;
+        let (mut wish_cursor, mut trail_cursor, mut installed_cursor, mut var_cursor) = (0, 0, 0, 0);
+        let (literals, wishes) = (&self.literals, &self.wishes);
+        let depends = &self.depends;
+        let by_package = &self.by_package;
+        let choices = &self.choices;
+        sat.solve(|sat| {
+            if sat.conflicts() != seen_conflicts {
+                seen_conflicts = sat.conflicts();
+                (wish_cursor, trail_cursor, installed_cursor, var_cursor) = (0, 0, 0, 0);
+            }
+            let first_unassigned = |lits: &[Lit]| lits.iter().copied().find(|l| sat.value(*l).is_none());
+            // the package asked for, in its preferred version (or the candidates to upgrade to)
+            while wish_cursor < wishes.len() {
+                if !wishes[wish_cursor].iter().any(|l| sat.value(*l) == Some(true)) {
+                    if let Some(l) = first_unassigned(&wishes[wish_cursor]) { return Some(l) }
+                }
+                wish_cursor += 1;
+            }
+            // the first alternative left for each relation of what is chosen, and another version for installed
+            // packages that cannot keep theirs
+            while trail_cursor < sat.trail().len() {
+                let l = sat.trail()[trail_cursor];
+                let choice = &choices[sat::lit_var(l)];
+                if sat::is_positive(l) {
+                    for d in &depends[sat::lit_var(l)] {
+                        if literals[*d].iter().any(|l| sat.value(*l) == Some(true)) { continue }
+                        if let Some(l) = first_unassigned(&literals[*d][1..]) { return Some(l) }
+                    }
+                } else if Encoder::is_installed(choice) {
+                    let nums = &by_package[&choice.package_num];
+                    if !nums.iter().any(|c| sat.value(sat::lit(*c, true)) == Some(true)) {
+                        if let Some(c) = nums.iter().find(|c| sat.value(sat::lit(**c, true)).is_none()) {
+                            return Some(sat::lit(*c, true));
+                        }
+                    }
+                }
+                trail_cursor += 1;
+            }
+            // installed packages keep their version, or else get another one
+            while installed_cursor < installed_packages.len() {
+                let nums = &by_package[&installed_packages[installed_cursor]];
+                if !nums.iter().any(|c| sat.value(sat::lit(*c, true)) == Some(true)) {
+                    if let Some(c) = nums.iter().find(|c| sat.value(sat::lit(**c, true)).is_none()) {
+                        return Some(sat::lit(*c, true));
+                    }
+                }
+                installed_cursor += 1;
+            }
+            // and nothing else is installed
+            while var_cursor < choices.len() {
+                if sat.value(sat::lit(var_cursor, true)).is_none() {
+                    return Some(sat::lit(var_cursor, false));
+                }
+                var_cursor += 1;
+            }
+            None
+        })
+    }
+
+    fn solve(self, goal: u32) -> Result<Vec<Change<'a>>, Vec<Reason<'a>>> {
+        let model = match self.search() {
+            Err(core) => return Err(core.into_iter().map(|c| self.reasons[c].clone()).collect()),
+            Ok(model) => model
+        };
+        let packages = self.packages;
+        let mut changes = vec![];
+        for p in &self.order {
+            let chosen = self.by_package[p].iter().find(|c| model[**c]).map(|c| &self.choices[*c]);
+            match (packages.installed_debver(*p), chosen) {
+                (None, Some(Choice { available : Some(v), .. })) => changes.push(Change::Install { package_num : *p, version : v }),
+                (Some(from), Some(Choice { available : Some(v), .. })) => changes.push(Change::Upgrade { package_num : *p, from, to : v }),
+                (Some(version), None) => changes.push(Change::Remove { package_num : *p, version }),
+                _ => ()
+            }
+        }
+        changes.sort_by_key(|c| (c.package_num() != goal, packages.interner.get(c.package_num())));
+        Ok(changes)
+    }
+}
+
+impl Packages {
+    /// How installing package_name can be planned, following the relations of the given kinds of the new versions:
+    /// the changes of a plan that satisfies all of them and every Conflicts and Breaks, or the constraints that rule
+    /// out any plan. None if there is no such package.
+    pub fn solve_install(&self, package_name: &str, kinds: &[RelationKind]) -> Option<Result<Vec<Change<'_>>, Vec<Reason<'_>>>> {
+        let goal = self.package_num(package_name)?;
+        let mut encoder = Encoder::new(self, kinds, true);
+        encoder.encode(goal);
+        Some(encoder.solve(goal))
+    }
+
+    pub fn solver_mode(&self) -> SolverMode {
+        self.solver_mode
+    }
+
+    /// Sets how how-to-install plans from now on; see SolverMode.
+    pub fn set_solver_mode(&mut self, mode: SolverMode) {
+        self.solver_mode = mode;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packages::testutil::{load, num};
+
+    #[test]
+    fn conflicts_force_a_removal() {
+        let packages = load("Package: a\nVersion: 1\nDepends: c\nConflicts: b\n\nPackage: c\nVersion: 1\n",
+                            "Package: b\nStatus: install ok installed\nVersion: 1\n");
+        let changes = packages.solve_install("a", &RelationKind::REQUIRED).unwrap().ok().unwrap();
+        let described : Vec<String> = changes.iter().map(|c| match c {
+            Change::Install { package_num, version } => format!("install {} {}", packages.shown_name(*package_num), version.version),
+            Change::Upgrade { package_num, .. } => format!("upgrade {}", packages.shown_name(*package_num)),
+            Change::Remove { package_num, version } => format!("remove {} {}", packages.shown_name(*package_num), version)
+        }).collect();
+        assert_eq!(described, ["install a 1", "remove b 1", "install c 1"]);
+    }
+
+    #[test]
+    fn unsatisfiable_depends_is_the_reason() {
+        let packages = load("Package: a\nVersion: 1\nDepends: b, c (>= 2) | d\n\nPackage: b\nVersion: 1\n\nPackage: c\nVersion: 1\n", "");
+        let reasons = packages.solve_install("a", &RelationKind::REQUIRED).unwrap().err().unwrap();
+        let depends : Vec<(u32, String)> = reasons.iter().filter_map(|r| match r {
+            Reason::Depends { package_num, relation, .. } => Some((*package_num, packages.dep2str(relation).unwrap())),
+            _ => None
+        }).collect();
+        assert_eq!(depends, [(num(&packages, "a"), String::from("c (>= 2) | d"))]);
+        assert!(reasons.iter().any(|r| matches!(r, Reason::Install { package_num, .. } if *package_num == num(&packages, "a"))));
+    }
+}
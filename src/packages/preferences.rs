@@ -0,0 +1,355 @@
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::debversion::DebianVersionNum;
+
+use crate::Packages;
+use crate::packages::parsers::read_paragraphs;
+use crate::packages::errors::{LoadError, LoadReport};
+
+// apt_preferences(5): each stanza pins the versions of some packages (Package: names or patterns) that match a Pin
+// (a version, an archive site, or Release file fields) to a Pin-Priority. The candidate version of a package is its
+// available version with the highest priority; see get_candidate.
+
+/// Priority of versions from an archive without NotAutomatic, when no pin applies.
+pub const DEFAULT_PRIORITY : i32 = 500;
+/// Priority of the installed version, and of NotAutomatic archives with ButAutomaticUpgrades.
+pub const INSTALLED_PRIORITY : i32 = 100;
+/// Priority of NotAutomatic archives such as experimental.
+pub const NOT_AUTOMATIC_PRIORITY : i32 = 1;
+/// From this priority on, a version is chosen even if it downgrades the installed package.
+pub const DOWNGRADE_PRIORITY : i32 = 1000;
+
+/// A package name or field value in a preferences file: a plain string, a glob ("lib*"), or a regular expression
+/// between slashes ("/^lib.*-dev$/").
+pub struct PinPattern {
+    text : String,
+    regex : Option<Regex>
+}
+
+impl PinPattern {
+    pub fn new(text: &str) -> Result<PinPattern, String> {
+        let regex = if text.len() > 1 && text.starts_with('/') && text.ends_with('/') {
+            Some(Regex::new(&text[1..text.len()-1]).map_err(|e| format!("bad regular expression {}: {}", text, e))?)
+        } else if text.contains(['*', '?']) {
+            let mut re = String::from("^");
+            for c in text.chars() {
+                match c {
+                    '*' => re.push_str(".*"),
+                    '?' => re.push('.'),
+                    c => re.push_str(&regex::escape(&c.to_string()))
+                }
+            }
+            re.push('$');
+            Some(Regex::new(&re).unwrap())
+        } else {
+            None
+        };
+        Ok(PinPattern { text : text.to_string(), regex })
+    }
+
+    pub fn matches(&self, s: &str) -> bool {
+        match &self.regex {
+            None => self.text == s,
+            Some(re) => re.is_match(s)
+        }
+    }
+
+    fn is_exact(&self) -> bool {
+        self.regex.is_none()
+    }
+}
+
+// snapshots keep the pattern as written; the regular expression is compiled again when loading
+impl Serialize for PinPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.text.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PinPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        PinPattern::new(&String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+/// What a preference applies to, from its Pin field.
+#[derive(Serialize,Deserialize)]
+pub enum Pin {
+    // Pin: version 2.0*
+    Version(PinPattern),
+    // Pin: origin "deb.debian.org"; an empty site matches local files
+    Origin(String),
+    // Pin: release a=stable-backports,c=main; the keys are a (suite), n (codename), c (component), o (origin),
+    // l (label) and v (release version), and a value without a key is a release version
+    Release(Vec<(char, PinPattern)>)
+}
+
+impl Pin {
+    fn parse(s: &str) -> Result<Pin, String> {
+        let (kind, rest) = match s.split_once(char::is_whitespace) {
+            None => (s, ""),
+            Some((kind, rest)) => (kind, rest.trim())
+        };
+        match kind {
+            "version" => Ok(Pin::Version(PinPattern::new(rest)?)),
+            "origin" => Ok(Pin::Origin(rest.trim_matches('"').to_string())),
+            "release" => {
+                let mut conditions = vec![];
+                for term in rest.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                    let (key, value) = match term.split_once('=') {
+                        None => ('v', term),
+                        Some((key, value)) => match key.trim() {
+                            "a" | "archive" => ('a', value),
+                            "n" | "codename" => ('n', value),
+                            "c" | "component" => ('c', value),
+                            "o" | "origin" => ('o', value),
+                            "l" | "label" => ('l', value),
+                            "v" | "version" => ('v', value),
+                            k => return Err(format!("unknown release key {}", k))
+                        }
+                    };
+                    conditions.push((key, PinPattern::new(value.trim().trim_matches('"'))?));
+                }
+                Ok(Pin::Release(conditions))
+            }
+            _ => Err(format!("bad Pin {:?}: expected version, origin or release", s))
+        }
+    }
+}
+
+/// One stanza of a preferences file.
+#[derive(Serialize,Deserialize)]
+pub struct Preference {
+    pub packages : Vec<PinPattern>,
+    pub pin : Pin,
+    pub priority : i32
+}
+
+impl Preference {
+    fn applies_to(&self, package_name: &str) -> bool {
+        self.packages.iter().any(|p| p.matches(package_name))
+    }
+
+    /// Specific preferences name packages exactly; they are checked before general ones (with patterns such as "*").
+    fn is_specific(&self) -> bool {
+        self.packages.iter().all(|p| p.is_exact())
+    }
+}
+
+impl Packages {
+    /// Loads pin preferences in the format of /etc/apt/preferences, replacing those loaded before (unless loading fails).
+    pub fn parse_preferences(&mut self, filename: &str) -> Result<LoadReport, LoadError> {
+        let paragraphs = read_paragraphs(filename).map_err(|e| LoadError::io(filename, e))?;
+        let mut report = LoadReport::new(filename);
+        self.record_loaded_file(filename, &mut report.warnings);
+        let mut preferences = vec![];
+        report.load_paragraphs(self.load_mode, paragraphs, |para, _| {
+            let packages = para.get_folded("Package").ok_or("no Package field")?;
+            let packages = packages.split_whitespace().map(PinPattern::new).collect::<Result<Vec<_>, _>>()?;
+            let pin = Pin::parse(para.get("Pin").ok_or("no Pin field")?)?;
+            let priority = para.get("Pin-Priority").ok_or("no Pin-Priority field")?;
+            let priority = priority.parse::<i32>().map_err(|_| format!("bad Pin-Priority {}", priority))?;
+            preferences.push(Preference { packages, pin, priority });
+            Ok(())
+        })?;
+        self.preferences = preferences;
+        self.clear_candidates();
+        Ok(report)
+    }
+
+    /// Number of pin preferences loaded.
+    pub fn preference_count(&self) -> usize {
+        self.preferences.len()
+    }
+
+    /// Does pin select version when it comes from the given origin (None for the installed version)?
+    fn pin_matches(&self, pin: &Pin, version: &DebianVersionNum, origin: Option<usize>) -> bool {
+        let origin = origin.map(|o| &self.origins[o]);
+        match pin {
+            Pin::Version(pattern) => pattern.matches(&version.to_string()),
+            Pin::Origin(site) => origin.is_some_and(|o| o.site().unwrap_or("") == site),
+            Pin::Release(conditions) => {
+                let (origin, release) = match origin.and_then(|o| o.release.as_ref().map(|r| (o, r))) {
+                    None => return false,
+                    Some(o) => o
+                };
+                conditions.iter().all(|(key, pattern)| {
+                    let value = match key {
+                        'a' => release.suite.as_deref(),
+                        'n' => release.codename.as_deref(),
+                        'c' => origin.component.as_deref(),
+                        'o' => release.origin.as_deref(),
+                        'l' => release.label.as_deref(),
+                        _ => release.version.as_deref()
+                    };
+                    value.is_some_and(|v| pattern.matches(v))
+                })
+            }
+        }
+    }
+
+    /// The priority of versions of package_name from origin (None for the installed version): that of the first general
+    /// preference matching it, or else the default for the origin.
+    pub fn origin_priority(&self, package_name: &str, version: &DebianVersionNum, origin: Option<usize>) -> i32 {
+        let general = self.preferences.iter().filter(|p| !p.is_specific() && p.applies_to(package_name));
+        for preference in general {
+            if self.pin_matches(&preference.pin, version, origin) {
+                return preference.priority;
+            }
+        }
+        match origin.and_then(|o| self.origins[o].release.as_ref()) {
+            None if origin.is_none() => INSTALLED_PRIORITY,
+            Some(r) if r.not_automatic && r.but_automatic_upgrades => INSTALLED_PRIORITY,
+            Some(r) if r.not_automatic => NOT_AUTOMATIC_PRIORITY,
+            _ => DEFAULT_PRIORITY
+        }
+    }
+
+    /// Where a version of package_num is known from: the origins of the available version, plus None if it is installed.
+    pub fn version_sources(&self, package_num: u32, version: &DebianVersionNum) -> Vec<Option<usize>> {
+        let available = self.get_available_versions(package_num).iter().filter(|v| &v.version == version);
+        let mut sources : Vec<Option<usize>> = available.flat_map(|v| v.origins.iter().map(|o| Some(*o))).collect();
+        // a version can be listed by several indices of one origin, not necessarily one after the other
+        sources.sort_unstable();
+        sources.dedup();
+        if self.installed_debver(package_num) == Some(version) {
+            sources.push(None);
+        }
+        sources
+    }
+
+    /// The pin priority of a version of package_num: that of the first specific preference matching it, or else the
+    /// highest priority of the origins it is available from. A version that is neither available nor installed (of a
+    /// package that has no number, for one) has priority 0.
+    pub fn version_priority(&self, package_num: u32, version: &DebianVersionNum) -> i32 {
+        let package_name = match self.interner.get(package_num) {
+            None => return 0,
+            Some(name) => name
+        };
+        let sources = self.version_sources(package_num, version);
+        let specific = self.preferences.iter().filter(|p| p.is_specific() && p.applies_to(package_name));
+        for preference in specific {
+            if sources.iter().any(|s| self.pin_matches(&preference.pin, version, *s)) {
+                return preference.priority;
+            }
+        }
+        sources.iter().map(|s| self.origin_priority(package_name, version, *s)).max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packages::testutil::{load, num, TempFile};
+
+    #[test]
+    fn patterns() {
+        let glob = PinPattern::new("lib?-*").unwrap();
+        assert!(glob.matches("libc-dev") && glob.matches("libx-") && !glob.matches("xlibc-dev") && !glob.matches("libc6-dev"));
+        let regex = PinPattern::new("/^lib.*-dev$/").unwrap();
+        assert!(regex.matches("libc6-dev") && !regex.matches("libc6"));
+        // a plain name is compared as is, so its dots are not wildcards
+        let exact = PinPattern::new("python3.11").unwrap();
+        assert!(exact.is_exact() && exact.matches("python3.11") && !exact.matches("python3x11"));
+        assert!(!glob.is_exact() && !regex.is_exact());
+        assert!(PinPattern::new("/(/").is_err());
+    }
+
+    #[test]
+    fn pins() {
+        assert!(matches!(Pin::parse("version 2.0*"), Ok(Pin::Version(p)) if p.matches("2.0.1") && !p.matches("12.0")));
+        assert!(matches!(Pin::parse("origin \"deb.debian.org\""), Ok(Pin::Origin(site)) if site == "deb.debian.org"));
+        let conditions = match Pin::parse("release a=stable-backports, n=bookworm*,12") {
+            Ok(Pin::Release(conditions)) => conditions,
+            _ => panic!("not a release pin")
+        };
+        let keys : Vec<(char, &str)> = conditions.iter().map(|(key, pattern)| (*key, pattern.text.as_str())).collect();
+        assert_eq!(keys, [('a', "stable-backports"), ('n', "bookworm*"), ('v', "12")]);
+        assert!(Pin::parse("release x=1").is_err());
+        assert!(Pin::parse("suite stable").is_err());
+
+        let preferences = TempFile::new("Package: *\nPin: release a=stable\nPin-Priority: 700\n\n\
+                                         Package: lib* /-dev$/\nPin: version 1.*\nPin-Priority: -1\n\n\
+                                         Package: a\nPin: version 1.0\n\n\
+                                         Package: b\nPin: release o=Debian\nPin-Priority: high\n");
+        let mut packages = Packages::new();
+        let report = packages.parse_preferences(preferences.path()).unwrap();
+        assert_eq!(report.skipped.iter().map(|e| e.line).collect::<Vec<_>>(), [Some(9), Some(12)]);
+        assert_eq!(packages.preference_count(), 2);
+        assert!(!packages.preferences[0].is_specific() && packages.preferences[1].applies_to("libc6"));
+    }
+
+    // loads index with a Release file of the given header, so that release pins and NotAutomatic apply to it
+    fn load_with_release(packages: &mut Packages, index: &str, release: &str) {
+        let (index, release) = (TempFile::new(index), TempFile::new(release));
+        let report = packages.parse_packages(index.path(), Some(release.path())).unwrap();
+        assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+    }
+
+    // the candidate version of name, with the given preferences loaded (replacing those loaded before)
+    fn candidate(packages: &mut Packages, preferences: &str, name: &str) -> Option<String> {
+        packages.parse_preferences(TempFile::new(preferences).path()).unwrap();
+        packages.get_candidate_debver(num(packages, name)).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn release_pins() {
+        let mut packages = Packages::new();
+        load_with_release(&mut packages, "Package: a\nVersion: 1.0\nArchitecture: amd64\n",
+                          "Origin: Debian\nSuite: stable\nCodename: bookworm\nVersion: 12.5\n");
+        load_with_release(&mut packages, "Package: a\nVersion: 2.0\nArchitecture: amd64\n",
+                          "Origin: Debian Backports\nSuite: stable-backports\nCodename: bookworm-backports\n");
+        assert_eq!(candidate(&mut packages, "", "a").as_deref(), Some("2.0"));
+        let backports = "Package: *\nPin: release a=stable-backports,n=bookworm-backports\nPin-Priority: 100\n";
+        assert_eq!(candidate(&mut packages, backports, "a").as_deref(), Some("1.0"));
+        // every condition must hold
+        let mismatched = "Package: *\nPin: release a=stable-backports,n=bookworm\nPin-Priority: 100\n";
+        assert_eq!(candidate(&mut packages, mismatched, "a").as_deref(), Some("2.0"));
+        let by_version = "Package: a\nPin: release o=Debian,v=12*\nPin-Priority: 600\n";
+        assert_eq!(candidate(&mut packages, by_version, "a").as_deref(), Some("1.0"));
+        // a specific preference for another package leaves a alone
+        assert_eq!(candidate(&mut packages, "Package: b\nPin: version 2.0\nPin-Priority: -1\n", "a").as_deref(), Some("2.0"));
+    }
+
+    #[test]
+    fn priority_thresholds() {
+        let mut packages = load("Package: a\nVersion: 1.0\nArchitecture: amd64\n\nPackage: a\nVersion: 3.0\nArchitecture: amd64\n\n\
+                                 Package: b\nVersion: 1.0\nArchitecture: amd64\n\nPackage: b\nVersion: 3.0\nArchitecture: amd64\n",
+                                "Package: a\nStatus: install ok installed\nVersion: 2.0\nArchitecture: amd64\n");
+        let pin = |version: &str, priority: i32| format!("Package: a b\nPin: version {}\nPin-Priority: {}\n", version, priority);
+        assert_eq!(candidate(&mut packages, "", "a").as_deref(), Some("3.0"));
+        // below 0, never; the older version would be a downgrade, so a stays
+        assert_eq!(candidate(&mut packages, &pin("3.0", -1), "a"), None);
+        assert_eq!(candidate(&mut packages, &pin("3.0", -1), "b").as_deref(), Some("1.0"));
+        // 100 still upgrades the installed version, but loses to the default priority
+        assert_eq!(candidate(&mut packages, &pin("3.0", 100), "a").as_deref(), Some("3.0"));
+        assert_eq!(candidate(&mut packages, &pin("3.0", 100), "b").as_deref(), Some("1.0"));
+        // 990 beats the default, but does not downgrade
+        assert_eq!(candidate(&mut packages, &pin("1.0", 990), "a").as_deref(), Some("3.0"));
+        assert_eq!(candidate(&mut packages, &pin("1.0", 990), "b").as_deref(), Some("1.0"));
+        // from 1000 on, it does
+        assert_eq!(candidate(&mut packages, &pin("1.0", 1001), "a").as_deref(), Some("1.0"));
+        let a = num(&packages, "a");
+        assert_eq!(packages.version_priority(a, &"1.0".parse().unwrap()), 1001);
+        assert_eq!(packages.version_priority(a, &"2.0".parse().unwrap()), INSTALLED_PRIORITY);
+        assert_eq!(packages.version_priority(a, &"3.0".parse().unwrap()), DEFAULT_PRIORITY);
+    }
+
+    #[test]
+    fn not_automatic() {
+        let mut packages = load("Package: a\nVersion: 1.0\nArchitecture: amd64\n\nPackage: b\nVersion: 1.0\nArchitecture: amd64\n",
+                                "Package: b\nStatus: install ok installed\nVersion: 2.0\nArchitecture: amd64\n");
+        load_with_release(&mut packages, "Package: a\nVersion: 3.0\nArchitecture: amd64\n\nPackage: b\nVersion: 3.0\nArchitecture: amd64\n",
+                          "Suite: experimental\nNotAutomatic: yes\n");
+        assert_eq!(packages.version_priority(num(&packages, "a"), &"3.0".parse().unwrap()), NOT_AUTOMATIC_PRIORITY);
+        assert_eq!(candidate(&mut packages, "", "a").as_deref(), Some("1.0"));
+        assert_eq!(candidate(&mut packages, "", "b"), None);
+        // reloading the index with ButAutomaticUpgrades upgrades installed packages, but still installs none from it
+        load_with_release(&mut packages, "Package: a\nVersion: 3.0\nArchitecture: amd64\n\nPackage: b\nVersion: 3.0\nArchitecture: amd64\n",
+                          "Suite: stable-backports\nNotAutomatic: yes\nButAutomaticUpgrades: yes\n");
+        assert_eq!(packages.version_priority(num(&packages, "a"), &"3.0".parse().unwrap()), INSTALLED_PRIORITY);
+        assert_eq!(candidate(&mut packages, "", "a").as_deref(), Some("1.0"));
+        assert_eq!(candidate(&mut packages, "", "b").as_deref(), Some("3.0"));
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+
+use crate::debversion::{DebianVersionNum, VersionRelation};
+
+use crate::Packages;
+use crate::packages::{Dependency, RelVersionedPackageNum};
+use crate::packages::interner::PackageTable;
+
+/// A real package that provides a virtual package, e.g. dbus-user-session provides default-dbus-session-bus.
+/// Versioned provides ("Provides: foo (= 1.2)") carry the provided version.
+#[derive(Serialize,Deserialize)]
+pub struct Provider {
+    pub package_num : u32,
+    pub version : Option<DebianVersionNum>
+}
+
+/// One entry of a Provides field: the virtual package, and the provided version for versioned provides.
+#[derive(Serialize,Deserialize)]
+pub struct Provided {
+    pub package_num : u32,
+    pub version : Option<DebianVersionNum>
+}
+
+impl Provided {
+    /// Converts the entries of a Provides field, parsed like a relation field.
+    pub fn from_dependencies(provides: Vec<Dependency>) -> Vec<Provided> {
+        provides.into_iter().flatten().map(|provided| {
+            // only "=" is allowed in Provides; other operators are ignored
+            let version = match provided.rel_version {
+                Some((VersionRelation::Equal, v)) => v.parse::<DebianVersionNum>().ok(),
+                _ => None
+            };
+            Provided { package_num : provided.package_num, version }
+        }).collect()
+    }
+}
+
+impl Packages {
+    /// Records in index that package_num provides each of the packages in provides.
+    pub(super) fn record_provides(index: &mut PackageTable<Vec<Provider>>, package_num: u32, provides: Vec<Provided>) {
+        for provided in provides {
+            let providers = index.entry(provided.package_num);
+            providers.retain(|p| p.package_num != package_num);
+            providers.push(Provider { package_num, version : provided.version });
+        }
+    }
+
+    /// The packages whose candidate version provides virtual_num.
+    pub fn get_available_providers(&self, virtual_num: u32) -> Vec<Provider> {
+        let mut result = vec![];
+        for package_num in self.available_providers.get(virtual_num).into_iter().flatten() {
+            let candidate = match self.get_candidate(*package_num) {
+                None => continue,
+                Some(c) => c
+            };
+            if let Some(provided) = candidate.provides.iter().find(|p| p.package_num == virtual_num) {
+                result.push(Provider { package_num : *package_num, version : provided.version.clone() });
+            }
+        }
+        result
+    }
+
+    pub fn get_installed_providers(&self, virtual_num: u32) -> &[Provider] {
+        match self.installed_providers.get(virtual_num) {
+            None => &[],
+            Some(p) => p
+        }
+    }
+
+    /// A virtual package is a name that only exists because something provides it: it has neither an available
+    /// nor an installed version of its own.
+    pub fn is_virtual(&self, package_num: u32) -> bool {
+        self.get_available_versions(package_num).is_empty() && self.installed_debver(package_num).is_none()
+            && !(self.get_available_providers(package_num).is_empty() && self.get_installed_providers(package_num).is_empty())
+    }
+
+    /// Returns true if provider can stand in for relation. Following Debian policy, an unversioned relation is satisfied
+    /// by any provider, and a versioned relation only by a versioned provide whose version meets the constraint.
+    pub fn provider_satisfies(&self, provider: &Provider, relation: &RelVersionedPackageNum) -> bool {
+        match (&relation.rel_version, &provider.version) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(_), Some(v)) => self.rel_version_matches(relation, v) == Some(true)
+        }
+    }
+
+    /// Finds an installed package satisfying relation, declared by a package of architecture from_arch (None for the
+    /// native architecture): the package itself at a suitable installed version and architecture, or else an installed
+    /// provider. Each installed architecture counts with its own version. Returns the satisfying package and its
+    /// installed (or provided) version.
+    pub fn installed_satisfier<'a>(&'a self, relation: &'a RelVersionedPackageNum, from_arch: Option<&'a str>) -> Option<(u32, &'a DebianVersionNum)> {
+        let named = self.installed_arch_satisfiers(relation, from_arch, relation.package_num)
+            .filter_map(|i| i.debver.as_ref())
+            .find(|v| self.rel_version_matches(relation, v) == Some(true));
+        if let Some(v) = named {
+            return Some((relation.package_num, v));
+        }
+        self.get_installed_providers(relation.package_num).iter()
+            .filter(|p| self.provider_satisfies(p, relation))
+            .find_map(|p| self.installed_arch_satisfiers(relation, from_arch, p.package_num).find_map(|i| i.debver.as_ref())
+                .map(|iv| (p.package_num, p.version.as_ref().unwrap_or(iv))))
+    }
+
+    /// Returns the packages that could be installed to satisfy relation, declared by a package of architecture
+    /// from_arch (None for the native architecture): the package itself if its candidate meets the version and
+    /// architecture constraints, followed by the available providers that satisfy it.
+    pub fn available_satisfiers(&self, relation: &RelVersionedPackageNum, from_arch: Option<&str>) -> Vec<u32> {
+        let candidate_arch_satisfies = |package_num: u32| match self.get_candidate(package_num) {
+            None => false,
+            Some(c) => self.arch_satisfies(relation, from_arch, c.architecture.as_deref(), c.multi_arch)
+        };
+        let mut result = vec![];
+        if let Some(av) = self.get_candidate_debver(relation.package_num) {
+            if self.rel_version_matches(relation, av) == Some(true) && candidate_arch_satisfies(relation.package_num) {
+                result.push(relation.package_num);
+            }
+        }
+        for p in self.get_available_providers(relation.package_num) {
+            if self.provider_satisfies(&p, relation) && candidate_arch_satisfies(p.package_num) && !result.contains(&p.package_num) {
+                result.push(p.package_num);
+            }
+        }
+        result
+    }
+}
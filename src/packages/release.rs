@@ -0,0 +1,329 @@
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::deb822::{Paragraph, ParagraphReader};
+
+use crate::Packages;
+use crate::packages::parsers::open_decompressed;
+use crate::packages::errors::{LoadError, LoadReport};
+
+/// The header of a Debian Release (or InRelease) file, which describes an archive suite; see
+/// https://wiki.debian.org/DebianRepository/Format#A.22Release.22_files
+#[derive(Serialize,Deserialize)]
+pub struct ReleaseInfo {
+    pub origin : Option<String>,
+    pub label : Option<String>,
+    pub suite : Option<String>,
+    pub codename : Option<String>,
+    pub version : Option<String>,
+    pub components : Vec<String>,
+    // NotAutomatic archives (e.g. experimental) are only used when asked for; ButAutomaticUpgrades still upgrades
+    // packages already installed from them
+    pub not_automatic : bool,
+    pub but_automatic_upgrades : bool,
+    // the index files of the suite, from the MD5Sum and SHA256 tables
+    pub files : Vec<ReleaseFile>
+}
+
+/// One index file listed in a Release file, with its path relative to the Release file's directory.
+#[derive(Serialize,Deserialize)]
+pub struct ReleaseFile {
+    pub path : String,
+    pub size : u64,
+    pub md5sum : Option<String>,
+    pub sha256 : Option<String>
+}
+
+impl ReleaseInfo {
+    pub fn from_paragraph(para: &Paragraph) -> ReleaseInfo {
+        let mut files : Vec<ReleaseFile> = vec![];
+        for (field, is_sha256) in [("MD5Sum", false), ("SHA256", true)] {
+            // " <hash> <size> <path>" on each continuation line
+            for line in para.get_lines(field).into_iter().flatten() {
+                let (hash, size, path) = match line.split_whitespace().collect::<Vec<&str>>()[..] {
+                    [hash, size, path] => match size.parse::<u64>() {
+                        Ok(size) => (hash.to_lowercase(), size, path),
+                        Err(_) => continue
+                    },
+                    _ => continue
+                };
+                let file = match files.iter_mut().position(|f| f.path == path) {
+                    Some(i) => &mut files[i],
+                    None => {
+                        files.push(ReleaseFile { path : path.to_string(), size, md5sum : None, sha256 : None });
+                        files.last_mut().unwrap()
+                    }
+                };
+                if is_sha256 { file.sha256 = Some(hash) } else { file.md5sum = Some(hash) }
+            }
+        }
+        ReleaseInfo {
+            origin : para.get("Origin").map(String::from),
+            label : para.get("Label").map(String::from),
+            suite : para.get("Suite").map(String::from),
+            codename : para.get("Codename").map(String::from),
+            version : para.get("Version").map(String::from),
+            components : para.get("Components").map(|v| v.split_whitespace().map(String::from).collect()).unwrap_or_default(),
+            not_automatic : para.get("NotAutomatic") == Some("yes"),
+            but_automatic_upgrades : para.get("ButAutomaticUpgrades") == Some("yes"),
+            files
+        }
+    }
+
+    pub fn get_file(&self, path: &str) -> Option<&ReleaseFile> {
+        self.files.iter().find(|f| f.path == path)
+    }
+}
+
+impl ReleaseFile {
+    /// Checks the size and hash of filename against this entry, preferring SHA256 to MD5.
+    pub fn verify(&self, filename: &str) -> io::Result<Result<(), String>> {
+        Ok(self.verify_contents(&fs::read(filename)?))
+    }
+
+    /// Like verify, for the contents of the file as read already.
+    pub fn verify_contents(&self, contents: &[u8]) -> Result<(), String> {
+        if contents.len() as u64 != self.size {
+            return Err(format!("size is {}, Release says {}", contents.len(), self.size));
+        }
+        let (kind, expected, actual) = match (&self.sha256, &self.md5sum) {
+            (Some(expected), _) => ("SHA256", expected, format!("{:x}", Sha256::digest(contents))),
+            (None, Some(expected)) => ("MD5Sum", expected, format!("{:x}", Md5::digest(contents))),
+            (None, None) => return Err(String::from("Release has no hash for it"))
+        };
+        if &actual != expected {
+            return Err(format!("{} is {}, Release says {}", kind, actual, expected));
+        }
+        Ok(())
+    }
+}
+
+/// Reads the Release paragraph of a Release or InRelease file; the OpenPGP armor around an InRelease file is skipped.
+pub fn read_release_paragraph(filename: &str) -> io::Result<Paragraph> {
+    let file = open_decompressed(filename)?;
+    let mut text = String::new();
+    let mut in_signature = false;
+    let mut lines = file.lines();
+    while let Some(line) = lines.next() {
+        let line = line?;
+        if line.starts_with("-----BEGIN PGP SIGNED MESSAGE-----") {
+            // armor headers ("Hash: SHA512") end at the first blank line
+            for header in &mut lines {
+                if header?.trim().is_empty() { break }
+            }
+            continue;
+        }
+        if line.starts_with("-----BEGIN PGP SIGNATURE-----") { in_signature = true; }
+        if in_signature {
+            if line.starts_with("-----END PGP SIGNATURE-----") { in_signature = false; }
+            continue;
+        }
+        // dash-escaped lines in the signed text
+        text.push_str(line.strip_prefix("- ").unwrap_or(&line));
+        text.push('\n');
+    }
+    match ParagraphReader::new(text.as_bytes()).next() {
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: no Release paragraph", filename))),
+        Some(para) => para
+    }
+}
+
+pub fn read_release(filename: &str) -> io::Result<ReleaseInfo> {
+    Ok(ReleaseInfo::from_paragraph(&read_release_paragraph(filename)?))
+}
+
+/// The path of filename relative to the directory of release_filename, as listed in the Release file.
+fn path_in_release(release_filename: &str, filename: &str) -> Option<String> {
+    let dir = fs::canonicalize(Path::new(release_filename).parent()?.join(".")).ok()?;
+    let file = fs::canonicalize(filename).ok()?;
+    Some(file.strip_prefix(dir).ok()?.to_string_lossy().into_owned())
+}
+
+/// Checks the index filename, whose contents are given, against its entry in the Release file release_filename; Err
+/// if it must not be loaded. An index the Release file does not list is only warned about.
+fn check_index(release: &ReleaseInfo, release_filename: &str, filename: &str, contents: &[u8], warnings: &mut Vec<String>) -> Result<(), LoadError> {
+    let entry = match path_in_release(release_filename, filename).and_then(|p| release.get_file(&p)) {
+        None => {
+            warnings.push(format!("{} is not listed in {}, so it cannot be checked", filename, release_filename));
+            return Ok(());
+        }
+        Some(entry) => entry
+    };
+    entry.verify_contents(contents).map_err(|mismatch|
+        LoadError::release(filename, format!("does not match its entry in {}: {}; not loading it", release_filename, mismatch)))
+}
+
+/// The header of the Release file release_filename (if any) that the index filename, whose contents are given, comes
+/// from, for its origin; Err if the Release file cannot be read or filename does not match it.
+pub(super) fn checked_release(filename: &str, contents: &[u8], release_filename: Option<&str>, warnings: &mut Vec<String>) -> Result<Option<ReleaseInfo>, LoadError> {
+    let release_filename = match release_filename {
+        None => return Ok(None),
+        Some(r) => r
+    };
+    let release = read_release(release_filename).map_err(|e| LoadError::from_read(release_filename, e))?;
+    check_index(&release, release_filename, filename, contents, warnings)?;
+    // the origin only needs the header, not the table of index files
+    Ok(Some(ReleaseInfo { files : vec![], ..release }))
+}
+
+impl Packages {
+    /// Loads the binary packages of one component and architecture of a suite from a local mirror tree:
+    /// dists/<suite>/InRelease (or Release), then the Packages index it lists for binary-<arch>, checked against the
+    /// Release file. The xz and gzip compressed indices are preferred, as mirrors may not carry the plain one.
+    pub fn load_suite(&mut self, mirror: &str, suite: &str, component: &str, arch: &str) -> Result<LoadReport, LoadError> {
+        let dists = Path::new(mirror).join("dists").join(suite);
+        let release_path = ["InRelease", "Release"].iter().map(|r| dists.join(r)).find(|p| p.exists());
+        let release_filename = match release_path {
+            None => return Err(LoadError::release(&dists.to_string_lossy(), String::from("no InRelease or Release file"))),
+            Some(p) => p.to_string_lossy().into_owned()
+        };
+        let release = read_release(&release_filename).map_err(|e| LoadError::from_read(&release_filename, e))?;
+        if !release.components.is_empty() && !release.components.iter().any(|c| c == component) {
+            let message = format!("no component {} (it has {})", component, release.components.join(", "));
+            return Err(LoadError::release(&release_filename, message));
+        }
+        let index = format!("{}/binary-{}/Packages", component, arch);
+        let filename = ["xz", "gz", ""].iter()
+            .map(|ext| if ext.is_empty() { index.clone() } else { format!("{}.{}", index, ext) })
+            .filter(|f| release.get_file(f).is_some())
+            .map(|f| dists.join(f))
+            .find(|p| p.exists());
+        match filename {
+            None => Err(LoadError::release(&release_filename, format!("no {} listed and present in the mirror", index))),
+            Some(f) => self.parse_packages(&f.to_string_lossy(), Some(&release_filename))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packages::LoadErrorKind;
+    use crate::packages::testutil::{gzip, xz, TempDir, TempFile};
+
+    const INDEX : &str = "Package: a\nVersion: 1.0\nArchitecture: amd64\n";
+
+    fn sha256(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    fn md5(data: &[u8]) -> String {
+        format!("{:x}", Md5::digest(data))
+    }
+
+    // a Release file listing each (path, contents) with its size, MD5 and SHA256
+    fn release(header: &str, files: &[(&str, &[u8])]) -> String {
+        let mut text = String::from(header);
+        for (field, hash) in [("MD5Sum", md5 as fn(&[u8]) -> String), ("SHA256", sha256)] {
+            text.push_str(field);
+            text.push_str(":\n");
+            for (path, contents) in files {
+                text.push_str(&format!(" {} {} {}\n", hash(contents), contents.len(), path));
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn verify() {
+        let index = TempFile::new(INDEX);
+        let entry = |size: usize, md5sum: Option<String>, sha256: Option<String>|
+            ReleaseFile { path : String::from("main/binary-amd64/Packages"), size : size as u64, md5sum, sha256 }.verify(index.path()).unwrap();
+        let (size, good_md5, good_sha256) = (INDEX.len(), Some(md5(INDEX.as_bytes())), Some(sha256(INDEX.as_bytes())));
+        assert_eq!(entry(size, good_md5.clone(), good_sha256.clone()), Ok(()));
+        assert_eq!(entry(size + 1, good_md5.clone(), good_sha256.clone()), Err(format!("size is {}, Release says {}", size, size + 1)));
+        // SHA256 is preferred, MD5 only used without it
+        assert!(entry(size, good_md5.clone(), Some(sha256(b"other"))).unwrap_err().starts_with("SHA256 is "));
+        assert_eq!(entry(size, Some(md5(b"other")), good_sha256), Ok(()));
+        assert_eq!(entry(size, good_md5, None), Ok(()));
+        assert!(entry(size, Some(md5(b"other")), None).unwrap_err().starts_with("MD5Sum is "));
+        assert_eq!(entry(size, None, None), Err(String::from("Release has no hash for it")));
+    }
+
+    #[test]
+    fn parse_release() {
+        let dir = TempDir::new();
+        let text = release("Origin: Debian\nSuite: stable\nCodename: bookworm\nComponents: main contrib\nNotAutomatic: yes\n",
+                           &[("main/binary-amd64/Packages", INDEX.as_bytes())]);
+        let info = read_release(&dir.file("Release", &text)).unwrap();
+        assert_eq!((info.origin.as_deref(), info.suite.as_deref(), info.codename.as_deref()), (Some("Debian"), Some("stable"), Some("bookworm")));
+        assert_eq!(info.components, ["main", "contrib"]);
+        assert!(info.not_automatic && !info.but_automatic_upgrades);
+        let file = info.get_file("main/binary-amd64/Packages").unwrap();
+        assert_eq!((file.size, file.md5sum.clone(), file.sha256.clone()),
+                   (INDEX.len() as u64, Some(md5(INDEX.as_bytes())), Some(sha256(INDEX.as_bytes()))));
+        assert!(info.get_file("main/binary-i386/Packages").is_none());
+    }
+
+    #[test]
+    fn armored_in_release() {
+        let dir = TempDir::new();
+        let text = "-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA512\n\n\
+                    Origin: Debian\n- Suite: stable\nMD5Sum:\n 0123 10 main/binary-amd64/Packages\n\
+                    -----BEGIN PGP SIGNATURE-----\n\niQIzBAEBCgAdFiEE\n=abcd\n-----END PGP SIGNATURE-----\n";
+        let info = read_release(&dir.file("InRelease", text)).unwrap();
+        assert_eq!((info.origin.as_deref(), info.suite.as_deref()), (Some("Debian"), Some("stable")));
+        assert_eq!(info.files.len(), 1);
+        // the armor alone has no Release paragraph
+        let empty = "-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA512\n\n-----BEGIN PGP SIGNATURE-----\n\n=abcd\n-----END PGP SIGNATURE-----\n";
+        assert_eq!(read_release(&dir.file("Empty", empty)).err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn indices_are_checked() {
+        let dir = TempDir::new();
+        let index = dir.file("main/binary-amd64/Packages", INDEX);
+        let listed = dir.file("Release", release("Suite: stable\n", &[("main/binary-amd64/Packages", INDEX.as_bytes())]));
+        let mut packages = Packages::new();
+        let report = packages.parse_packages(&index, Some(&listed)).unwrap();
+        assert!(report.warnings.is_empty() && report.loaded == 1);
+        assert_eq!(packages.origins()[0].release.as_ref().unwrap().suite.as_deref(), Some("stable"));
+
+        let resized = dir.file("Resized", release("", &[("main/binary-amd64/Packages", b"Package: a\n")]));
+        let error = packages.parse_packages(&index, Some(&resized)).unwrap_err();
+        assert!(matches!(&error.kind, LoadErrorKind::Release(m) if m.contains("size is")), "{}", error);
+        let mut changed = release("", &[("main/binary-amd64/Packages", INDEX.as_bytes())]);
+        changed = changed.replace(&sha256(INDEX.as_bytes()), &sha256(b"other"));
+        let error = packages.parse_packages(&index, Some(&dir.file("Changed", changed))).unwrap_err();
+        assert!(matches!(&error.kind, LoadErrorKind::Release(m) if m.contains("SHA256 is")), "{}", error);
+
+        // an index the Release file does not list is loaded, with a warning
+        let unlisted = dir.file("Unlisted", release("", &[("main/binary-i386/Packages", INDEX.as_bytes())]));
+        let report = packages.parse_packages(&index, Some(&unlisted)).unwrap();
+        assert_eq!(report.loaded, 1);
+        assert!(report.warnings[0].contains("is not listed in"), "{:?}", report.warnings);
+    }
+
+    #[test]
+    fn load_suite_prefers_compressed_indices() {
+        let mirror = TempDir::new();
+        let (gz, xz) = (gzip(INDEX.as_bytes()), xz(INDEX.as_bytes()));
+        let files : [(&str, &[u8]); 3] = [("main/binary-amd64/Packages", INDEX.as_bytes()),
+                                          ("main/binary-amd64/Packages.gz", &gz), ("main/binary-amd64/Packages.xz", &xz)];
+        for (path, contents) in &files {
+            mirror.file(&format!("dists/stable/{}", path), contents);
+        }
+        mirror.file("dists/stable/Release", release("Suite: stable\nComponents: main\n", &files));
+        let loaded = |packages: &Packages| packages.origins().last().unwrap().path.rsplit('/').next().unwrap().to_string();
+        let mut packages = Packages::new();
+        assert!(packages.load_suite(mirror.path(), "stable", "main", "amd64").unwrap().warnings.is_empty());
+        assert_eq!(loaded(&packages), "Packages.xz");
+        // the first one listed and present
+        fs::remove_file(format!("{}/dists/stable/main/binary-amd64/Packages.xz", mirror.path())).unwrap();
+        packages.load_suite(mirror.path(), "stable", "main", "amd64").unwrap();
+        assert_eq!(loaded(&packages), "Packages.gz");
+        mirror.file("dists/stable/Release", release("Suite: stable\nComponents: main\n", &files[..1]));
+        packages.load_suite(mirror.path(), "stable", "main", "amd64").unwrap();
+        assert_eq!(loaded(&packages), "Packages");
+        assert_eq!(packages.get_available_versions(packages.package_num("a").unwrap()).len(), 1);
+
+        assert!(packages.load_suite(mirror.path(), "stable", "contrib", "amd64").unwrap_err().to_string().contains("no component contrib"));
+        assert!(packages.load_suite(mirror.path(), "stable", "main", "i386").unwrap_err().to_string().contains("binary-i386/Packages listed"));
+        assert!(packages.load_suite(mirror.path(), "unstable", "main", "amd64").is_err());
+    }
+}
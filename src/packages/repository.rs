@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::debversion::DebianVersionNum;
+
+use crate::Packages;
+use crate::packages::{Dependency, RelationKind};
+use crate::packages::interner::PackageTable;
+use crate::packages::providers::Provided;
+use crate::packages::release::ReleaseInfo;
+use crate::packages::preferences::DOWNGRADE_PRIORITY;
+use crate::packages::multiarch::MultiArch;
+use crate::packages::mapped::{StanzaText, Text};
+
+/// Where available versions came from: an index file and, if we were given its Release file, the archive it belongs to.
+#[derive(Serialize,Deserialize)]
+pub struct Origin {
+    pub path : String,
+    pub release : Option<ReleaseInfo>,
+    pub component : Option<String>
+}
+
+impl Origin {
+    pub fn new(path: &str, release: Option<ReleaseInfo>) -> Origin {
+        // dists/<suite>/<component>/binary-<arch>/Packages, or the only component listed in the Release file
+        let from_path = Path::new(path).parent()
+            .filter(|dir| dir.file_name().is_some_and(|d| d.to_string_lossy().starts_with("binary-")))
+            .and_then(|dir| dir.parent()).and_then(|dir| dir.file_name())
+            .map(|c| c.to_string_lossy().into_owned());
+        let component = from_path.or_else(|| match &release {
+            Some(r) if r.components.len() == 1 => Some(r.components[0].clone()),
+            _ => None
+        });
+        Origin { path : path.to_string(), release, component }
+    }
+
+    /// The archive site of an index downloaded by apt, whose file name is the URL with '_' for '/', e.g.
+    /// deb.debian.org_debian_dists_sid_main_binary-amd64_Packages; None for other files.
+    pub fn site(&self) -> Option<&str> {
+        let file_name = self.path.rsplit('/').next().unwrap_or(&self.path);
+        if !file_name.contains("_dists_") { return None }
+        file_name.split('_').next()
+    }
+}
+
+impl fmt::Display for Origin {
+    // e.g. "Debian unstable/main (dists/sid/main/binary-amd64/Packages)"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(r) = &self.release {
+            let origin = r.origin.as_deref().or(r.label.as_deref()).unwrap_or("unknown");
+            let suite = r.suite.as_deref().or(r.codename.as_deref()).unwrap_or("unknown");
+            write!(f, "{} {}", origin, suite)?;
+            if let Some(c) = &self.component { write!(f, "/{}", c)?; }
+            return write!(f, " ({})", self.path);
+        }
+        write!(f, "{}", self.path)
+    }
+}
+
+/// The same package version was loaded from two origins with different MD5sums.
+#[derive(Serialize,Deserialize)]
+pub struct IntegrityConflict {
+    pub package_num : u32,
+    pub version : DebianVersionNum,
+    pub first : (usize, String),
+    pub second : (usize, String)
+}
+
+/// One version of a package from a Packages index (or CSV file), with everything that varies between versions.
+/// A package can have several of these, e.g. when both stable and backports are loaded.
+#[derive(Serialize,Deserialize)]
+pub struct AvailableVersion {
+    pub version : DebianVersionNum,
+    pub md5sum : Option<Text>,
+    // None if the stanza has no Architecture field (e.g. from a CSV file), which matches any architecture
+    pub architecture : Option<String>,
+    pub multi_arch : MultiArch,
+    pub relations : HashMap<RelationKind,Vec<Dependency>>,
+    pub provides : Vec<Provided>,
+    pub paragraph : StanzaText,
+    // indices into Packages::origins of every source this version was seen in
+    pub origins : Vec<usize>
+}
+
+impl AvailableVersion {
+    pub fn get_relations(&self, kind: RelationKind) -> &[Dependency] {
+        match self.relations.get(&kind) {
+            None => &[],
+            Some(deps) => deps
+        }
+    }
+}
+
+impl Packages {
+    /// Registers an index file (and its Release information) as an origin, returning its index in origins.
+    /// Loading the same file again reuses its origin.
+    pub(super) fn add_origin(&mut self, path: &str, release: Option<ReleaseInfo>) -> usize {
+        self.clear_candidates();
+        let origin = Origin::new(path, release);
+        match self.origins.iter().position(|o| o.path == origin.path) {
+            Some(i) => { self.origins[i] = origin; i }
+            None => { self.origins.push(origin); self.origins.len() - 1 }
+        }
+    }
+
+    /// Adds a version of package_num (seen in one origin) to the repository. Versions are told apart by version
+    /// number and architecture. If the version is already known, the origin is added to it; if the MD5sums differ, the
+    /// first one is kept and an integrity conflict is recorded (and warned about), once.
+    pub(super) fn add_available_version(&mut self, package_num: u32, available: AvailableVersion, warnings: &mut Vec<String>) {
+        self.clear_candidates();
+        for provided in &available.provides {
+            let providers = self.available_providers.entry(provided.package_num);
+            if !providers.contains(&package_num) {
+                providers.push(package_num);
+            }
+        }
+        let versions = self.available.entry(package_num);
+        let existing = match versions.iter_mut().find(|v| v.version == available.version && v.architecture == available.architecture) {
+            None => { versions.push(available); return }
+            Some(existing) => existing
+        };
+        let origin = available.origins[0];
+        match (&existing.md5sum, &available.md5sum) {
+            // loading an origin again keeps the first MD5sum, unless the version only came from that origin
+            (Some(m1), Some(m2)) if m1 != m2 && existing.origins.contains(&origin) && existing.origins != [origin] => (),
+            (Some(m1), Some(m2)) if m1 != m2 && !existing.origins.contains(&origin) => {
+                let conflict = IntegrityConflict {
+                    package_num, version : available.version.clone(),
+                    first : (existing.origins[0], m1.to_string()), second : (origin, m2.to_string())
+                };
+                existing.origins.push(origin);
+                warnings.push(format!("integrity conflict: {} {} has MD5sum {} in {} but {} in {}", self.shown_name(package_num),
+                         conflict.version, conflict.first.1, self.origins[conflict.first.0], conflict.second.1, self.origins[origin]));
+                self.integrity_conflicts.push(conflict);
+            }
+            _ => {
+                let mut origins = std::mem::take(&mut existing.origins);
+                if !origins.contains(&origin) { origins.push(origin); }
+                *existing = AvailableVersion { origins, ..available };
+            }
+        }
+    }
+
+    /// Integrity conflicts involving package_num.
+    pub fn get_integrity_conflicts(&self, package_num: u32) -> impl Iterator<Item = &IntegrityConflict> {
+        self.integrity_conflicts.iter().filter(move |c| c.package_num == package_num)
+    }
+
+    /// Every version that was loaded with different MD5sums from different origins.
+    pub fn integrity_conflicts(&self) -> &[IntegrityConflict] {
+        &self.integrity_conflicts
+    }
+
+    /// Every index file loaded so far; AvailableVersion::origins and IntegrityConflict refer to them by position.
+    pub fn origins(&self) -> &[Origin] {
+        &self.origins
+    }
+
+    /// All known available versions of package_num, in the order they were loaded.
+    pub fn get_available_versions(&self, package_num: u32) -> &[AvailableVersion] {
+        match self.available.get(package_num) {
+            None => &[],
+            Some(versions) => versions
+        }
+    }
+
+    /// The version that would be installed for package_num: the available version with the highest pin priority (the
+    /// newest among equals). Versions with a negative priority are never candidates, and versions older than the installed
+    /// one only with a priority of at least 1000. Only versions of the native architecture (or Architecture: all) are
+    /// considered. None if the installed version wins, or nothing is available. The candidates of all packages are
+    /// worked out on the first call and kept until something is loaded.
+    pub fn get_candidate(&self, package_num: u32) -> Option<&AvailableVersion> {
+        let candidates = self.candidates.get_or_init(|| {
+            let mut candidates = PackageTable::default();
+            for (package_num, _) in self.available.iter() {
+                *candidates.entry(package_num) = self.find_candidate(package_num);
+            }
+            candidates
+        });
+        candidates.value(package_num).map(|i| &self.get_available_versions(package_num)[*i])
+    }
+
+    // the candidate of package_num as get_candidate describes it, by position in its available versions
+    fn find_candidate(&self, package_num: u32) -> Option<usize> {
+        let installed = self.installed_debver(package_num);
+        let mut best : Option<(i32, usize, &AvailableVersion)> = None;
+        for (n, v) in self.get_available_versions(package_num).iter().enumerate() {
+            if !self.is_native_arch(v.architecture.as_deref()) { continue }
+            let priority = self.version_priority(package_num, &v.version);
+            if priority < 0 { continue }
+            if priority < DOWNGRADE_PRIORITY && installed.is_some_and(|i| v.version < *i) { continue }
+            if best.is_none_or(|(p, _, b)| (priority, &v.version) > (p, &b.version)) {
+                best = Some((priority, n, v));
+            }
+        }
+        // an installed version that is no longer available stays if nothing beats it
+        if let (Some(i), Some((p, _, b))) = (installed, best) {
+            if (self.version_priority(package_num, i), i) > (p, &b.version) {
+                return None;
+            }
+        }
+        best.map(|(_, n, _)| n)
+    }
+
+    // forgets the candidates, which are computed again when next asked for; anything that changes the available or
+    // installed versions, the origins or the preferences calls it
+    pub(super) fn clear_candidates(&mut self) {
+        self.candidates = OnceLock::new();
+    }
+
+    pub fn get_candidate_debver(&self, package_num: u32) -> Option<&DebianVersionNum> {
+        self.get_candidate(package_num).map(|c| &c.version)
+    }
+
+    /// Finds the available version of package_num whose version string is version, preferring the native architecture.
+    pub fn get_available_version(&self, package_num: u32, version: &str) -> Option<&AvailableVersion> {
+        let version = version.parse::<DebianVersionNum>().ok()?;
+        self.get_available_versions(package_num).iter().filter(|v| v.version == version)
+            .min_by_key(|v| !self.is_native_arch(v.architecture.as_deref()))
+    }
+
+    /// Number of packages with at least one available version.
+    pub fn available_count(&self) -> usize {
+        self.available.values().filter(|v| !v.is_empty()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Packages;
+    use crate::packages::testutil::{load, num, TempFile};
+
+    // loads each index in turn, returning the warnings of every load
+    fn load_indices(packages: &mut Packages, indices: &[&TempFile]) -> Vec<String> {
+        indices.iter().flat_map(|i| packages.parse_packages(i.path(), None).unwrap().warnings).collect()
+    }
+
+    #[test]
+    fn same_version_from_several_origins() {
+        let stable = TempFile::new("Package: a\nVersion: 1\nArchitecture: amd64\nMD5sum: 11\n\nPackage: b\nVersion: 1\nArchitecture: amd64\n");
+        let backports = TempFile::new("Package: a\nVersion: 1\nArchitecture: amd64\nMD5sum: 11\n\n\
+                                       Package: a\nVersion: 2\nArchitecture: amd64\nMD5sum: 22\n\n\
+                                       Package: b\nVersion: 1\nArchitecture: all\n");
+        let mut packages = Packages::new();
+        assert!(load_indices(&mut packages, &[&stable, &backports, &stable]).is_empty());
+        assert_eq!(packages.origins().len(), 2);
+        let versions : Vec<(String, Vec<usize>)> = packages.get_available_versions(num(&packages, "a")).iter()
+            .map(|v| (v.version.to_string(), v.origins.clone())).collect();
+        assert_eq!(versions, [(String::from("1"), vec![0, 1]), (String::from("2"), vec![1])]);
+        // versions are told apart by architecture too
+        assert_eq!(packages.get_available_versions(num(&packages, "b")).len(), 2);
+        assert!(packages.integrity_conflicts().is_empty());
+    }
+
+    // the first MD5sum is kept, and the clash is recorded once
+    #[test]
+    fn integrity_conflicts() {
+        let stable = TempFile::new("Package: a\nVersion: 1\nMD5sum: 11\n");
+        let mirror = TempFile::new("Package: a\nVersion: 1\nMD5sum: 99\n");
+        let mut packages = Packages::new();
+        let warnings = load_indices(&mut packages, &[&stable, &mirror, &mirror]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("integrity conflict: a 1 has MD5sum 11 in "), "{}", warnings[0]);
+        let a = num(&packages, "a");
+        let conflicts : Vec<_> = packages.get_integrity_conflicts(a).collect();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!((conflicts[0].first.clone(), conflicts[0].second.clone()), ((0, String::from("11")), (1, String::from("99"))));
+        let versions = packages.get_available_versions(a);
+        assert_eq!((versions.len(), versions[0].md5sum.as_deref(), versions[0].origins.clone()), (1, Some("11"), vec![0, 1]));
+    }
+
+    #[test]
+    fn candidates() {
+        let packages = load("Package: a\nVersion: 1.0\nArchitecture: amd64\n\nPackage: a\nVersion: 1.2\nArchitecture: amd64\n\n\
+                             Package: a\nVersion: 1.1\nArchitecture: amd64\n\nPackage: a\nVersion: 2.0\nArchitecture: i386\n\n\
+                             Package: b\nVersion: 1\nArchitecture: all\n\nPackage: b\nVersion: 1\nArchitecture: amd64\n\n\
+                             Package: c\nVersion: 1\nArchitecture: amd64\n\nPackage: d\nVersion: 2\nArchitecture: amd64\n",
+                            "Package: dpkg\nStatus: install ok installed\nVersion: 1\nArchitecture: amd64\n\n\
+                             Package: c\nStatus: install ok installed\nVersion: 2\nArchitecture: amd64\n\n\
+                             Package: d\nStatus: install ok installed\nVersion: 2\nArchitecture: amd64\n");
+        let candidate = |name: &str| packages.get_candidate(num(&packages, name)).map(|c| (c.version.to_string(), c.architecture.clone()));
+        // the newest of the native architecture, wherever it is in the index
+        assert_eq!(candidate("a"), Some((String::from("1.2"), Some(String::from("amd64")))));
+        // between equals, the first loaded
+        assert_eq!(candidate("b"), Some((String::from("1"), Some(String::from("all")))));
+        // no downgrade to an older available version
+        assert_eq!(candidate("c"), None);
+        // the installed version is available
+        assert_eq!(candidate("d"), Some((String::from("2"), Some(String::from("amd64")))));
+        assert!(packages.get_candidate(num(&packages, "dpkg")).is_none());
+    }
+}
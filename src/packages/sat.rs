@@ -0,0 +1,372 @@
+use std::collections::HashSet;
+
+// A small CDCL SAT solver (watched literals, first-UIP clause learning, non-chronological backjumping) for the
+// installation planner. The caller picks every decision, so that the search follows apt-like preferences, and gets
+// back either a model or the original clauses behind the proof that there is none.
+
+/// A variable (var << 1) or its negation (var << 1 | 1).
+pub(super) type Lit = u32;
+
+pub(super) fn lit(var: usize, positive: bool) -> Lit {
+    (var as u32) << 1 | if positive { 0 } else { 1 }
+}
+
+pub(super) fn lit_var(l: Lit) -> usize {
+    (l >> 1) as usize
+}
+
+pub(super) fn is_positive(l: Lit) -> bool {
+    l & 1 == 0
+}
+
+fn negate(l: Lit) -> Lit {
+    l ^ 1
+}
+
+pub(super) struct Sat {
+    // original clauses first, then learned ones; the two watched literals of a clause are its first two
+    clauses : Vec<Vec<Lit>>,
+    // for a learned clause, the clauses it was resolved from; empty for original clauses
+    antecedents : Vec<Vec<usize>>,
+    // per literal, the clauses watching it
+    watches : Vec<Vec<usize>>,
+    // per variable: its value, the decision level and the clause that implied it (None for decisions)
+    values : Vec<Option<bool>>,
+    levels : Vec<usize>,
+    reasons : Vec<Option<usize>>,
+    trail : Vec<Lit>,
+    // where each decision level starts in trail
+    trail_lim : Vec<usize>,
+    // trail[..qhead] has been propagated
+    qhead : usize,
+    // original clauses with a single literal, or none at all
+    units : Vec<usize>,
+    conflicts : usize
+}
+
+impl Sat {
+    pub(super) fn new(vars: usize) -> Sat {
+        Sat {
+            clauses : vec![], antecedents : vec![], watches : vec![vec![]; 2 * vars],
+            values : vec![None; vars], levels : vec![0; vars], reasons : vec![None; vars],
+            trail : vec![], trail_lim : vec![], qhead : 0, units : vec![], conflicts : 0
+        }
+    }
+
+    /// Adds an original clause (before solve), returning its index for explanations.
+    pub(super) fn add_clause(&mut self, mut lits: Vec<Lit>) -> usize {
+        let mut seen = HashSet::new();
+        lits.retain(|l| seen.insert(*l));
+        let c = self.clauses.len();
+        if lits.len() < 2 {
+            self.units.push(c);
+        } else {
+            self.watches[lits[0] as usize].push(c);
+            self.watches[lits[1] as usize].push(c);
+        }
+        self.clauses.push(lits);
+        self.antecedents.push(vec![]);
+        c
+    }
+
+    pub(super) fn value(&self, l: Lit) -> Option<bool> {
+        self.values[lit_var(l)].map(|v| v == is_positive(l))
+    }
+
+    /// Everything assigned so far, in order.
+    pub(super) fn trail(&self) -> &[Lit] {
+        &self.trail
+    }
+
+    /// How many conflicts the search ran into; when this changes, assignments may have been undone.
+    pub(super) fn conflicts(&self) -> usize {
+        self.conflicts
+    }
+
+    fn level(&self) -> usize {
+        self.trail_lim.len()
+    }
+
+    fn enqueue(&mut self, l: Lit, reason: Option<usize>) {
+        let var = lit_var(l);
+        self.values[var] = Some(is_positive(l));
+        self.levels[var] = self.level();
+        self.reasons[var] = reason;
+        self.trail.push(l);
+    }
+
+    // unit propagation; returns the clause that became false, if any
+    fn propagate(&mut self) -> Option<usize> {
+        while self.qhead < self.trail.len() {
+            let false_lit = negate(self.trail[self.qhead]);
+            self.qhead += 1;
+            let watching = std::mem::take(&mut self.watches[false_lit as usize]);
+            let mut kept = Vec::with_capacity(watching.len());
+            let mut conflict = None;
+            for (i, &c) in watching.iter().enumerate() {
+                if conflict.is_some() {
+                    kept.extend_from_slice(&watching[i..]);
+                    break;
+                }
+                if self.clauses[c][0] == false_lit {
+                    self.clauses[c].swap(0, 1);
+                }
+                let first = self.clauses[c][0];
+                if self.value(first) == Some(true) {
+                    kept.push(c);
+                    continue;
+                }
+                match (2..self.clauses[c].len()).find(|k| self.value(self.clauses[c][*k]) != Some(false)) {
+                    Some(k) => {
+                        self.clauses[c].swap(1, k);
+                        self.watches[self.clauses[c][1] as usize].push(c);
+                    }
+                    None => {
+                        kept.push(c);
+                        if self.value(first) == Some(false) {
+                            conflict = Some(c);
+                        } else {
+                            self.enqueue(first, Some(c));
+                        }
+                    }
+                }
+            }
+            self.watches[false_lit as usize] = kept;
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    // the reasons of the level 0 assignments behind lits, and of those behind them
+    fn level_zero_reasons(&self, lits: &[Lit], seen: &mut HashSet<usize>, into: &mut Vec<usize>) {
+        let mut stack : Vec<usize> = lits.iter().map(|l| lit_var(*l)).collect();
+        while let Some(var) = stack.pop() {
+            if self.levels[var] != 0 || !seen.insert(var) { continue }
+            if let Some(r) = self.reasons[var] {
+                into.push(r);
+                stack.extend(self.clauses[r].iter().map(|l| lit_var(*l)));
+            }
+        }
+    }
+
+    // first-UIP conflict analysis: the learned clause (asserting literal first), the clauses it was resolved from, and
+    // the level to jump back to
+    fn analyze(&self, conflict: usize) -> (Vec<Lit>, Vec<usize>, usize) {
+        let mut learnt = vec![0];
+        let mut antecedents = vec![conflict];
+        let mut seen = vec![false; self.values.len()];
+        let mut zero = vec![];
+        let mut pending = 0;
+        let mut clause = conflict;
+        let mut index = self.trail.len();
+        let asserting = loop {
+            for &q in &self.clauses[clause] {
+                let var = lit_var(q);
+                if seen[var] || self.reasons[var] == Some(clause) { continue }
+                seen[var] = true;
+                if self.levels[var] == self.level() {
+                    pending += 1;
+                } else if self.levels[var] > 0 {
+                    learnt.push(q);
+                } else {
+                    zero.push(q);
+                }
+            }
+            loop {
+                index -= 1;
+                if seen[lit_var(self.trail[index])] { break }
+            }
+            let p = self.trail[index];
+            pending -= 1;
+            if pending == 0 { break p }
+            clause = self.reasons[lit_var(p)].unwrap();
+            antecedents.push(clause);
+        };
+        learnt[0] = negate(asserting);
+        self.level_zero_reasons(&zero, &mut HashSet::new(), &mut antecedents);
+        let mut backjump = 0;
+        if learnt.len() > 1 {
+            let (i, _) = learnt.iter().enumerate().skip(1).max_by_key(|(_, l)| self.levels[lit_var(**l)]).unwrap();
+            learnt.swap(1, i);
+            backjump = self.levels[lit_var(learnt[1])];
+        }
+        (learnt, antecedents, backjump)
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.level() <= level { return }
+        let start = self.trail_lim[level];
+        for l in self.trail.drain(start..) {
+            self.values[lit_var(l)] = None;
+            self.reasons[lit_var(l)] = None;
+        }
+        self.trail_lim.truncate(level);
+        self.qhead = start;
+    }
+
+    // the original clauses a proof of unsatisfiability rests on, when conflict is false at level 0
+    fn core(&self, conflict: usize) -> Vec<usize> {
+        let mut pending = vec![conflict];
+        self.level_zero_reasons(&self.clauses[conflict], &mut HashSet::new(), &mut pending);
+        let mut visited = HashSet::new();
+        let mut core = vec![];
+        while let Some(c) = pending.pop() {
+            if !visited.insert(c) { continue }
+            if self.antecedents[c].is_empty() {
+                core.push(c);
+            } else {
+                pending.extend(&self.antecedents[c]);
+            }
+        }
+        core.sort_unstable();
+        core
+    }
+
+    /// Searches for an assignment satisfying every clause. decide is asked for the next decision whenever propagation
+    /// is done, and must return an unassigned literal, or None once every variable is assigned. Returns the value of
+    /// each variable, or the original clauses that cannot all hold, in order.
+    pub(super) fn solve(&mut self, mut decide: impl FnMut(&Sat) -> Option<Lit>) -> Result<Vec<bool>, Vec<usize>> {
+        for c in self.units.clone() {
+            match self.clauses[c].first().copied() {
+                None => return Err(vec![c]),
+                Some(l) => match self.value(l) {
+                    Some(false) => return Err(self.core(c)),
+                    Some(true) => (),
+                    None => self.enqueue(l, Some(c))
+                }
+            }
+        }
+        loop {
+            if let Some(conflict) = self.propagate() {
+                self.conflicts += 1;
+                if self.level() == 0 {
+                    return Err(self.core(conflict));
+                }
+                let (learnt, antecedents, backjump) = self.analyze(conflict);
+                self.backtrack(backjump);
+                let c = self.clauses.len();
+                if learnt.len() > 1 {
+                    self.watches[learnt[0] as usize].push(c);
+                    self.watches[learnt[1] as usize].push(c);
+                }
+                let asserting = learnt[0];
+                self.clauses.push(learnt);
+                self.antecedents.push(antecedents);
+                self.enqueue(asserting, Some(c));
+                continue;
+            }
+            match decide(self) {
+                None => return Ok(self.values.iter().map(|v| v.unwrap_or(false)).collect()),
+                Some(l) => {
+                    debug_assert!(self.value(l).is_none());
+                    self.trail_lim.push(self.trail.len());
+                    self.enqueue(l, None);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // decides the first unassigned variable of vars that is left, positively, then every other variable negatively
+    fn decide_in_order(vars: &[usize]) -> impl FnMut(&Sat) -> Option<Lit> + '_ {
+        move |sat| vars.iter().map(|v| lit(*v, true)).chain((0..sat.values.len()).map(|v| lit(v, false)))
+            .find(|l| sat.value(*l).is_none())
+    }
+
+    fn holds(clause: &[Lit], model: &[bool]) -> bool {
+        clause.iter().any(|l| model[lit_var(*l)] == is_positive(*l))
+    }
+
+    // whether some assignment of vars variables satisfies every clause, trying them all
+    fn satisfiable(vars: usize, clauses: &[&Vec<Lit>]) -> bool {
+        (0..1u32 << vars).any(|bits| {
+            let model : Vec<bool> = (0..vars).map(|v| bits >> v & 1 == 1).collect();
+            clauses.iter().all(|c| holds(c, &model))
+        })
+    }
+
+    fn formula(vars: usize, clauses: &[Vec<Lit>]) -> Sat {
+        let mut sat = Sat::new(vars);
+        for c in clauses {
+            sat.add_clause(c.clone());
+        }
+        sat
+    }
+
+    #[test]
+    fn model_satisfies_every_clause() {
+        let clauses = vec![
+            vec![lit(0, true), lit(1, true)],
+            vec![lit(0, false), lit(2, true)],
+            vec![lit(1, false), lit(2, false)],
+            vec![lit(3, true), lit(3, true), lit(1, true)],
+            vec![lit(0, true)]
+        ];
+        let model = formula(4, &clauses).solve(decide_in_order(&[1])).unwrap();
+        assert!(clauses.iter().all(|c| holds(c, &model)));
+        assert_eq!(model, [true, false, true, true]);
+    }
+
+    // three pigeons in two holes (variable 2 * pigeon + hole), next to a satisfiable part on variables 6 and 7
+    #[test]
+    fn core_is_unsatisfiable_and_original() {
+        let p = |pigeon: usize, hole: usize, positive: bool| lit(2 * pigeon + hole, positive);
+        let mut clauses : Vec<Vec<Lit>> = vec![vec![lit(6, true), lit(7, true)], vec![lit(6, false), lit(7, false)]];
+        for pigeon in 0..3 {
+            clauses.push(vec![p(pigeon, 0, true), p(pigeon, 1, true)]);
+        }
+        for hole in 0..2 {
+            for (a, b) in [(0, 1), (0, 2), (1, 2)] {
+                clauses.push(vec![p(a, hole, false), p(b, hole, false)]);
+            }
+        }
+        let mut sat = formula(8, &clauses);
+        let core = sat.solve(decide_in_order(&[6, 0, 2])).unwrap_err();
+        assert!(sat.conflicts() > 1 && sat.clauses.len() > clauses.len());
+        assert!(core.windows(2).all(|w| w[0] < w[1]));
+        assert!(core.iter().all(|c| *c < clauses.len()));
+        assert!(!core.contains(&0) && !core.contains(&1));
+        assert!(!satisfiable(8, &core.iter().map(|c| &clauses[*c]).collect::<Vec<_>>()));
+    }
+
+    // deciding a, then the unrelated b, then c breaks a clause; the clause learned from it, (not a or not c), sends the
+    // search back to the level of a, undoing b, and asserts not c there
+    #[test]
+    fn conflict_learns_a_clause_and_backjumps() {
+        let (a, b, c, d) = (0, 1, 2, 3);
+        let clauses = vec![vec![lit(a, false), lit(c, false), lit(d, true)], vec![lit(a, false), lit(c, false), lit(d, false)]];
+        let mut sat = formula(4, &clauses);
+        let decisions = [a, b, c];
+        let mut order = decide_in_order(&decisions);
+        let mut after_conflict = None;
+        let model = sat.solve(|sat| {
+            if sat.conflicts() == 1 && after_conflict.is_none() {
+                after_conflict = Some((sat.level(), sat.value(lit(a, true)), sat.value(lit(b, true)), sat.value(lit(c, true))));
+                assert_eq!(sat.clauses[clauses.len()], [lit(c, false), lit(a, false)]);
+                assert_eq!(sat.antecedents[clauses.len()].len(), 2);
+            }
+            order(sat)
+        }).unwrap();
+        assert_eq!(after_conflict, Some((1, Some(true), None, Some(false))));
+        assert!(clauses.iter().all(|c| holds(c, &model)));
+        assert_eq!(model, [true, true, false, false]);
+    }
+
+    #[test]
+    fn empty_clause_is_its_own_core() {
+        let mut sat = formula(1, &[vec![lit(0, true)], vec![]]);
+        assert_eq!(sat.solve(decide_in_order(&[])), Err(vec![1]));
+    }
+
+    #[test]
+    fn contradictory_units() {
+        let mut sat = formula(2, &[vec![lit(1, true), lit(0, true)], vec![lit(0, true)], vec![lit(0, false)]]);
+        assert_eq!(sat.solve(decide_in_order(&[])), Err(vec![1, 2]));
+    }
+}
@@ -0,0 +1,242 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Packages;
+
+// A snapshot is the magic bytes, then (with bincode) the format, the files the state was loaded from, and the whole
+// Packages state except session settings such as the load mode and the server. Loading one is much faster than parsing
+// those files again, but only correct while they are unchanged, so their stamps are checked first.
+
+const SNAPSHOT_MAGIC : [u8; 8] = *b"RPKGSNAP";
+// bump whenever Packages (or anything it contains) changes shape; older snapshots are then refused
+const SNAPSHOT_FORMAT : u32 = 3;
+
+/// A file the state was loaded from, with its size and modification time when it was loaded.
+#[derive(Clone,PartialEq,Eq,Debug,Serialize,Deserialize)]
+pub struct FileStamp {
+    pub path : String,
+    pub size : u64,
+    pub modified : SystemTime
+}
+
+impl FileStamp {
+    pub fn of(path: &str) -> io::Result<FileStamp> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp { path : path.to_string(), size : metadata.len(), modified : metadata.modified()? })
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    // not a snapshot, one from another format, or a damaged one
+    Format(String),
+    // a file the snapshot was made from has changed (or is gone) since
+    Stale(String)
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::Format(message) => write!(f, "not a usable snapshot: {}", message),
+            SnapshotError::Stale(message) => write!(f, "stale snapshot: {}; load the files again and save a new one", message)
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> SnapshotError {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(e: bincode::Error) -> SnapshotError {
+        match *e {
+            bincode::ErrorKind::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => SnapshotError::Format(String::from("truncated")),
+            bincode::ErrorKind::Io(e) => SnapshotError::Io(e),
+            e => SnapshotError::Format(e.to_string())
+        }
+    }
+}
+
+// why the file stamp no longer describes the file, if it does not
+fn check_stamp(stamp: &FileStamp) -> Result<(), String> {
+    match FileStamp::of(&stamp.path) {
+        Err(e) => Err(format!("{}: {}", stamp.path, e)),
+        Ok(now) if now.size != stamp.size => Err(format!("{} changed size ({} bytes, was {})", stamp.path, now.size, stamp.size)),
+        Ok(now) if now.modified != stamp.modified => Err(format!("{} was modified", stamp.path)),
+        Ok(_) => Ok(())
+    }
+}
+
+impl Packages {
+    /// Records that path is being loaded, with its current size and modification time, replacing an earlier stamp.
+    pub(super) fn record_loaded_file(&mut self, path: &str, warnings: &mut Vec<String>) {
+        let stamp = match FileStamp::of(path) {
+            // the file was just opened, so this hardly happens; a snapshot then cannot check it
+            Err(e) => { warnings.push(format!("{}: {}; snapshots will not notice if it changes", path, e)); return }
+            Ok(stamp) => stamp
+        };
+        match self.loaded_files.iter_mut().find(|f| f.path == path) {
+            Some(existing) => *existing = stamp,
+            None => self.loaded_files.push(stamp)
+        }
+    }
+
+    /// Writes the whole state to filename (through a temporary file, so that an existing snapshot is replaced only
+    /// once the new one is complete).
+    pub fn save_snapshot(&self, filename: &str) -> Result<(), SnapshotError> {
+        let temporary = format!("{}.tmp", filename);
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        writer.write_all(&SNAPSHOT_MAGIC)?;
+        bincode::serialize_into(&mut writer, &SNAPSHOT_FORMAT)?;
+        bincode::serialize_into(&mut writer, &self.loaded_files)?;
+        bincode::serialize_into(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temporary, filename)?;
+        Ok(())
+    }
+
+    /// Replaces the state with the snapshot in filename, unless one of the files it was loaded from has changed
+    /// since, in which case nothing is loaded. The load mode and server are kept, as are requests not yet sent.
+    pub fn load_snapshot(&mut self, filename: &str) -> Result<(), SnapshotError> {
+        let mut reader = BufReader::new(File::open(filename)?);
+        let mut magic = [0; 8];
+        if reader.read_exact(&mut magic).is_err() || magic != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::Format(String::from("no snapshot header")));
+        }
+        let format : u32 = bincode::deserialize_from(&mut reader)?;
+        if format != SNAPSHOT_FORMAT {
+            return Err(SnapshotError::Format(format!("format {}, expected {}", format, SNAPSHOT_FORMAT)));
+        }
+        let files : Vec<FileStamp> = bincode::deserialize_from(&mut reader)?;
+        for stamp in &files {
+            check_stamp(stamp).map_err(SnapshotError::Stale)?;
+        }
+        let mut loaded : Packages = bincode::deserialize_from(&mut reader)?;
+        loaded.load_mode = self.load_mode;
+        loaded.index_storage = self.index_storage;
+        loaded.solver_mode = self.solver_mode;
+        std::mem::swap(&mut loaded.async_state, &mut self.async_state);
+        *self = loaded;
+        Ok(())
+    }
+
+    /// Every file the state was loaded from, with its size and modification time then.
+    pub fn loaded_files(&self) -> &[FileStamp] {
+        &self.loaded_files
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::packages::LoadMode;
+    use crate::packages::testutil::{num, TempDir};
+
+    const INDEX : &str = "Package: a\nVersion: 2.0\nArchitecture: amd64\nDepends: b (>= 1)\n\nPackage: b\nVersion: 1.5\nArchitecture: amd64\n";
+    const STATUS : &str = "Package: b\nStatus: install ok installed\nVersion: 1.0\nArchitecture: amd64\n";
+
+    // a Packages loaded from an index, a status file and preferences in dir, saved to dir/snapshot
+    fn saved(dir: &TempDir) -> (Packages, String) {
+        let mut packages = Packages::new();
+        packages.parse_installed(&dir.file("status", STATUS)).unwrap();
+        packages.parse_packages(&dir.file("Packages", INDEX), None).unwrap();
+        packages.parse_preferences(&dir.file("preferences", "Package: b\nPin: version 1.0\nPin-Priority: 1001\n")).unwrap();
+        let snapshot = format!("{}/snapshot", dir.path());
+        packages.save_snapshot(&snapshot).unwrap();
+        (packages, snapshot)
+    }
+
+    fn format_error(result: Result<(), SnapshotError>) -> String {
+        match result {
+            Err(SnapshotError::Format(message)) => message,
+            Err(e) => panic!("not a format error: {}", e),
+            Ok(()) => panic!("loaded")
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = TempDir::new();
+        let (saved, snapshot) = saved(&dir);
+        let mut loaded = Packages::new();
+        loaded.set_load_mode(LoadMode::Strict);
+        loaded.load_snapshot(&snapshot).unwrap();
+        assert_eq!(loaded.get_package_names(), saved.get_package_names());
+        assert_eq!(loaded.loaded_files(), saved.loaded_files());
+        assert_eq!(loaded.loaded_files().len(), 3);
+        let (a, b) = (num(&loaded, "a"), num(&loaded, "b"));
+        assert_eq!(loaded.installed_debver(b).map(|v| v.to_string()), Some(String::from("1.0")));
+        assert_eq!(loaded.deps2str(loaded.get_relations(crate::packages::RelationKind::Depends, a)).unwrap(), "b (>= 1)");
+        // the pin is kept, so b stays at its installed version
+        assert_eq!(loaded.preference_count(), 1);
+        assert!(loaded.get_candidate(b).is_none());
+        assert_eq!(loaded.get_candidate_debver(a).map(|v| v.to_string()), Some(String::from("2.0")));
+        // session settings are not part of it
+        assert_eq!(loaded.load_mode(), LoadMode::Strict);
+    }
+
+    #[test]
+    fn foreign_files_are_refused() {
+        let dir = TempDir::new();
+        let (_, snapshot) = saved(&dir);
+        let mut packages = Packages::new();
+        assert_eq!(format_error(packages.load_snapshot(&dir.file("empty", ""))), "no snapshot header");
+        assert_eq!(format_error(packages.load_snapshot(&dir.file("other", "Package: a\nVersion: 1\n"))), "no snapshot header");
+        let mut newer = SNAPSHOT_MAGIC.to_vec();
+        newer.extend(bincode::serialize(&(SNAPSHOT_FORMAT + 1)).unwrap());
+        assert_eq!(format_error(packages.load_snapshot(&dir.file("newer", newer))),
+                   format!("format {}, expected {}", SNAPSHOT_FORMAT + 1, SNAPSHOT_FORMAT));
+        let contents = fs::read(&snapshot).unwrap();
+        assert_eq!(format_error(packages.load_snapshot(&dir.file("truncated", &contents[..contents.len() - 10]))), "truncated");
+        assert!(matches!(packages.load_snapshot(&format!("{}/missing", dir.path())), Err(SnapshotError::Io(_))));
+        assert_eq!(packages.package_count(), 0);
+    }
+
+    #[test]
+    fn changed_files_make_it_stale() {
+        let dir = TempDir::new();
+        let (_, snapshot) = saved(&dir);
+        let stale = |packages: &mut Packages| match packages.load_snapshot(&snapshot) {
+            Err(SnapshotError::Stale(message)) => message,
+            _ => panic!("not stale")
+        };
+        let mut packages = Packages::new();
+        packages.load_snapshot(&snapshot).unwrap();
+
+        // same size, later modification time
+        let status = format!("{}/status", dir.path());
+        let file = File::options().write(true).open(&status).unwrap();
+        file.set_modified(fs::metadata(&status).unwrap().modified().unwrap() + Duration::from_secs(60)).unwrap();
+        drop(file);
+        let mut packages = Packages::new();
+        assert_eq!(stale(&mut packages), format!("{} was modified", status));
+        // nothing is loaded then
+        assert_eq!(packages.package_count(), 0);
+
+        dir.file("status", STATUS.to_string() + "\n");
+        assert_eq!(stale(&mut packages), format!("{} changed size ({} bytes, was {})", status, STATUS.len() + 1, STATUS.len()));
+        fs::remove_file(&status).unwrap();
+        assert!(stale(&mut packages).starts_with(&status));
+    }
+}
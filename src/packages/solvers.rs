@@ -1,132 +1,222 @@
-use rpkg::debversion::VersionRelation;
-use rpkg::debversion::{self, DebianVersionNum};
+use crate::debversion::VersionRelation;
+use crate::debversion::{self, DebianVersionNum};
 
 use crate::Packages;
-use crate::packages::Dependency;
+use crate::packages::{Dependency, RelationKind};
 use std::collections::VecDeque;
 use std::collections::HashSet;
 
+/// A relation that transitive_versioned_solution could not follow: no alternative has a candidate version (or a
+/// provider) that meets its constraint.
+pub struct UnsatisfiableEdge<'a> {
+    pub package_num : u32,
+    pub kind : RelationKind,
+    pub relation : &'a Dependency
+}
+
+/// What transitive_versioned_solution found: the packages reached, in the order they were, and the relations on the way
+/// that nothing available satisfies.
+pub struct VersionedClosure<'a> {
+    pub packages : Vec<u32>,
+    pub unsatisfiable : Vec<UnsatisfiableEdge<'a>>
+}
+
 impl Packages {
-    /// Computes a solution for the transitive dependencies of package_name; when there is a choice A | B | C, 
-    /// chooses the first option A. Returns a Vec<i32> of package numbers.
+    /// Computes a solution for the transitive dependencies (Pre-Depends and Depends) of package_name; when there is a choice A | B | C, 
+    /// chooses the first option A (or A's first provider, if A is virtual). Returns a Vec<u32> of package numbers.
     ///
     /// Note: does not consider which packages are installed.
-    pub fn transitive_dep_solution(&self, package_name: &str) -> Vec<i32> {
-        if !self.package_exists(package_name) {
-            return vec![];
-        }
-
-        let deps : &Vec<Dependency> = &*self.dependencies.get(self.get_package_num(package_name)).unwrap();
+    pub fn transitive_dep_solution(&self, package_name: &str) -> Vec<u32> {
+        let package_num = match self.package_num(package_name) {
+            None => return vec![],
+            Some(n) => n
+        };
+
+        let kinds = &RelationKind::REQUIRED;
+        let deps = self.get_relations_of_kinds(kinds, package_num);
         // Create a queue to act as a worklist (pop new work from front, add work to the back)
-        let mut worklist: VecDeque<i32> = VecDeque::new();
+        let mut worklist: VecDeque<u32> = VecDeque::new();
 
         // Create hashset such that duplicate dependencies are handled
-        let mut dependency_set: HashSet<i32> = HashSet::new(); 
+        let mut dependency_set: HashSet<u32> = HashSet::new(); 
 
         for dep in deps {
-            worklist.push_back(dep[0].package_num);
+            worklist.push_back(self.first_alternative(dep, self.candidate_arch(package_num)));
         }
 
         while let Some(front) = worklist.pop_front() {
             dependency_set.insert(front);
-            let new_deps = self.dependencies.get(&front).unwrap();
-            for dep in new_deps {
-                if !dependency_set.contains(&dep[0].package_num) {
-                    worklist.push_back(dep[0].package_num);
+            for dep in self.get_relations_of_kinds(kinds, front) {
+                let first = self.first_alternative(dep, self.candidate_arch(front));
+                if !dependency_set.contains(&first) {
+                    worklist.push_back(first);
                 }
             }
         }
 
         // Convert hashset back into vector to return
-        let dependecy_list: Vec<i32> = dependency_set.into_iter().collect();
+        dependency_set.into_iter().collect()
+    }
 
-        return dependecy_list;
+    /// Like transitive_dep_solution, but checks version constraints against the candidate versions: the first
+    /// alternative whose candidate (or a provider's) satisfies a relation is followed, and relations that no alternative
+    /// satisfies are recorded instead. None if there is no such package.
+    pub fn transitive_versioned_solution(&self, package_name: &str) -> Option<VersionedClosure<'_>> {
+        let package_num = self.package_num(package_name)?;
+        let kinds = &RelationKind::REQUIRED;
+        let mut closure = VersionedClosure { packages : vec![], unsatisfiable : vec![] };
+        let mut seen : HashSet<u32> = HashSet::from([package_num]);
+        let mut worklist : VecDeque<u32> = VecDeque::from([package_num]);
+        while let Some(front) = worklist.pop_front() {
+            for kind in kinds {
+                for dep in self.get_relations(*kind, front) {
+                    match dep.iter().find_map(|alt| self.available_satisfiers(alt, self.candidate_arch(front)).first().copied()) {
+                        None => closure.unsatisfiable.push(UnsatisfiableEdge { package_num : front, kind : *kind, relation : dep }),
+                        Some(p) => if seen.insert(p) {
+                            closure.packages.push(p);
+                            worklist.push_back(p);
+                        }
+                    }
+                }
+            }
+        }
+        Some(closure)
+    }
+
+    // A of A | B | C (declared by a package of architecture from_arch), or if A is a virtual package, its first
+    // available provider
+    fn first_alternative(&self, dep: &Dependency, from_arch: Option<&str>) -> u32 {
+        let first = &dep[0];
+        if self.get_candidate_debver(first.package_num).is_some() {
+            return first.package_num;
+        }
+        match self.available_satisfiers(first, from_arch).first() {
+            None => first.package_num,
+            Some(provider) => *provider
+        }
     }
 
     /// Computes a set of packages that need to be installed to satisfy package_name's deps given the current installed packages.
+    /// Follows the relations of the given kinds, e.g. RelationKind::REQUIRED, plus Recommends to behave like apt's default.
     /// When a dependency A | B | C is unsatisfied, there are two possible cases:
     ///   (1) there are no versions of A, B, or C installed; pick the alternative with the highest version number (yes, compare apples and oranges).
     ///   (2) at least one of A, B, or C is installed (say A, B), but with the wrong version; of the installed packages (A, B), pick the one with the highest version number.
-    pub fn compute_how_to_install(&self, package_name: &str) -> Vec<i32> {
-        if !self.package_exists(package_name) {
-            return vec![];
-        }
-        // implement more sophisticated worklist
+    pub fn compute_how_to_install(&self, package_name: &str, kinds: &[RelationKind]) -> Vec<u32> {
+        let package_num = match self.package_num(package_name) {
+            None => return vec![],
+            Some(n) => n
+        };
+        self.compute_how_to_satisfy(self.get_relations_of_kinds(kinds, package_num), self.candidate_arch(package_num), kinds)
+    }
 
-        let deps : &Vec<Dependency> = &*self.dependencies.get(self.get_package_num(package_name)).unwrap();
+    /// The worklist behind compute_how_to_install: the packages to install so that deps (declared by a package of
+    /// architecture from_arch) are satisfied, following the relations of the given kinds of everything chosen. Also used
+    /// for the build dependencies of source packages.
+    pub fn compute_how_to_satisfy<'a>(&self, deps: impl Iterator<Item = &'a Dependency>, from_arch: Option<&str>, kinds: &[RelationKind]) -> Vec<u32> {
         // Create a queue to act as a worklist (pop new work from front, add work to the back)
-        let mut worklist: VecDeque<i32> = VecDeque::new();
+        let mut worklist: VecDeque<u32> = VecDeque::new();
 
         // Create hashset such that duplicate dependencies are handled
-        let mut dependencies_to_add: HashSet<i32> = HashSet::new(); 
+        let mut dependencies_to_add: HashSet<u32> = HashSet::new(); 
 
         for dep in deps {
-            match self.handle_dependency(dep) {
-                Some(package) => {worklist.push_back(package)}
-                None => {}
+            if let Some(package) = self.handle_dependency(dep, from_arch) {
+                worklist.push_back(package);
             }
         }
 
         while let Some(front) = worklist.pop_front() {
-            dependencies_to_add.insert(front);
-            let new_deps = self.dependencies.get(&front).unwrap();
-            for dep in new_deps {
-                match self.handle_dependency(dep) {
-                    Some(package) => {
-                        worklist.push_back(package);
-                    }
-                    None => {}
+            // already expanded; without this check, dependency cycles among uninstalled packages never terminate
+            if !dependencies_to_add.insert(front) {
+                continue;
+            }
+            for dep in self.get_relations_of_kinds(kinds, front) {
+                if let Some(package) = self.handle_dependency(dep, self.candidate_arch(front)) {
+                    worklist.push_back(package);
                 }
             }
         }
 
         // Convert hashset back into vector to return
-        let dependecy_list: Vec<i32> = dependencies_to_add.into_iter().collect();
-
-        return dependecy_list;
-
+        dependencies_to_add.into_iter().collect()
     }
-    pub fn handle_dependency(&self, dd:&Dependency) -> Option<i32> {
-        match self.dep_is_satisfied_2(dd) {
-            Some(package_number) => {
-                return None
+
+    /// Returns None if dd, declared by a package of architecture from_arch (None for the native architecture), is
+    /// already satisfied by the installed packages; otherwise returns the package to install for it. Virtual packages are
+    /// replaced by their available providers. Also None if no alternative is available at all.
+    pub fn handle_dependency(&self, dd:&Dependency, from_arch: Option<&str>) -> Option<u32> {
+        let installed_incorrect_versions = self.dep_satisfied_by_wrong_version(dd, from_arch)?;
+        if !installed_incorrect_versions.is_empty() {
+            // CASE: We are picking between installed package that have incorrect versions
+            return self.pick_highest_available(installed_incorrect_versions.iter().map(|(p, _)| *p));
+        }
+        // CASE: All alternatives are not installed
+        let candidates = dd.iter().flat_map(|package| {
+            if self.get_candidate_debver(package.package_num).is_some() {
+                vec![package.package_num]
+            } else {
+                self.available_satisfiers(package, from_arch)
             }
-            None => {
-                let installed_incorrect_versions = self.dep_satisfied_by_wrong_version(dd);
-                // If the length of packages is 1, we return that 
-                if installed_incorrect_versions.len() == 1 {
-                    return Some(installed_incorrect_versions[0].0);
-                }
+        });
+        self.pick_highest_available(candidates)
+    }
 
-                if installed_incorrect_versions.len() > 1 {
-                    // CASE: We are picking between installed package that have incorrect versions
-                    let mut highest_version_number = installed_incorrect_versions[0].1;
-                    let mut selected_package_number = installed_incorrect_versions[0].0;
-                    for package in installed_incorrect_versions {
-                        let v = self.available_debvers.get(&package.0).unwrap();
-                        if debversion::cmp_debversion_with_op(&VersionRelation::StrictlyGreater, &v, highest_version_number) {
-                            highest_version_number = package.1;
-                            selected_package_number = package.0;
-                        }
-                    }
-                    return Some(selected_package_number);
-                } else {
-                    // CASE: All alternatives are not installed
-                    let mut highest_version_number = self.available_debvers.get(&dd[0].package_num).unwrap();
-                    let mut selected_package_number = dd[0].package_num;
-
-                    for package in dd {
-                        let v = self.available_debvers.get(&package.package_num).unwrap();
-
-                        if debversion::cmp_debversion_with_op(&VersionRelation::StrictlyGreater, &v, highest_version_number) {
-                            highest_version_number = v.clone();
-                            selected_package_number = package.package_num;
-                        }
-                    }
-                    return Some(selected_package_number);
+    // of the candidates that are available, the first one with the highest available version number
+    fn pick_highest_available(&self, candidates: impl Iterator<Item = u32>) -> Option<u32> {
+        let mut selected : Option<(u32, &DebianVersionNum)> = None;
+        for candidate in candidates {
+            if let Some(v) = self.get_candidate_debver(candidate) {
+                match selected {
+                    Some((_, highest)) if !debversion::cmp_debversion_with_op(&VersionRelation::StrictlyGreater, v, highest) => (),
+                    _ => selected = Some((candidate, v))
                 }
             }
         }
+        selected.map(|(p, _)| p)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::packages::RelationKind;
+    use crate::packages::testutil::{load, num};
+
+    const INDEX : &str = "Package: a\nVersion: 1\nPre-Depends: b (>= 2) | c\nDepends: d (>= 3)\n\n\
+                          Package: b\nVersion: 1\n\n\
+                          Package: c\nVersion: 1\nDepends: e (<< 2)\n\n\
+                          Package: d\nVersion: 3\nRecommends: missing\n\n\
+                          Package: e\nVersion: 1.5\nDepends: x (>= 1)\n\n\
+                          Package: x\nVersion: 1\nDepends: c, a\n";
+
+    fn names(packages: &crate::Packages, nums: &[u32]) -> Vec<String> {
+        nums.iter().map(|p| packages.get_package_name(*p).unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn versioned_constraints_choose_alternatives() {
+        let packages = load(INDEX, "");
+        let closure = packages.transitive_versioned_solution("a").unwrap();
+        // b is too old for a, so c stands in for it
+        assert_eq!(names(&packages, &closure.packages), ["c", "d", "e", "x"]);
+        assert!(closure.unsatisfiable.is_empty());
+        assert!(packages.transitive_versioned_solution("nonexistent").is_none());
+    }
+
+    #[test]
+    fn unsatisfiable_relations_are_reported() {
+        let packages = load(&INDEX.replace("Version: 1.5", "Version: 2"), "");
+        let closure = packages.transitive_versioned_solution("a").unwrap();
+        assert_eq!(names(&packages, &closure.packages), ["c", "d"]);
+        let edges : Vec<(u32, RelationKind, String)> = closure.unsatisfiable.iter()
+            .map(|e| (e.package_num, e.kind, packages.dep2str(e.relation).unwrap())).collect();
+        assert_eq!(edges, [(num(&packages, "c"), RelationKind::Depends, String::from("e (<< 2)"))]);
+    }
+
+    // x depends on a and c, which are on the way to it; each package is followed once
+    #[test]
+    fn cycles_end() {
+        let packages = load(INDEX, "");
+        let closure = packages.transitive_versioned_solution("x").unwrap();
+        assert_eq!(names(&packages, &closure.packages), ["c", "a", "e", "d"]);
+    }
+}
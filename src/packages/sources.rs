@@ -0,0 +1,514 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+
+use itertools::Itertools;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::debversion::DebianVersionNum;
+use crate::deb822::{Paragraph, ParagraphReader};
+
+use crate::Packages;
+use crate::packages::{Dependency, RelVersionedPackageNum, RelationKind};
+use crate::packages::conflicts::{ConflictResolution, PlanConflict};
+use crate::packages::multiarch::arch_matches_wildcard;
+use crate::packages::parsers::{decompress, parse_relation_text, RelationText, PKGNAME_AND_VERSION_REGEX};
+use crate::packages::release::checked_release;
+use crate::packages::errors::{LoadError, LoadReport};
+
+// Sources indices describe source packages; their build relations are like binary relations, except that each
+// alternative can be restricted to some architectures ("foo [amd64 !i386]") and build profiles ("foo <!nocheck>"); see
+// https://www.debian.org/doc/debian-policy/ch-relationships.html#relationships-between-source-and-binary-packages
+
+/// The build relationship fields a source package can declare. The -Arch and -Indep fields are only needed to build
+/// the architecture dependent and independent binaries, respectively; build-dep needs all of them.
+#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug,Serialize,Deserialize)]
+pub enum BuildRelationKind {
+    Depends,
+    DependsArch,
+    DependsIndep,
+    Conflicts,
+    ConflictsArch,
+    ConflictsIndep
+}
+
+impl BuildRelationKind {
+    pub const ALL : [BuildRelationKind; 6] = [
+        BuildRelationKind::Depends, BuildRelationKind::DependsArch, BuildRelationKind::DependsIndep,
+        BuildRelationKind::Conflicts, BuildRelationKind::ConflictsArch, BuildRelationKind::ConflictsIndep
+    ];
+    pub const DEPENDS : [BuildRelationKind; 3] =
+        [BuildRelationKind::Depends, BuildRelationKind::DependsArch, BuildRelationKind::DependsIndep];
+    pub const CONFLICTS : [BuildRelationKind; 3] =
+        [BuildRelationKind::Conflicts, BuildRelationKind::ConflictsArch, BuildRelationKind::ConflictsIndep];
+
+    pub fn field_name(&self) -> &'static str {
+        match self {
+            BuildRelationKind::Depends => "Build-Depends",
+            BuildRelationKind::DependsArch => "Build-Depends-Arch",
+            BuildRelationKind::DependsIndep => "Build-Depends-Indep",
+            BuildRelationKind::Conflicts => "Build-Conflicts",
+            BuildRelationKind::ConflictsArch => "Build-Conflicts-Arch",
+            BuildRelationKind::ConflictsIndep => "Build-Conflicts-Indep"
+        }
+    }
+}
+
+/// One alternative of a build relation, with the restrictions that say when it applies.
+#[derive(Clone,Serialize,Deserialize)]
+pub struct BuildRelation {
+    pub relation : RelVersionedPackageNum,
+    // "[amd64 arm64]" or "[!i386]" as (negated, architecture or wildcard); empty if unrestricted
+    pub arches : Vec<(bool, String)>,
+    // "<!nocheck> <stage1 cross>" as one list per <...>, each of (negated, profile); the alternative applies when every
+    // term of some list holds; empty if unrestricted
+    pub profiles : Vec<Vec<(bool, String)>>
+}
+pub type BuildDependency = Vec<BuildRelation>;
+
+impl BuildRelation {
+    /// Does this alternative apply when building on arch with the given build profiles active?
+    pub fn applies(&self, arch: &str, profiles: &[String]) -> bool {
+        // policy wants a list all positive or all negated; a mixed one needs a positive match and no negated one
+        let (negated, positive) : (Vec<_>, Vec<_>) = self.arches.iter().partition(|(negated, _)| *negated);
+        let arch_ok = (positive.is_empty() || positive.iter().any(|(_, a)| arch_matches_wildcard(arch, a)))
+            && !negated.iter().any(|(_, a)| arch_matches_wildcard(arch, a));
+        let profiles_ok = self.profiles.is_empty()
+            || self.profiles.iter().any(|list| list.iter().all(|(negated, p)| profiles.contains(p) != *negated));
+        arch_ok && profiles_ok
+    }
+}
+
+/// The source package a binary package is built from, from its Source field: "Source: glibc", or "Source: glibc
+/// (2.33-1)" when the binary is versioned differently (e.g. a binNMU).
+#[derive(Clone,PartialEq,Eq,Debug,Serialize,Deserialize)]
+pub struct SourceRef {
+    pub name : String,
+    pub version : Option<String>
+}
+
+impl SourceRef {
+    /// The source of the stanza para of binary package_name: without a Source field the package is its own source,
+    /// and without a version there the source version is the binary version.
+    pub fn of_paragraph(package_name: &str, para: &Paragraph) -> SourceRef {
+        SourceRef::new(package_name, para.get("Source"), para.get("Version"))
+    }
+
+    /// The source of binary package_name, given the Source and Version fields of its stanza.
+    pub fn new(package_name: &str, source: Option<&str>, binary_version: Option<&str>) -> SourceRef {
+        let (name, version) = match source.map(|s| (s, s.split_once('('))) {
+            None => (package_name, binary_version),
+            Some((source, None)) => (source.trim(), binary_version),
+            Some((_, Some((name, version)))) => (name.trim(), Some(version.trim().trim_end_matches(')').trim()))
+        };
+        SourceRef { name : name.to_string(), version : version.map(String::from) }
+    }
+}
+
+impl fmt::Display for SourceRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.version {
+            None => write!(f, "{}", self.name),
+            Some(v) => write!(f, "{} {}", self.name, v)
+        }
+    }
+}
+
+/// One version of a source package, from a Sources index.
+#[derive(Serialize,Deserialize)]
+pub struct SourceVersion {
+    pub version : DebianVersionNum,
+    // the binary packages built from it
+    pub binaries : Vec<String>,
+    pub build_relations : HashMap<BuildRelationKind, Vec<BuildDependency>>,
+    pub origins : Vec<usize>
+}
+
+// "foo (>= 1) [amd64 !i386] <!nocheck>" -> ("foo (>= 1)", ["amd64", "!i386"], ["!nocheck"]); the < of version
+// operators is inside the parentheses, so it does not open a profile list
+fn split_restrictions(alternative: &str) -> Result<(String, Vec<&str>, Vec<&str>), String> {
+    let (mut relation, mut arch_lists, mut profile_lists) = (String::new(), vec![], vec![]);
+    let mut rest = alternative;
+    let mut depth = 0;
+    while let Some(c) = rest.chars().next() {
+        let close = match c {
+            '[' if depth == 0 => ']',
+            '<' if depth == 0 => '>',
+            c => {
+                match c { '(' => depth += 1, ')' => depth -= 1, _ => () }
+                relation.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+        };
+        let end = rest.find(close).ok_or_else(|| format!("unterminated {} in {:?}", c, alternative))?;
+        let list = &rest[1..end];
+        if c == '[' { arch_lists.push(list) } else { profile_lists.push(list) }
+        rest = &rest[end + 1..];
+    }
+    if arch_lists.len() > 1 {
+        return Err(format!("more than one architecture list in {:?}", alternative));
+    }
+    Ok((relation.trim().to_string(), arch_lists, profile_lists))
+}
+
+// a BuildRelation before its package name is interned
+struct BuildRelationText {
+    relation : RelationText,
+    arches : Vec<(bool, String)>,
+    profiles : Vec<Vec<(bool, String)>>
+}
+
+/// Parses a build relation field such as "debhelper-compat (= 13), libfoo-dev [!hurd-any] <!nocheck> | libbar-dev".
+fn parse_build_dependencies(pkgver_regexp: &Regex, value: &str) -> Result<Vec<Vec<BuildRelationText>>, String> {
+    let mut dependencies = vec![];
+    for element in value.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let mut alternatives = vec![];
+        for alternative in element.split('|').map(|s| s.trim()) {
+            let (relation, arch_lists, profile_lists) = split_restrictions(alternative)?;
+            alternatives.push(BuildRelationText {
+                relation : parse_relation_text(pkgver_regexp, &relation)?,
+                arches : arch_lists.first().map(|l| parse_restriction_list(l)).unwrap_or_default(),
+                profiles : profile_lists.iter().map(|l| parse_restriction_list(l)).collect()
+            });
+        }
+        dependencies.push(alternatives);
+    }
+    Ok(dependencies)
+}
+
+// "amd64 !i386" -> [(false, "amd64"), (true, "i386")]
+fn parse_restriction_list(list: &str) -> Vec<(bool, String)> {
+    list.split_whitespace().map(|term| match term.strip_prefix('!') {
+        Some(t) => (true, t.to_string()),
+        None => (false, term.to_string())
+    }).collect()
+}
+
+/// A version of a binary package, installed or available, and the source package it is built from.
+pub struct BinarySource {
+    pub installed : bool,
+    // "libc6:i386" for a foreign architecture
+    pub qualified_name : String,
+    pub version : String,
+    pub source : SourceRef
+}
+
+/// A source package with installed binaries from different versions of it: each source version (newest first) with
+/// the qualified names of its binaries, sorted.
+pub struct VersionSkew<'a> {
+    pub source : &'a str,
+    pub versions : Vec<(&'a str, Vec<String>)>
+}
+
+/// A Build-Conflicts relation that holds once the build dependencies are installed.
+pub struct BuildConflict {
+    pub relation : String,
+    pub target : u32,
+    pub target_planned : bool,
+    // the version of target once the build dependencies are installed
+    pub version : DebianVersionNum,
+    pub resolution : ConflictResolution
+}
+
+/// What building a source package takes, as planned by plan_build_dep.
+pub struct BuildPlan {
+    /// the packages to install, by name
+    pub packages : Vec<u32>,
+    /// build dependencies that are neither satisfied nor satisfiable by an available package
+    pub unsatisfiable : Vec<Dependency>,
+    pub build_conflicts : Vec<BuildConflict>,
+    pub conflicts : Vec<PlanConflict>
+}
+
+impl Packages {
+    /// Loads source packages, their versions, binaries and build relations from a Sources index (plain, gzip or xz),
+    /// checked against release_filename like Packages indices are. Versions already loaded are kept.
+    pub fn parse_sources(&mut self, filename: &str, release_filename: Option<&str>) -> Result<LoadReport, LoadError> {
+        let pkgver_regexp = Regex::new(PKGNAME_AND_VERSION_REGEX).unwrap();
+
+        let mut report = LoadReport::new(filename);
+        let raw = fs::read(filename).map_err(|e| LoadError::io(filename, e))?;
+        let release = checked_release(filename, &raw, release_filename, &mut report.warnings)?;
+        let paragraphs = ParagraphReader::new(decompress(filename, &raw[..]).map_err(|e| LoadError::io(filename, e))?);
+        self.record_loaded_file(filename, &mut report.warnings);
+        if let Some(r) = release_filename { self.record_loaded_file(r, &mut report.warnings) }
+        let origin = self.add_origin(filename, release);
+        report.load_paragraphs(self.load_mode, paragraphs, |para, _| self.load_source_stanza(&pkgver_regexp, origin, para))?;
+        Ok(report)
+    }
+
+    // one stanza of a Sources file; nothing is recorded unless the whole stanza parses
+    fn load_source_stanza(&mut self, pkgver_regexp: &Regex, origin: usize, para: Paragraph) -> Result<(), String> {
+        let name = para.get("Package").ok_or("no Package field")?.to_string();
+        let version = para.get("Version").ok_or("no Version field")?.parse::<DebianVersionNum>().map_err(|e| e.to_string())?;
+        // names are only interned once the stanza is known to parse, so a rejected one leaves none behind
+        let mut build_relations = vec![];
+        for kind in BuildRelationKind::ALL.iter() {
+            if let Some(value) = para.get_folded(kind.field_name()) {
+                let deps = parse_build_dependencies(pkgver_regexp, &value).map_err(|e| format!("{}: {}", kind.field_name(), e))?;
+                build_relations.push((*kind, deps));
+            }
+        }
+        let build_relations : HashMap<BuildRelationKind, Vec<BuildDependency>> = build_relations.into_iter()
+            .map(|(kind, deps)| (kind, self.intern_build_dependencies(deps))).collect();
+        let binaries : Vec<String> = para.get_folded("Binary").map(|b| b.split(',').map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty()).collect()).unwrap_or_default();
+        for binary in &binaries {
+            let package_num = self.get_package_num_inserting(binary);
+            self.add_source_binary(&name, package_num);
+        }
+        let versions = self.sources.entry(name).or_default();
+        match versions.iter_mut().find(|v| v.version == version) {
+            Some(existing) => if !existing.origins.contains(&origin) { existing.origins.push(origin) },
+            None => versions.push(SourceVersion { version, binaries, build_relations, origins : vec![origin] })
+        }
+        Ok(())
+    }
+
+    fn intern_build_dependencies(&mut self, deps: Vec<Vec<BuildRelationText>>) -> Vec<BuildDependency> {
+        deps.into_iter().map(|dep| dep.into_iter().map(|BuildRelationText { relation, arches, profiles }| {
+            BuildRelation { relation : self.intern_relation(relation), arches, profiles }
+        }).collect()).collect()
+    }
+
+    /// Records that binary package_num is built from source_name, for binaries-of.
+    pub(super) fn add_source_binary(&mut self, source_name: &str, package_num: u32) {
+        let binaries = self.source_binaries.entry(source_name.to_string()).or_default();
+        if !binaries.contains(&package_num) {
+            binaries.push(package_num);
+        }
+    }
+
+    /// The sources of the installed instances and then the available versions of package_num.
+    pub fn sources_of(&self, package_num: u32) -> Vec<BinarySource> {
+        let installed = self.get_installed_instances(package_num).iter().map(|i| BinarySource {
+            installed : true, qualified_name : self.qualify(self.shown_name(package_num), i.arch.as_deref()),
+            version : i.version.clone().unwrap_or_default(), source : i.source.clone()
+        });
+        let available = self.get_available_versions(package_num).iter().map(|v| BinarySource {
+            installed : false, qualified_name : self.qualify(self.shown_name(package_num), v.architecture.as_deref()),
+            version : v.version.to_string(), source : SourceRef::of_paragraph(self.shown_name(package_num), &v.paragraph.paragraph())
+        });
+        installed.chain(available).collect()
+    }
+
+    /// The binary packages built from source_name, per the Source fields of binary packages and the Binary fields of
+    /// Sources indices; None if no binary package is known to come from it.
+    pub fn get_source_binaries(&self, source_name: &str) -> Option<&[u32]> {
+        self.source_binaries.get(source_name).map(|b| &b[..])
+    }
+
+    /// The source packages whose installed binaries come from different source versions, as after a partial upgrade,
+    /// by name. Binaries of one source version can still differ in version (binNMUs), so source versions are compared.
+    pub fn version_skew(&self) -> Vec<VersionSkew<'_>> {
+        let mut by_source : BTreeMap<&str, BTreeMap<&str, Vec<String>>> = BTreeMap::new();
+        for (package_num, instances) in self.installed_instances.iter() {
+            for i in instances.iter().filter(|i| i.status.is_installed()) {
+                let version = i.source.version.as_deref().unwrap_or("");
+                by_source.entry(&i.source.name).or_default().entry(version).or_default()
+                    .push(self.qualify(self.shown_name(package_num), i.arch.as_deref()));
+            }
+        }
+        by_source.into_iter().filter(|(_, versions)| versions.len() > 1).map(|(source, versions)| {
+            let mut versions : Vec<(&str, Vec<String>)> = versions.into_iter()
+                .map(|(version, binaries)| (version, binaries.into_iter().sorted().collect())).collect();
+            versions.sort_by_key(|(v, _)| std::cmp::Reverse(v.parse::<DebianVersionNum>().ok()));
+            VersionSkew { source, versions }
+        }).collect()
+    }
+
+    /// Number of source packages loaded from Sources indices.
+    pub fn source_count(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// The highest version of source package source_name.
+    pub fn get_source_candidate(&self, source_name: &str) -> Option<&SourceVersion> {
+        self.sources.get(source_name)?.iter().max_by(|a, b| a.version.cmp(&b.version))
+    }
+
+    /// The build relations of source of the given kinds as ordinary relations: the alternatives that do not apply to
+    /// arch and profiles are dropped, and so are relations left without alternatives.
+    pub fn reduce_build_relations(&self, source: &SourceVersion, kinds: &[BuildRelationKind], arch: &str, profiles: &[String]) -> Vec<Dependency> {
+        kinds.iter().filter_map(|k| source.build_relations.get(k)).flatten()
+            .map(|dep| dep.iter().filter(|alt| alt.applies(arch, profiles)).map(|alt| alt.relation.clone()).collect::<Dependency>())
+            .filter(|dep| !dep.is_empty())
+            .collect()
+    }
+
+    /// Plans building source on the native architecture arch with the given build profiles active, like apt-get
+    /// build-dep: the packages to install, the build dependencies nothing can satisfy, and the Build-Conflicts and
+    /// Conflicts that are in the way.
+    pub fn plan_build_dep(&self, source: &SourceVersion, arch: &str, profiles: &[String]) -> BuildPlan {
+        let depends = self.reduce_build_relations(source, &BuildRelationKind::DEPENDS, arch, profiles);
+        let mut packages = self.compute_how_to_satisfy(depends.iter(), Some(arch), &RelationKind::REQUIRED);
+        packages.sort_by_key(|p| self.interner.get(*p));
+        let build_conflicts = self.find_build_conflicts(source, arch, profiles, &packages);
+        let conflicts = self.find_plan_conflicts(&packages);
+        let unsatisfiable = depends.into_iter()
+            .filter(|dep| self.dep_is_satisfied_2(dep, Some(arch)).is_none() && self.handle_dependency(dep, Some(arch)).is_none()).collect();
+        BuildPlan { packages, unsatisfiable, build_conflicts, conflicts }
+    }
+
+    // Build-Conflicts of source that hold once plan is installed, with what to do about them
+    fn find_build_conflicts(&self, source: &SourceVersion, arch: &str, profiles: &[String], plan: &[u32]) -> Vec<BuildConflict> {
+        let plan_set: HashSet<u32> = plan.iter().copied().collect();
+        let conflicts = self.reduce_build_relations(source, &BuildRelationKind::CONFLICTS, arch, profiles);
+        let mut found = vec![];
+        for rel in conflicts.iter().flatten() {
+            let target = rel.package_num;
+            let planned = plan_set.contains(&target);
+            let version = match self.version_after_plan(target, &plan_set) {
+                Some(v) if self.rel_version_matches(rel, v) == Some(true) => v.clone(),
+                _ => continue
+            };
+            let resolution = if planned {
+                ConflictResolution::Unresolvable
+            } else {
+                match self.get_candidate_debver(target) {
+                    Some(av) if rel.rel_version.is_some() && self.rel_version_matches(rel, av) == Some(false) => ConflictResolution::Upgrade(target),
+                    _ => ConflictResolution::Remove(target)
+                }
+            };
+            found.push(BuildConflict { relation : self.relation_text(rel), target, target_planned : planned, version, resolution });
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packages::ArchQualifier;
+    use crate::packages::testutil::{load, num, TempFile};
+
+    // the alternatives of a build relation field, with their restrictions, before interning
+    fn parse(value: &str) -> Result<Vec<Vec<BuildRelationText>>, String> {
+        parse_build_dependencies(&Regex::new(PKGNAME_AND_VERSION_REGEX).unwrap(), value)
+    }
+
+    fn applies(alternative: &str, arch: &str, profiles: &[&str]) -> bool {
+        let text = parse(alternative).unwrap().remove(0).remove(0);
+        let relation = BuildRelation { relation : RelVersionedPackageNum { package_num : 0, rel_version : None, arch : None },
+                                       arches : text.arches, profiles : text.profiles };
+        relation.applies(arch, &profiles.iter().map(|p| p.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn restrictions_are_split_off() {
+        assert_eq!(split_restrictions("foo (>= 1) [amd64 !i386] <!nocheck> <stage1 cross>").unwrap(),
+                   (String::from("foo (>= 1)"), vec!["amd64 !i386"], vec!["!nocheck", "stage1 cross"]));
+        // inside the version, < and [ belong to it
+        assert_eq!(split_restrictions("foo (<< 2<3>[x]) <!nocheck>").unwrap(), (String::from("foo (<< 2<3>[x])"), vec![], vec!["!nocheck"]));
+        assert!(split_restrictions("foo [amd64] [i386]").is_err());
+        assert!(split_restrictions("foo <!nocheck").is_err());
+        let mut packages = Packages::new();
+        let deps = packages.intern_build_dependencies(parse("debhelper-compat (= 13), python3:native (>= 3.9) [linux-any] | python3-dev <!cross>").unwrap());
+        assert_eq!(deps.len(), 2);
+        let python = &deps[1][0];
+        assert_eq!(packages.get_package_name(python.relation.package_num()), Some("python3"));
+        assert_eq!((python.relation.arch(), python.relation.rel_version().map(|(_, v)| v)), (Some(&ArchQualifier::Native), Some("3.9")));
+        assert_eq!(python.arches, [(false, String::from("linux-any"))]);
+        assert_eq!(deps[1][1].profiles, [vec![(true, String::from("cross"))]]);
+        // the version must still parse
+        assert!(parse("foo (<< 2<3>)").is_err());
+    }
+
+    #[test]
+    fn architecture_lists() {
+        assert!(applies("foo [amd64 arm64]", "arm64", &[]) && !applies("foo [amd64 arm64]", "i386", &[]));
+        assert!(!applies("foo [!i386 !armel]", "i386", &[]) && applies("foo [!i386 !armel]", "amd64", &[]));
+        assert!(applies("foo [linux-any]", "amd64", &[]) && !applies("foo [linux-any]", "kfreebsd-amd64", &[]));
+        assert!(applies("foo [any-amd64]", "kfreebsd-amd64", &[]));
+        // a mixed list: some plain architecture, and none of the negated ones
+        assert!(applies("foo [amd64 !i386]", "amd64", &[]) && !applies("foo [amd64 !i386]", "i386", &[]));
+        assert!(!applies("foo [amd64 !i386]", "arm64", &[]));
+        assert!(!applies("foo [any !i386]", "i386", &[]) && applies("foo [any !i386]", "arm64", &[]));
+    }
+
+    #[test]
+    fn profile_lists() {
+        assert!(applies("foo <!nocheck>", "amd64", &[]) && !applies("foo <!nocheck>", "amd64", &["nocheck"]));
+        // each <...> is an AND, and the lists are ORed
+        let restricted = "foo <stage1 cross> <!nocheck>";
+        assert!(applies(restricted, "amd64", &[]));
+        assert!(!applies(restricted, "amd64", &["nocheck"]) && !applies(restricted, "amd64", &["nocheck", "stage1"]));
+        assert!(applies(restricted, "amd64", &["nocheck", "stage1", "cross"]));
+        // both kinds of restriction must hold
+        assert!(!applies("foo [amd64] <!nocheck>", "i386", &[]) && !applies("foo [amd64] <!nocheck>", "amd64", &["nocheck"]));
+        assert!(applies("foo", "i386", &["nocheck"]));
+    }
+
+    // the first build dependency parses, the second does not; neither name is kept
+    #[test]
+    fn rejected_source_stanza_leaves_no_names() {
+        let sources = TempFile::new("Package: bad\nVersion: 1\nBinary: bad-bin\nBuild-Depends: debhelper, libfoo-dev [amd64\n\n\
+                                     Package: good\nVersion: 1\nBinary: good-bin\n");
+        let mut packages = Packages::new();
+        let report = packages.parse_sources(sources.path(), None).unwrap();
+        assert_eq!((report.loaded, report.skipped.len()), (1, 1));
+        assert_eq!(packages.get_package_names(), ["good-bin"]);
+        assert!(packages.get_source_candidate("bad").is_none());
+    }
+
+    #[test]
+    fn source_refs() {
+        let binnmu = SourceRef::new("libc6", Some("glibc (2.36-9)"), Some("2.36-9+b1"));
+        assert_eq!((binnmu.name.as_str(), binnmu.version.as_deref()), ("glibc", Some("2.36-9")));
+        assert_eq!(binnmu.to_string(), "glibc 2.36-9");
+        // a bare Source field takes the binary version, and no Source field the binary name too
+        assert_eq!(SourceRef::new("libc6", Some(" glibc "), Some("2.36-9")), SourceRef { name : String::from("glibc"), version : Some(String::from("2.36-9")) });
+        assert_eq!(SourceRef::new("hello", None, Some("2.10-3")).to_string(), "hello 2.10-3");
+        assert_eq!(SourceRef::new("hello", None, None).to_string(), "hello");
+    }
+
+    const STATUS : &str = "Package: dpkg\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1.21\n\n\
+                           Package: foo-bin\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1.0-1+b1\nSource: foo (1.0-1)\n\n\
+                           Package: foo-data\nStatus: install ok installed\nArchitecture: all\nVersion: 1.0-1\nSource: foo\n\n\
+                           Package: libbar\nStatus: install ok installed\nArchitecture: amd64\nMulti-Arch: same\nVersion: 2.0\nSource: bar\n\n\
+                           Package: libbar\nStatus: install ok installed\nArchitecture: i386\nMulti-Arch: same\nVersion: 1.9\nSource: bar\n\n\
+                           Package: old\nStatus: deinstall ok config-files\nVersion: 0.1\nSource: foo (0.1)\n";
+
+    // a source version with its installed binaries
+    type Binaries = (String, Vec<String>);
+
+    // each source with skew, with its binaries per source version, newest first
+    fn skew(packages: &Packages) -> Vec<(String, Vec<Binaries>)> {
+        packages.version_skew().into_iter().map(|s| (s.source.to_string(),
+            s.versions.into_iter().map(|(v, binaries)| (v.to_string(), binaries)).collect())).collect()
+    }
+
+    #[test]
+    fn version_skew() {
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        // binNMUs and Architecture: all binaries of one source version agree; packages that are not installed don't count
+        let packages = load("", STATUS);
+        assert_eq!(skew(&packages), [(String::from("bar"), vec![(String::from("2.0"), strings(&["libbar"])),
+                                                                 (String::from("1.9"), strings(&["libbar:i386"]))])]);
+        let lagging = STATUS.to_string() + "\nPackage: foo-utils\nStatus: install ok installed\nArchitecture: amd64\nVersion: 0.9-2\nSource: foo\n";
+        let packages = load("", &lagging);
+        assert_eq!(skew(&packages)[1], (String::from("foo"), vec![(String::from("1.0-1"), strings(&["foo-bin", "foo-data"])),
+                                                                  (String::from("0.9-2"), strings(&["foo-utils"]))]));
+    }
+
+    #[test]
+    fn binaries_of() {
+        let mut packages = load("Package: foo-bin\nVersion: 1.1-1+b2\nArchitecture: amd64\nSource: foo (1.1-1)\n\n\
+                                                             Package: foo-doc\nVersion: 1.1-1\nArchitecture: all\nSource: foo\n", STATUS);
+        let sources = TempFile::new("Package: foo\nVersion: 1.1-1\nBinary: foo-bin, foo-doc,\n foo-extra\n");
+        packages.parse_sources(sources.path(), None).unwrap();
+        let names = |binaries: &[u32]| binaries.iter().map(|b| packages.get_package_name(*b).unwrap()).sorted().collect::<Vec<_>>();
+        // from the status file (even what is not installed), the Packages index and the Binary field
+        assert_eq!(names(packages.get_source_binaries("foo").unwrap()), ["foo-bin", "foo-data", "foo-doc", "foo-extra", "old"]);
+        assert_eq!(names(packages.get_source_binaries("bar").unwrap()), ["libbar"]);
+        assert!(packages.get_source_binaries("foo-bin").is_none());
+        assert_eq!(packages.get_source_candidate("foo").unwrap().binaries, ["foo-bin", "foo-doc", "foo-extra"]);
+        let sources_of : Vec<(bool, String, String, String)> = packages.sources_of(num(&packages, "foo-bin")).into_iter()
+            .map(|b| (b.installed, b.qualified_name, b.version, b.source.to_string())).collect();
+        assert_eq!(sources_of, [(true, String::from("foo-bin"), String::from("1.0-1+b1"), String::from("foo 1.0-1")),
+                                (false, String::from("foo-bin"), String::from("1.1-1+b2"), String::from("foo 1.1-1"))]);
+    }
+
+}
@@ -0,0 +1,238 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Packages;
+use crate::packages::multiarch::InstalledInstance;
+
+// dpkg records "Status: <want> <eflag> <status>" for every package it knows about; see dpkg-query(1).
+
+#[derive(Clone,Copy,PartialEq,Eq,Debug,Serialize,Deserialize)]
+pub enum Want {
+    Unknown,
+    Install,
+    Hold,
+    Deinstall,
+    Purge
+}
+
+#[derive(Clone,Copy,PartialEq,Eq,Debug,Serialize,Deserialize)]
+pub enum EFlag {
+    Ok,
+    ReinstReq,
+    // obsolete, but still found in old status files
+    Hold,
+    HoldReinstReq
+}
+
+#[derive(Clone,Copy,PartialEq,Eq,Debug,Serialize,Deserialize)]
+pub enum StatusState {
+    NotInstalled,
+    ConfigFiles,
+    HalfInstalled,
+    Unpacked,
+    HalfConfigured,
+    TriggersAwaiting,
+    TriggersPending,
+    Installed
+}
+
+#[derive(Clone,Copy,PartialEq,Eq,Debug,Serialize,Deserialize)]
+pub struct PackageStatus {
+    pub want : Want,
+    pub eflag : EFlag,
+    pub status : StatusState
+}
+
+impl PackageStatus {
+    /// What we assume for stanzas without a Status field, e.g. hand-written installed lists.
+    pub const INSTALLED : PackageStatus = PackageStatus { want : Want::Install, eflag : EFlag::Ok, status : StatusState::Installed };
+
+    /// Only packages that dpkg has fully configured satisfy dependencies; packages with pending or awaited
+    /// triggers are configured too.
+    pub fn is_installed(&self) -> bool {
+        self.eflag == EFlag::Ok && matches!(self.status,
+            StatusState::Installed | StatusState::TriggersPending | StatusState::TriggersAwaiting)
+    }
+
+    /// Describes why this status needs attention, or None if it is a normal state (installed or not installed).
+    pub fn problem(&self) -> Option<&'static str> {
+        if matches!(self.eflag, EFlag::ReinstReq | EFlag::HoldReinstReq) {
+            return Some("broken and need to be reinstalled (reinstreq)");
+        }
+        match self.status {
+            StatusState::NotInstalled | StatusState::Installed => None,
+            StatusState::ConfigFiles => Some("removed but not purged (only configuration files remain)"),
+            StatusState::HalfInstalled => Some("half-installed"),
+            StatusState::Unpacked => Some("unpacked but not configured"),
+            StatusState::HalfConfigured => Some("half-configured"),
+            StatusState::TriggersAwaiting => Some("awaiting trigger processing by other packages"),
+            StatusState::TriggersPending => Some("waiting for their own triggers to be processed")
+        }
+    }
+}
+
+impl FromStr for PackageStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        if words.len() != 3 {
+            return Err(format!("expected \"<want> <eflag> <status>\", got {:?}", s));
+        }
+        let want = match words[0] {
+            "unknown" => Want::Unknown,
+            "install" => Want::Install,
+            "hold" => Want::Hold,
+            "deinstall" => Want::Deinstall,
+            "purge" => Want::Purge,
+            w => return Err(format!("bad want {:?}", w))
+        };
+        let eflag = match words[1] {
+            "ok" => EFlag::Ok,
+            "reinstreq" => EFlag::ReinstReq,
+            "hold" => EFlag::Hold,
+            "hold-reinstreq" => EFlag::HoldReinstReq,
+            e => return Err(format!("bad error flag {:?}", e))
+        };
+        let status = match words[2] {
+            "not-installed" => StatusState::NotInstalled,
+            "config-files" => StatusState::ConfigFiles,
+            "half-installed" => StatusState::HalfInstalled,
+            "unpacked" => StatusState::Unpacked,
+            "half-configured" => StatusState::HalfConfigured,
+            "triggers-awaited" | "triggers-awaiting" => StatusState::TriggersAwaiting,
+            "triggers-pending" => StatusState::TriggersPending,
+            "installed" => StatusState::Installed,
+            st => return Err(format!("bad status {:?}", st))
+        };
+        Ok(PackageStatus { want, eflag, status })
+    }
+}
+
+impl fmt::Display for PackageStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let want = match self.want {
+            Want::Unknown => "unknown",
+            Want::Install => "install",
+            Want::Hold => "hold",
+            Want::Deinstall => "deinstall",
+            Want::Purge => "purge"
+        };
+        let eflag = match self.eflag {
+            EFlag::Ok => "ok",
+            EFlag::ReinstReq => "reinstreq",
+            EFlag::Hold => "hold",
+            EFlag::HoldReinstReq => "hold-reinstreq"
+        };
+        let status = match self.status {
+            StatusState::NotInstalled => "not-installed",
+            StatusState::ConfigFiles => "config-files",
+            StatusState::HalfInstalled => "half-installed",
+            StatusState::Unpacked => "unpacked",
+            StatusState::HalfConfigured => "half-configured",
+            StatusState::TriggersAwaiting => "triggers-awaited",
+            StatusState::TriggersPending => "triggers-pending",
+            StatusState::Installed => "installed"
+        };
+        write!(f, "{} {} {}", want, eflag, status)
+    }
+}
+
+impl Packages {
+    /// The dpkg status of package_name: that of an installed architecture if there is one.
+    pub fn get_installed_status(&self, package_name: &str) -> Option<&PackageStatus> {
+        let package_num = self.interner.num(package_name)?;
+        let instances = self.get_installed_instances(package_num);
+        instances.iter().find(|i| i.status.is_installed()).or_else(|| instances.first()).map(|i| &i.status)
+    }
+
+    /// The instances in the status file that are in an abnormal state, grouped by problem, like dpkg --audit. Instances
+    /// are sorted by package name and architecture, and problems come in the order of their first instance.
+    pub fn audit(&self) -> Vec<(&'static str, Vec<(u32, &InstalledInstance)>)> {
+        let mut problems : Vec<(&'static str, Vec<(u32, &InstalledInstance)>)> = vec![];
+        let mut entries : Vec<(u32, &InstalledInstance)> = self.installed_instances.iter()
+            .flat_map(|(p, instances)| instances.iter().map(move |i| (p, i))).collect();
+        entries.sort_by_key(|(p, i)| (self.interner.get(*p), i.arch.as_deref()));
+        for (package_num, instance) in entries {
+            let problem = match instance.status.problem() {
+                None => continue,
+                Some(p) => p
+            };
+            match problems.iter_mut().find(|(p, _)| *p == problem) {
+                Some((_, instances)) => instances.push((package_num, instance)),
+                None => problems.push((problem, vec![(package_num, instance)]))
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packages::testutil::{load, num};
+
+    #[test]
+    fn status_triples() {
+        let status : PackageStatus = "hold reinstreq half-configured".parse().unwrap();
+        assert_eq!(status, PackageStatus { want : Want::Hold, eflag : EFlag::ReinstReq, status : StatusState::HalfConfigured });
+        for s in ["install ok installed", "deinstall ok config-files", "purge ok not-installed", "unknown hold-reinstreq half-installed",
+                  "install hold unpacked", "install ok triggers-awaited", "install ok triggers-pending"] {
+            assert_eq!(s.parse::<PackageStatus>().unwrap().to_string(), s);
+        }
+        // the name dpkg used before 1.16
+        assert_eq!("install ok triggers-awaiting".parse::<PackageStatus>().unwrap().status, StatusState::TriggersAwaiting);
+        assert_eq!("install ok".parse::<PackageStatus>().err().unwrap(), "expected \"<want> <eflag> <status>\", got \"install ok\"");
+        assert_eq!("keep ok installed".parse::<PackageStatus>().err().unwrap(), "bad want \"keep\"");
+        assert_eq!("install bad installed".parse::<PackageStatus>().err().unwrap(), "bad error flag \"bad\"");
+        assert_eq!("install ok configured".parse::<PackageStatus>().err().unwrap(), "bad status \"configured\"");
+    }
+
+    #[test]
+    fn only_configured_packages_are_installed() {
+        let installed = |s: &str| s.parse::<PackageStatus>().unwrap().is_installed();
+        assert!(installed("install ok installed") && installed("hold ok installed"));
+        assert!(installed("install ok triggers-pending") && installed("install ok triggers-awaited"));
+        assert!(!installed("install ok half-configured") && !installed("install ok unpacked") && !installed("install ok half-installed"));
+        assert!(!installed("deinstall ok config-files") && !installed("install reinstreq installed"));
+    }
+
+    const STATUS : &str = "Package: app\nStatus: install ok installed\nVersion: 1\nDepends: half, unpacked, triggered\n\n\
+                           Package: half\nStatus: install ok half-configured\nVersion: 1\n\n\
+                           Package: unpacked\nStatus: install ok unpacked\nVersion: 1\n\n\
+                           Package: triggered\nStatus: install ok triggers-pending\nVersion: 1\n\n\
+                           Package: conf\nStatus: deinstall ok config-files\nVersion: 1\n\n\
+                           Package: broken\nStatus: install reinstreq half-installed\nVersion: 1\n\n\
+                           Package: awaiting\nStatus: install ok triggers-awaited\nVersion: 1\n\n\
+                           Package: another\nStatus: install ok unpacked\nVersion: 1\nArchitecture: i386\n";
+
+    // half-configured and unpacked packages are known with their status, but do not satisfy dependencies
+    #[test]
+    fn partly_installed_packages_do_not_satisfy_dependencies() {
+        let packages = load("", STATUS);
+        assert!(packages.installed_debver(num(&packages, "half")).is_none());
+        assert!(packages.installed_debver(num(&packages, "unpacked")).is_none());
+        assert!(packages.installed_debver(num(&packages, "triggered")).is_some());
+        assert_eq!(packages.get_installed_status("half").unwrap().status, StatusState::HalfConfigured);
+        let unmet : Vec<String> = packages.check_installed()[0].unmet.iter().map(|u| packages.dep2str(u.relation).unwrap()).collect();
+        assert_eq!(unmet, ["half", "unpacked"]);
+    }
+
+    // problems in the order of their first instance, instances by name
+    #[test]
+    fn audit_groups_by_problem() {
+        let packages = load("", STATUS);
+        let audit : Vec<(&str, Vec<&str>)> = packages.audit().into_iter()
+            .map(|(problem, instances)| (problem, instances.iter().map(|(p, _)| packages.get_package_name(*p).unwrap()).collect())).collect();
+        assert_eq!(audit, [
+            ("unpacked but not configured", vec!["another", "unpacked"]),
+            ("awaiting trigger processing by other packages", vec!["awaiting"]),
+            ("broken and need to be reinstalled (reinstreq)", vec!["broken"]),
+            ("removed but not purged (only configuration files remain)", vec!["conf"]),
+            ("half-configured", vec!["half"]),
+            ("waiting for their own triggers to be processed", vec!["triggered"])
+        ]);
+    }
+}
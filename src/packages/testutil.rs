@@ -0,0 +1,106 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::Packages;
+
+// Fixtures for the unit tests: Packages indices and status files are written to temporary files and loaded the way the
+// real ones are, so tests go through the loaders instead of filling the tables by hand.
+
+static NEXT_FILE : AtomicUsize = AtomicUsize::new(0);
+
+/// A file in the temporary directory, removed when dropped; tests run in parallel, so each has its own name.
+pub(crate) struct TempFile {
+    path : PathBuf
+}
+
+impl TempFile {
+    pub(crate) fn new(contents: impl AsRef<[u8]>) -> TempFile {
+        TempFile::with_extension(contents, "")
+    }
+
+    /// A file whose name ends in extension (such as ".gz"), for code that looks at it.
+    pub(crate) fn with_extension(contents: impl AsRef<[u8]>, extension: &str) -> TempFile {
+        let n = NEXT_FILE.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("rpkg-test-{}-{}{}", std::process::id(), n, extension));
+        fs::write(&path, contents).unwrap();
+        TempFile { path }
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        self.path.to_str().unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A directory in the temporary directory, such as a mirror tree, removed with its contents when dropped.
+pub(crate) struct TempDir {
+    path : PathBuf
+}
+
+impl TempDir {
+    pub(crate) fn new() -> TempDir {
+        let n = NEXT_FILE.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("rpkg-test-{}-{}.d", std::process::id(), n));
+        fs::create_dir(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        self.path.to_str().unwrap()
+    }
+
+    /// Writes the file at relative (creating the directories on the way), returning its path.
+    pub(crate) fn file(&self, relative: &str, contents: impl AsRef<[u8]>) -> String {
+        let path = self.path.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Packages with the Packages index available and the status file installed loaded, either of which may be empty;
+/// every stanza of both must load.
+pub(crate) fn load(available: &str, installed: &str) -> Packages {
+    let mut packages = Packages::new();
+    if !installed.is_empty() {
+        let report = packages.parse_installed(TempFile::new(installed).path()).unwrap();
+        assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+    }
+    if !available.is_empty() {
+        let report = packages.parse_packages(TempFile::new(available).path(), None).unwrap();
+        assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+    }
+    packages
+}
+
+/// The number of a package the fixture mentions.
+pub(crate) fn num(packages: &Packages, name: &str) -> u32 {
+    packages.package_num(name).unwrap_or_else(|| panic!("no package {}", name))
+}
+
+/// Compresses data as gzip does, for Packages.gz files.
+pub(crate) fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Compresses data as xz does, for Packages.xz files.
+pub(crate) fn xz(data: &[u8]) -> Vec<u8> {
+    let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
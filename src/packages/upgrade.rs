@@ -0,0 +1,251 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::debversion::DebianVersionNum;
+
+use crate::Packages;
+use crate::packages::RelationKind;
+use crate::packages::planner::{Change, Encoder, Reason};
+use crate::packages::repository::AvailableVersion;
+use crate::packages::sat::{self, Lit};
+
+// plan_upgrade is apt upgrade (and, with removals, apt full-upgrade) on the planner's encoder: every installed package
+// is part of the problem, with its installed version and those apt could move it to; the candidates of upgradable
+// packages are the first decisions, and the rest follows how-to-install, pulling in new dependencies. Without removals,
+// each installed package must keep a version. The model is then moved towards what is wanted of each package (the
+// candidate of an upgradable package, the installed version of another one, and nothing for a new package) one
+// package at a time, as long as no clause breaks; the clause that breaks otherwise is why the package is planned as it is.
+
+/// An installed package with a newer candidate.
+pub struct Upgradable<'a> {
+    pub package_num : u32,
+    pub installed : &'a DebianVersionNum,
+    pub candidate : &'a AvailableVersion
+}
+
+/// A change of plan_upgrade and the constraint that calls for it; None for an upgrade to the candidate.
+pub struct PlannedChange<'a> {
+    pub change : Change<'a>,
+    pub reason : Option<Reason<'a>>
+}
+
+/// An upgradable package that plan_upgrade keeps at its installed version, and the constraint that stops its upgrade;
+/// None if its candidate is not among the versions plan_upgrade can choose.
+pub struct HeldBack<'a> {
+    pub package_num : u32,
+    pub installed : &'a DebianVersionNum,
+    pub candidate : &'a AvailableVersion,
+    pub reason : Option<Reason<'a>>
+}
+
+/// What plan_upgrade would do, each list by package name: the upgrades, the new packages and the removals, and the
+/// upgradable packages left as they are.
+pub struct UpgradePlan<'a> {
+    pub upgraded : Vec<PlannedChange<'a>>,
+    pub new : Vec<PlannedChange<'a>>,
+    pub removed : Vec<PlannedChange<'a>>,
+    pub held_back : Vec<HeldBack<'a>>
+}
+
+impl<'a> Encoder<'a> {
+    // the problem of upgrading the system: every installed package and what its versions could involve, the wish to
+    // move each upgradable package to its candidate, and, without removals, a clause keeping each installed package
+    fn encode_upgrade(&mut self, upgradable: &[Upgradable<'a>], dist_upgrade: bool) {
+        let packages = self.packages;
+        let mut queue = VecDeque::new();
+        for (package_num, _) in packages.installed_versions() {
+            self.add_package(package_num, &mut queue);
+        }
+        while let Some(p) = queue.pop_front() {
+            self.expand(p, &mut queue);
+        }
+        self.add_relations();
+        for u in upgradable {
+            if let Some(c) = self.candidate_choice(u.package_num) {
+                self.wishes.push(vec![sat::lit(c, true)]);
+            }
+        }
+        if !dist_upgrade {
+            for (package_num, _) in packages.installed_versions() {
+                let nums = self.by_package[&package_num].clone();
+                let versions = nums.iter().map(|c| self.choices[*c].version).collect();
+                self.add_clause(nums.iter().map(|c| sat::lit(*c, true)).collect(), Reason::Install { package_num, versions });
+            }
+        }
+    }
+
+    fn candidate_choice(&self, package_num: u32) -> Option<usize> {
+        let candidate = self.packages.get_candidate_debver(package_num)?;
+        self.by_package[&package_num].iter().copied()
+            .find(|c| self.choices[*c].available.is_some() && self.choices[*c].version == candidate)
+    }
+
+    // what is wanted of package_num: its candidate if it is upgradable, its installed version if it is installed,
+    // and otherwise to leave it out
+    fn preferred_choice(&self, package_num: u32, upgradable: &HashMap<u32, &Upgradable>) -> Option<usize> {
+        if upgradable.contains_key(&package_num) {
+            if let Some(c) = self.candidate_choice(package_num) { return Some(c) }
+        }
+        self.by_package[&package_num].iter().copied().find(|c| self.choices[*c].available.is_none())
+    }
+
+    // gives package_num its preferred choice in model, unless a clause would break; returns the first such clause
+    fn prefer(&self, package_num: u32, preferred: Option<usize>, model: &mut [bool], occurrences: &[Vec<usize>]) -> Option<usize> {
+        let current = self.by_package[&package_num].iter().copied().find(|c| model[*c]);
+        let changed : Vec<usize> = current.into_iter().chain(preferred).collect();
+        for c in &changed {
+            model[*c] = !model[*c];
+        }
+        let holds = |l: &Lit| model[sat::lit_var(*l)] == sat::is_positive(*l);
+        let broken = changed.iter().flat_map(|c| &occurrences[*c]).copied()
+            .filter(|clause| !self.literals[*clause].iter().any(holds)).min();
+        if broken.is_some() {
+            for c in &changed {
+                model[*c] = !model[*c];
+            }
+        }
+        broken
+    }
+}
+
+impl Packages {
+    /// The installed packages whose candidate is newer than the installed version, by name.
+    pub fn upgradable(&self) -> Vec<Upgradable<'_>> {
+        let mut result : Vec<Upgradable> = self.installed_versions().filter_map(|(package_num, installed)| {
+            self.get_candidate(package_num).filter(|c| c.version > *installed)
+                .map(|candidate| Upgradable { package_num, installed, candidate })
+        }).collect();
+        result.sort_by_key(|u| self.interner.get(u.package_num));
+        result
+    }
+
+    /// Plans moving every upgradable package to its candidate, with the new packages their Pre-Depends and Depends
+    /// need; with dist_upgrade, installed packages may also be removed. Upgrades that cannot be done are held back.
+    /// Returns the constraints that rule out every plan, keeping the installed system included, if there is none.
+    pub fn plan_upgrade(&self, dist_upgrade: bool) -> Result<UpgradePlan<'_>, Vec<Reason<'_>>> {
+        let upgradable_list = self.upgradable();
+        let upgradable : HashMap<u32, &Upgradable> = upgradable_list.iter().map(|u| (u.package_num, u)).collect();
+        let mut encoder = Encoder::new(self, &RelationKind::REQUIRED, true);
+        encoder.encode_upgrade(&upgradable_list, dist_upgrade);
+        // keeping every installed version should meet every clause, as those the system already breaks are left out; but
+        // what the encoder makes of the installed relations may still disagree with the installed set
+        let mut model = encoder.search().map_err(|core| core.into_iter().map(|c| encoder.reasons[c].clone()).collect::<Vec<_>>())?;
+        let mut occurrences = vec![vec![]; encoder.choices.len()];
+        for (clause, literals) in encoder.literals.iter().enumerate() {
+            for l in literals {
+                occurrences[sat::lit_var(*l)].push(clause);
+            }
+        }
+        let preferred : HashMap<u32, Option<usize>> = encoder.order.iter()
+            .map(|p| (*p, encoder.preferred_choice(*p, &upgradable))).collect();
+        let is_preferred = |p: u32, model: &[bool]| encoder.by_package[&p].iter().all(|c| model[*c] == (preferred[&p] == Some(*c)));
+        // each package moves to its preferred choice at most once, so this ends; the last round finds what stops the others
+        let obstacles : HashMap<u32, usize> = loop {
+            let mut moved = false;
+            let mut obstacles = HashMap::new();
+            for p in &encoder.order {
+                if is_preferred(*p, &model) { continue }
+                match encoder.prefer(*p, preferred[p], &mut model, &occurrences) {
+                    None => moved = true,
+                    Some(clause) => { obstacles.insert(*p, clause); }
+                }
+            }
+            if !moved { break obstacles }
+        };
+        let reason = |p: &u32| obstacles.get(p).map(|c| encoder.reasons[*c].clone());
+        let mut plan = UpgradePlan { upgraded : vec![], new : vec![], removed : vec![], held_back : vec![] };
+        for p in &encoder.order {
+            let chosen = encoder.by_package[p].iter().find(|c| model[**c]).map(|c| &encoder.choices[*c]);
+            match (self.installed_debver(*p), chosen.and_then(|c| c.available)) {
+                (None, Some(version)) => plan.new.push(PlannedChange { change : Change::Install { package_num : *p, version }, reason : reason(p) }),
+                (Some(from), Some(to)) => plan.upgraded.push(PlannedChange { change : Change::Upgrade { package_num : *p, from, to }, reason : reason(p) }),
+                (Some(version), None) if chosen.is_none() => plan.removed.push(PlannedChange { change : Change::Remove { package_num : *p, version }, reason : reason(p) }),
+                (Some(installed), None) => if let Some(u) = upgradable.get(p) {
+                    plan.held_back.push(HeldBack { package_num : *p, installed, candidate : u.candidate, reason : reason(p) });
+                }
+                (None, None) => ()
+            }
+        }
+        let name = |p: u32| self.interner.get(p);
+        for changes in [&mut plan.upgraded, &mut plan.new, &mut plan.removed] {
+            changes.sort_by_key(|c| name(c.change.package_num()));
+        }
+        plan.held_back.sort_by_key(|h| name(h.package_num));
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packages::testutil::{load, num};
+
+    const AVAILABLE : &str = "Package: a\nVersion: 2\nDepends: n\n\nPackage: n\nVersion: 1\n\n\
+                              Package: b\nVersion: 2\nDepends: missing\n\nPackage: c\nVersion: 1\n\n\
+                              Package: d\nVersion: 2\nConflicts: e\n\nPackage: e\nVersion: 1\n";
+    const STATUS : &str = "Package: a\nStatus: install ok installed\nVersion: 1\n\n\
+                           Package: b\nStatus: install ok installed\nVersion: 1\n\n\
+                           Package: c\nStatus: install ok installed\nVersion: 2\n\n\
+                           Package: d\nStatus: install ok installed\nVersion: 1\n\n\
+                           Package: e\nStatus: install ok installed\nVersion: 1\n";
+
+    fn describe(packages: &Packages, changes: &[PlannedChange]) -> Vec<String> {
+        changes.iter().map(|c| match &c.change {
+            Change::Install { package_num, version } => format!("{} {}", packages.shown_name(*package_num), version.version),
+            Change::Upgrade { package_num, from, to } => format!("{} {} to {}", packages.shown_name(*package_num), from, to.version),
+            Change::Remove { package_num, version } => format!("{} {}", packages.shown_name(*package_num), version)
+        }).collect()
+    }
+
+    // c is newer than its candidate and e is at its candidate
+    #[test]
+    fn newer_candidates_are_upgradable() {
+        let packages = load(AVAILABLE, STATUS);
+        let upgradable : Vec<(&str, String, String)> = packages.upgradable().iter()
+            .map(|u| (packages.shown_name(u.package_num), u.installed.to_string(), u.candidate.version.to_string())).collect();
+        assert_eq!(upgradable, [("a", "1".to_string(), "2".to_string()), ("b", "1".to_string(), "2".to_string()),
+                                ("d", "1".to_string(), "2".to_string())]);
+    }
+
+    #[test]
+    fn upgrade_holds_back_what_needs_a_removal() {
+        let packages = load(AVAILABLE, STATUS);
+        let plan = packages.plan_upgrade(false).ok().unwrap();
+        assert_eq!(describe(&packages, &plan.upgraded), ["a 1 to 2"]);
+        assert!(plan.upgraded[0].reason.is_none());
+        assert_eq!(describe(&packages, &plan.new), ["n 1"]);
+        assert!(matches!(&plan.new[0].reason, Some(Reason::Depends { package_num, .. }) if *package_num == num(&packages, "a")));
+        assert!(plan.removed.is_empty());
+        let held_back : Vec<&str> = plan.held_back.iter().map(|h| packages.shown_name(h.package_num)).collect();
+        assert_eq!(held_back, ["b", "d"]);
+        assert!(matches!(&plan.held_back[0].reason, Some(Reason::Depends { package_num, .. }) if *package_num == num(&packages, "b")));
+        assert!(plan.held_back[1].reason.is_some());
+    }
+
+    #[test]
+    fn dist_upgrade_removes_a_conflicting_package() {
+        let packages = load(AVAILABLE, STATUS);
+        let plan = packages.plan_upgrade(true).ok().unwrap();
+        assert_eq!(describe(&packages, &plan.upgraded), ["a 1 to 2", "d 1 to 2"]);
+        assert_eq!(describe(&packages, &plan.removed), ["e 1"]);
+        assert!(matches!(&plan.removed[0].reason, Some(Reason::Conflicts { package_num, .. }) if *package_num == num(&packages, "d")));
+        let held_back : Vec<&str> = plan.held_back.iter().map(|h| packages.shown_name(h.package_num)).collect();
+        assert_eq!(held_back, ["b"]);
+    }
+
+    // what the installed system already breaks is left as it is: b still upgrades, and a is only held back by its own
+    // candidate's missing dependency and c's Breaks
+    #[test]
+    fn inconsistent_system_still_upgrades() {
+        let packages = load("Package: a\nVersion: 2\nDepends: missing\n\nPackage: b\nVersion: 2\n",
+                            "Package: a\nStatus: install ok installed\nVersion: 1\nDepends: gone, b (>= 3)\nConflicts: c\n\n\
+                             Package: b\nStatus: install ok installed\nVersion: 1\n\n\
+                             Package: c\nStatus: install ok installed\nVersion: 1\nBreaks: a\n");
+        for dist_upgrade in [false, true] {
+            let plan = packages.plan_upgrade(dist_upgrade).ok().unwrap();
+            assert_eq!(describe(&packages, &plan.upgraded), ["b 1 to 2"]);
+            assert!(plan.new.is_empty() && plan.removed.is_empty());
+            assert_eq!(plan.held_back.iter().map(|h| h.package_num).collect::<Vec<_>>(), [num(&packages, "a")]);
+            assert!(plan.held_back[0].reason.is_some());
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use regex::Regex;
+
+use crate::debversion::DebianVersionNum;
+use crate::deb822::Paragraph;
+
+use crate::Packages;
+use crate::packages::RelationKind;
+use crate::packages::errors::LoadError;
+use crate::packages::multiarch::MultiArch;
+use crate::packages::parsers::read_paragraphs;
+use crate::packages::status::PackageStatus;
+
+// Checks a Packages index or status file against Debian policy without loading it; see
+// https://www.debian.org/doc/debian-policy/ch-controlfields.html. Stanzas with a Status field are status file
+// stanzas, the others index stanzas; they have different required fields.
+
+const PACKAGE_NAME_REGEX : &str = r"^[a-z0-9][a-z0-9+.-]+$";
+// one alternative of a relation, as strictly as policy allows: "python3:any (>= 3.6~)"
+const RELATION_REGEX : &str = r"^(?P<pkg>[^\s:(]+)(:(?P<arch>[a-z0-9-]+))?\s*(\(\s*(?P<op>[<=>]+)\s*(?P<ver>[^\s)]*)\s*\))?$";
+
+const INDEX_REQUIRED : [&str; 6] = ["Package", "Version", "Architecture", "Maintainer", "Filename", "Size"];
+const STATUS_REQUIRED : [&str; 5] = ["Package", "Status", "Architecture", "Maintainer", "Description"];
+
+// the field name and length in hex digits of each checksum field
+const CHECKSUMS : [(&str, usize); 2] = [("MD5sum", 32), ("SHA256", 64)];
+
+struct Validator {
+    package_name : Regex,
+    relation : Regex
+}
+
+impl Validator {
+    // problems with one relation field, e.g. Depends; Provides may only use "="
+    fn check_relations(&self, field: &str, value: &str, problems: &mut Vec<String>) {
+        for element in value.split(',').map(str::trim) {
+            if element.is_empty() {
+                problems.push(format!("{}: empty relation", field));
+                continue;
+            }
+            for alternative in element.split('|').map(str::trim) {
+                let caps = match self.relation.captures(alternative) {
+                    None => { problems.push(format!("{}: bad relation {:?}", field, alternative)); continue }
+                    Some(caps) => caps
+                };
+                let name = &caps["pkg"];
+                if !self.package_name.is_match(name) {
+                    problems.push(format!("{}: bad package name {:?}", field, name));
+                }
+                if let Some(op) = caps.name("op").map(|op| op.as_str()) {
+                    match op {
+                        "<<" | "<=" | "=" | ">=" | ">>" => (),
+                        "<" | ">" => problems.push(format!("{}: obsolete operator {:?} in {:?}; use {}{} or {}", field, op, alternative, op, op, if op == "<" { "<=" } else { ">=" })),
+                        _ => problems.push(format!("{}: bad operator {:?} in {:?}", field, op, alternative))
+                    }
+                    if field == "Provides" && op != "=" {
+                        problems.push(format!("Provides: only \"=\" is allowed, got {:?}", alternative));
+                    }
+                    if let Err(e) = caps["ver"].parse::<DebianVersionNum>() {
+                        problems.push(format!("{}: {} in {:?}", field, e, alternative));
+                    }
+                }
+            }
+            if field == "Provides" && element.contains('|') {
+                problems.push(format!("Provides: alternatives are not allowed, got {:?}", element));
+            }
+        }
+    }
+
+    // every problem with para, each with the line of the field it is about
+    fn check_stanza(&self, para: &Paragraph) -> Vec<(usize, String)> {
+        let mut problems = vec![];
+        let line_of = |field: &str| para.field(field).map_or(para.line(), |f| f.line);
+        let is_status = para.get("Status").is_some();
+        let required : &[&str] = if is_status { &STATUS_REQUIRED } else { &INDEX_REQUIRED };
+        for field in required.iter().filter(|f| para.get(f).is_none()) {
+            problems.push((para.line(), format!("missing required field {}", field)));
+        }
+        if !is_status && para.get("Description").is_none() && para.get("Description-md5").is_none() {
+            problems.push((para.line(), String::from("missing required field Description (or Description-md5)")));
+        }
+        if let Some(name) = para.get("Package") {
+            if !self.package_name.is_match(name) {
+                problems.push((line_of("Package"), format!("bad package name {:?}: lowercase letters, digits, + - . only, at least two characters, starting with a letter or digit", name)));
+            }
+        }
+        if let Some(Err(e)) = para.get("Version").map(|v| v.parse::<DebianVersionNum>()) {
+            problems.push((line_of("Version"), e.to_string()));
+        }
+        if let Some(Err(e)) = para.get("Status").map(|s| s.parse::<PackageStatus>()) {
+            problems.push((line_of("Status"), e));
+        }
+        if is_status && para.get("Version").is_none()
+            && para.get("Status").and_then(|s| s.parse::<PackageStatus>().ok()).is_some_and(|s| s.is_installed()) {
+            problems.push((para.line(), String::from("missing required field Version for an installed package")));
+        }
+        if let Some(Err(e)) = para.get("Multi-Arch").map(|m| m.parse::<MultiArch>()) {
+            problems.push((line_of("Multi-Arch"), e));
+        }
+        if let Some(size) = para.get("Size") {
+            if size.is_empty() || !size.bytes().all(|c| c.is_ascii_digit()) {
+                problems.push((line_of("Size"), format!("bad Size {:?}", size)));
+            }
+        }
+        for (field, digits) in CHECKSUMS {
+            if let Some(sum) = para.get(field) {
+                if sum.len() != digits || !sum.bytes().all(|c| c.is_ascii_digit() || (b'a'..=b'f').contains(&c)) {
+                    problems.push((line_of(field), format!("bad {} {:?}: expected {} lowercase hex digits", field, sum, digits)));
+                }
+            }
+        }
+        if !is_status && CHECKSUMS.iter().all(|(field, _)| para.get(field).is_none()) {
+            problems.push((para.line(), String::from("no MD5sum or SHA256 checksum")));
+        }
+        let relation_fields = RelationKind::ALL.iter().map(|k| k.field_name()).chain(["Provides"]);
+        for field in relation_fields {
+            if let Some(value) = para.get_folded(field) {
+                let mut relation_problems = vec![];
+                self.check_relations(field, &value, &mut relation_problems);
+                problems.extend(relation_problems.into_iter().map(|p| (line_of(field), p)));
+            }
+        }
+        problems.sort_by_key(|(line, _)| *line);
+        problems
+    }
+}
+
+impl Packages {
+    /// Checks the Packages index or status file filename against Debian policy, without loading anything: required
+    /// fields, package names, versions, relations, sizes and checksums, and that no Package/Version/Architecture
+    /// appears twice. Returns every problem found; Err if the file cannot be read.
+    pub fn validate_index(&self, filename: &str) -> Result<Vec<LoadError>, LoadError> {
+        let validator = Validator {
+            package_name : Regex::new(PACKAGE_NAME_REGEX).unwrap(),
+            relation : Regex::new(RELATION_REGEX).unwrap()
+        };
+        let paragraphs = read_paragraphs(filename).map_err(|e| LoadError::io(filename, e))?;
+        let mut problems = vec![];
+        // first line of each Package/Version/Architecture seen
+        let mut seen : HashMap<(String, String, String), usize> = HashMap::new();
+        for para in paragraphs {
+            let para = match para {
+                Ok(p) => p,
+                Err(e) => {
+                    let error = LoadError::from_read(filename, e);
+                    if error.is_fatal() { return Err(error) }
+                    problems.push(error);
+                    continue;
+                }
+            };
+            let package = para.get("Package");
+            for (line, problem) in validator.check_stanza(&para) {
+                problems.push(LoadError::malformed(filename, line, package, problem));
+            }
+            if let (Some(p), Some(v), Some(a)) = (package, para.get("Version"), para.get("Architecture")) {
+                match seen.entry((p.to_string(), v.to_string(), a.to_string())) {
+                    Entry::Vacant(entry) => { entry.insert(para.line()); }
+                    Entry::Occupied(first) => {
+                        let message = format!("duplicate of the stanza at line {} ({} {} {})", first.get(), p, v, a);
+                        problems.push(LoadError::malformed(filename, para.line(), package, message));
+                    }
+                }
+            }
+        }
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Packages;
+    use crate::packages::LoadErrorKind;
+    use crate::packages::testutil::TempFile;
+
+    const VALID : &str = "Package: hello\nVersion: 2.10-3\nArchitecture: amd64\nMaintainer: Santiago Vila <sanvila@debian.org>\n\
+                          Depends: libc6 (>= 2.34), python3:any | perl\nProvides: greeter (= 1.0)\nFilename: pool/main/h/hello/hello_2.10-3_amd64.deb\n\
+                          Size: 53000\nMD5sum: 0123456789abcdef0123456789abcdef\nDescription: example package\n";
+
+    // each problem as (line, message)
+    fn problems(contents: &str) -> Vec<(Option<usize>, String)> {
+        let errors = Packages::new().validate_index(TempFile::new(contents).path()).unwrap();
+        errors.into_iter().map(|e| match e.kind {
+            LoadErrorKind::Malformed(message) => (e.line, message),
+            kind => panic!("not a malformed stanza: {:?}", kind)
+        }).collect()
+    }
+
+    // the problems of VALID with field replaced by value (or removed, if value is empty)
+    fn with_field(field: &str, value: &str) -> Vec<(Option<usize>, String)> {
+        let stanza = VALID.lines().map(|l| match l.split_once(':') {
+            Some((f, _)) if f == field => if value.is_empty() { String::new() } else { format!("{}: {}\n", f, value) },
+            _ => format!("{}\n", l)
+        }).collect::<String>();
+        problems(&stanza)
+    }
+
+    #[test]
+    fn valid_stanza() {
+        assert_eq!(problems(VALID), []);
+        let status = "Package: hello\nStatus: install ok installed\nArchitecture: amd64\nVersion: 2.10-3\nMaintainer: M <m@example.org>\n\
+                      Description: example package\n";
+        assert_eq!(problems(status), []);
+    }
+
+    #[test]
+    fn missing_required_field() {
+        assert_eq!(with_field("Maintainer", ""), [(Some(1), String::from("missing required field Maintainer"))]);
+        assert_eq!(with_field("Description", ""), [(Some(1), String::from("missing required field Description (or Description-md5)"))]);
+        assert_eq!(with_field("MD5sum", ""), [(Some(1), String::from("no MD5sum or SHA256 checksum"))]);
+        // status stanzas need a Status and Description, but no Filename; installed ones also need a Version
+        let status = "Package: hello\nStatus: install ok installed\nArchitecture: amd64\nMaintainer: M <m@example.org>\n";
+        assert_eq!(problems(status), [(Some(1), String::from("missing required field Description")),
+                                      (Some(1), String::from("missing required field Version for an installed package"))]);
+    }
+
+    #[test]
+    fn bad_package_name() {
+        for name in ["Hello", "h", "-hello", "hello_world"] {
+            let found = with_field("Package", name);
+            assert_eq!(found.len(), 1, "{}", name);
+            assert!(found[0].1.starts_with(&format!("bad package name {:?}", name)), "{:?}", found);
+        }
+        assert_eq!(with_field("Package", "g++-12"), []);
+    }
+
+    #[test]
+    fn bad_relation_syntax() {
+        assert_eq!(with_field("Depends", "libc6 (>= 2.34),, perl"), [(Some(5), String::from("Depends: empty relation"))]);
+        assert_eq!(with_field("Depends", "libc6 (> 2.34)"),
+                   [(Some(5), String::from("Depends: obsolete operator \">\" in \"libc6 (> 2.34)\"; use >> or >="))]);
+        assert_eq!(with_field("Depends", "libc6 (>= 2.34"), [(Some(5), String::from("Depends: bad relation \"libc6 (>= 2.34\""))]);
+        assert_eq!(with_field("Depends", "Libc6"), [(Some(5), String::from("Depends: bad package name \"Libc6\""))]);
+        assert_eq!(with_field("Provides", "greeter (>= 1.0) | hi").iter().map(|(_, p)| p.as_str()).collect::<Vec<_>>(),
+                   ["Provides: only \"=\" is allowed, got \"greeter (>= 1.0)\"", "Provides: alternatives are not allowed, got \"greeter (>= 1.0) | hi\""]);
+        assert_eq!(with_field("Depends", "libc6 (>= 2..34!)").len(), 1);
+    }
+
+    #[test]
+    fn checksum_format() {
+        assert_eq!(with_field("MD5sum", "0123456789ABCDEF0123456789ABCDEF"),
+                   [(Some(9), String::from("bad MD5sum \"0123456789ABCDEF0123456789ABCDEF\": expected 32 lowercase hex digits"))]);
+        assert_eq!(with_field("MD5sum", "0123").len(), 1);
+        let sha256 = VALID.replace("MD5sum: 0123456789abcdef0123456789abcdef", &format!("SHA256: {}", "ab".repeat(32)));
+        assert_eq!(problems(&sha256), []);
+        assert_eq!(problems(&sha256.replace("ab", "zz"))[0].1, format!("bad SHA256 {:?}: expected 64 lowercase hex digits", "zz".repeat(32)));
+        assert_eq!(with_field("Size", "53k"), [(Some(8), String::from("bad Size \"53k\""))]);
+    }
+
+    #[test]
+    fn duplicate_stanza() {
+        let index = [VALID, VALID, &VALID.replace("amd64\n", "arm64\n")].join("\n");
+        assert_eq!(problems(&index), [(Some(12), String::from("duplicate of the stanza at line 1 (hello 2.10-3 amd64)"))]);
+    }
+}